@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 
-use crate::Error;
+use crate::{Error, api::guild::GuildMember};
 
 const VIEW_CHANNEL_PERMISSION: u64 = 1 << 10;
 
@@ -11,6 +11,15 @@ pub struct Role {
     pub id: String,
     pub name: String,
     pub permissions: String,
+    /// RGB color, 0 when the role has none
+    #[serde(default)]
+    pub color: u32,
+    /// Higher roles come first in the role list
+    #[serde(default)]
+    pub position: i32,
+    /// Whether members with this role are listed under it in the member list
+    #[serde(default)]
+    pub hoist: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -40,8 +49,47 @@ pub struct Channel {
     #[serde(default)]
     pub permission_overwrites: Vec<Overwrite>,
     pub children: Option<Vec<Channel>>,
+    pub last_message_id: Option<String>,
+    /// Only set on threads
+    pub thread_metadata: Option<ThreadMetadata>,
+    /// Messages sent in a thread, not counting its starter message
+    pub message_count: Option<u32>,
+    /// Ids of the forum tags applied to a forum post
+    #[serde(default)]
+    pub applied_tags: Vec<String>,
+    /// Tags posts of a forum channel can use
+    #[serde(default)]
+    pub available_tags: Vec<ForumTag>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ThreadMetadata {
+    pub archived: bool,
+    #[serde(default)]
+    pub locked: bool,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForumTag {
+    pub id: String,
+    pub name: String,
+    pub emoji_name: Option<String>,
+}
+
+/// Answer of the thread listing endpoints.
+#[derive(Debug, Deserialize)]
+pub struct ThreadList {
+    pub threads: Vec<Channel>,
+}
+
+pub const GUILD_TEXT: u8 = 0;
+pub const GUILD_CATEGORY: u8 = 4;
+pub const GUILD_ANNOUNCEMENT: u8 = 5;
+pub const ANNOUNCEMENT_THREAD: u8 = 10;
+pub const PUBLIC_THREAD: u8 = 11;
+pub const PRIVATE_THREAD: u8 = 12;
+pub const GUILD_FORUM: u8 = 15;
+
 fn parse_permission_string(hex_string: &str) -> u64 {
     hex_string
         .parse::<u64>()
@@ -50,6 +98,17 @@ fn parse_permission_string(hex_string: &str) -> u64 {
 
 impl Channel {
     fn calculate_permissions(&self, context: &PermissionContext) -> u64 {
+        self.member_permissions(context, &context.user_id, &context.user_role_ids)
+    }
+
+    /// Permissions in this channel of the member with the given roles, the guild's roles and
+    /// `@everyone` coming from `context`.
+    fn member_permissions(
+        &self,
+        context: &PermissionContext,
+        user_id: &str,
+        role_ids: &[String],
+    ) -> u64 {
         let everyone_role = context
             .all_guild_roles
             .iter()
@@ -59,11 +118,13 @@ impl Channel {
                 id: context.everyone_role_id.clone(),
                 name: "@everyone".to_string(),
                 permissions: "0".to_string(),
+                color: 0,
+                position: 0,
+                hoist: false,
             });
 
         let mut permissions = parse_permission_string(&everyone_role.permissions);
-        for user_role_id in context
-            .user_role_ids
+        for user_role_id in role_ids
             .iter()
             .filter(|&id| id != &context.everyone_role_id)
         {
@@ -91,8 +152,7 @@ impl Channel {
         let mut role_denies = 0u64;
         let mut role_allows = 0u64;
 
-        for user_role_id in context
-            .user_role_ids
+        for user_role_id in role_ids
             .iter()
             .filter(|&id| id != &context.everyone_role_id)
         {
@@ -109,12 +169,6 @@ impl Channel {
         permissions &= !role_denies;
         permissions |= role_allows;
 
-        let user_id = context
-            .user_role_ids
-            .first()
-            .unwrap_or(&String::new())
-            .clone();
-
         if let Some(member_overwrite) = self
             .permission_overwrites
             .iter()
@@ -130,11 +184,28 @@ impl Channel {
         permissions
     }
 
+    pub fn is_thread(&self) -> bool {
+        matches!(
+            self.channel_type,
+            ANNOUNCEMENT_THREAD | PUBLIC_THREAD | PRIVATE_THREAD
+        )
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.thread_metadata.as_ref().is_some_and(|m| m.archived)
+    }
+
     pub fn is_readable(&self, context: &PermissionContext) -> bool {
         let permissions = self.calculate_permissions(context);
         (permissions & VIEW_CHANNEL_PERMISSION) != 0
     }
 
+    /// Whether another member of the guild can see this channel.
+    pub fn is_readable_by(&self, context: &PermissionContext, member: &GuildMember) -> bool {
+        let permissions = self.member_permissions(context, &member.user.id, &member.roles);
+        (permissions & VIEW_CHANNEL_PERMISSION) != 0
+    }
+
     pub fn filter_channels_by_categories(channels: Vec<Self>) -> Result<Vec<Self>, Error> {
         if channels.is_empty() {
             return Err("Error: channels must not be empty.".into());
@@ -170,4 +241,19 @@ impl Channel {
 
         Ok(final_list)
     }
+
+    /// Undoes `filter_channels_by_categories`, moving category children back to the top level.
+    pub fn flatten_categories(channels: Vec<Self>) -> Vec<Self> {
+        let mut flat = Vec::new();
+
+        for mut channel in channels {
+            let children = channel.children.take();
+            flat.push(channel);
+            if let Some(children) = children {
+                flat.extend(children);
+            }
+        }
+
+        flat
+    }
 }
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// JSON error code Discord returns when the user cannot see a channel or guild.
+pub const MISSING_ACCESS: u64 = 50001;
+/// JSON error code Discord returns when the user lacks a permission for an action.
+pub const MISSING_PERMISSIONS: u64 = 50013;
+
+/// Error body returned by Discord alongside non-2xx statuses.
+#[derive(Debug, Deserialize, Default)]
+struct DiscordErrorBody {
+    #[serde(default)]
+    code: Option<u64>,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Option<serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    Unauthorized,
+    Forbidden {
+        code: Option<u64>,
+        message: String,
+    },
+    NotFound {
+        code: Option<u64>,
+        message: String,
+    },
+    BadRequest {
+        code: Option<u64>,
+        message: String,
+    },
+    RateLimited {
+        retry_after: f64,
+    },
+    Network(reqwest::Error),
+    /// Reading an upload or writing a download on disk failed.
+    Io(std::io::Error),
+    Decode {
+        error: serde_json::Error,
+        body: String,
+    },
+    Server {
+        status: u16,
+        message: String,
+    },
+}
+
+impl ApiError {
+    /// Builds an error from a failed response's status and raw body.
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let parsed: DiscordErrorBody = serde_json::from_str(body).unwrap_or_default();
+        let mut message = if parsed.message.is_empty() {
+            body.to_string()
+        } else {
+            parsed.message
+        };
+        if let Some(errors) = parsed.errors {
+            message = format!("{message} {errors}");
+        }
+        let code = parsed.code;
+
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized,
+            StatusCode::FORBIDDEN => Self::Forbidden { code, message },
+            StatusCode::NOT_FOUND => Self::NotFound { code, message },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited {
+                retry_after: serde_json::from_str::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v["retry_after"].as_f64())
+                    .unwrap_or(0.0),
+            },
+            s if s.is_server_error() => Self::Server {
+                status: s.as_u16(),
+                message,
+            },
+            _ => Self::BadRequest { code, message },
+        }
+    }
+
+    /// The Discord JSON error code, if the response carried one.
+    pub fn code(&self) -> Option<u64> {
+        match self {
+            Self::Forbidden { code, .. }
+            | Self::NotFound { code, .. }
+            | Self::BadRequest { code, .. } => *code,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized => write!(f, "Unauthorized: the token was rejected"),
+            Self::Forbidden { code, message } => match code {
+                Some(MISSING_ACCESS) => write!(f, "Missing access"),
+                Some(MISSING_PERMISSIONS) => write!(f, "Missing permissions"),
+                _ => write!(f, "Forbidden: {message}"),
+            },
+            Self::NotFound { message, .. } => write!(f, "Not found: {message}"),
+            Self::BadRequest { message, .. } => write!(f, "Bad request: {message}"),
+            Self::RateLimited { retry_after } => {
+                write!(f, "Rate limited, retry in {retry_after:.1}s")
+            }
+            Self::Network(e) => write!(f, "Network error: {e}"),
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Decode { error, body } => {
+                write!(f, "Failed to decode response: {error}. Body: {body}")
+            }
+            Self::Server { status, message } => write!(f, "Discord error {status}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Decode { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Network(error)
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
@@ -0,0 +1,638 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc::Sender, watch},
+    time::{self, Instant},
+};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{Message as WsMessage, protocol::frame::coding::CloseCode},
+};
+
+use crate::{
+    AppAction, Error,
+    api::{
+        Channel, DM, Message,
+        message::{MessageUpdate, ReactionEmoji},
+    },
+};
+
+const GATEWAY_VERSION: u8 = 10;
+
+const INTENT_GUILDS: u64 = 1 << 0;
+const INTENT_GUILD_PRESENCES: u64 = 1 << 8;
+const INTENT_GUILD_MESSAGES: u64 = 1 << 9;
+const INTENT_GUILD_MESSAGE_REACTIONS: u64 = 1 << 10;
+const INTENT_DIRECT_MESSAGES: u64 = 1 << 12;
+const INTENT_DIRECT_MESSAGE_REACTIONS: u64 = 1 << 13;
+const INTENT_MESSAGE_CONTENT: u64 = 1 << 15;
+
+const INTENTS: u64 = INTENT_GUILDS
+    | INTENT_GUILD_MESSAGES
+    | INTENT_GUILD_MESSAGE_REACTIONS
+    | INTENT_DIRECT_MESSAGES
+    | INTENT_DIRECT_MESSAGE_REACTIONS
+    | INTENT_MESSAGE_CONTENT;
+
+const OP_DISPATCH: u8 = 0;
+const OP_HEARTBEAT: u8 = 1;
+const OP_IDENTIFY: u8 = 2;
+const OP_RESUME: u8 = 6;
+const OP_RECONNECT: u8 = 7;
+const OP_INVALID_SESSION: u8 = 9;
+const OP_HELLO: u8 = 10;
+const OP_HEARTBEAT_ACK: u8 = 11;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long a session must have been up for its end to reset the backoff.
+const STABLE_SESSION: Duration = Duration::from_secs(30);
+
+/// Events kept while the UI doesn't take them, beyond which they are dropped.
+const MAX_PENDING_EVENTS: usize = 1024;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Deserialize)]
+struct Payload {
+    op: u8,
+    #[serde(default)]
+    d: Value,
+    s: Option<u64>,
+    t: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ready {
+    session_id: String,
+    resume_gateway_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelete {
+    id: String,
+    channel_id: String,
+}
+
+/// Payload of `MESSAGE_REACTION_ADD` and `MESSAGE_REACTION_REMOVE`.
+#[derive(Debug, Deserialize)]
+pub struct ReactionUpdate {
+    pub user_id: String,
+    pub channel_id: String,
+    pub message_id: String,
+    pub emoji: ReactionEmoji,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceUser {
+    pub id: String,
+}
+
+/// Payload of `PRESENCE_UPDATE`, also listed in `GUILD_CREATE`.
+#[derive(Debug, Deserialize)]
+pub struct PresenceUpdate {
+    pub user: PresenceUser,
+    /// `online`, `idle`, `dnd` or `offline`
+    pub status: String,
+}
+
+/// Why a single gateway session ended, and what the reconnect loop should do next.
+#[derive(Debug)]
+enum SessionEnd {
+    Shutdown,
+    Resume,
+    Reidentify,
+    /// The token was rejected, a new one is needed to connect again
+    Unauthorized,
+    Fatal(String),
+}
+
+/// Discord gateway client.
+///
+/// Keeps the session id, resume URL and last sequence number across connections so a
+/// dropped socket can be resumed without missing events.
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    /// The token, replaced when the user enters a new one after it was rejected
+    tokens: watch::Receiver<String>,
+    url: String,
+    session_id: Option<String>,
+    resume_url: Option<String>,
+    sequence: Option<u64>,
+    /// Whether to ask for presences, which needs the privileged Presence intent
+    presences: bool,
+    /// When the current session got READY or RESUMED
+    ready_since: Option<Instant>,
+}
+
+impl Gateway {
+    pub fn new(tokens: watch::Receiver<String>, url: String, presences: bool) -> Self {
+        Self {
+            tokens,
+            url,
+            session_id: None,
+            resume_url: None,
+            sequence: None,
+            presences,
+            ready_since: None,
+        }
+    }
+
+    /// Connects to the gateway and forwards dispatched events as `AppAction`s until shutdown,
+    /// reconnecting with exponential backoff whenever the connection drops.
+    pub async fn run(mut self, tx: Sender<AppAction>, mut rx_shutdown: broadcast::Receiver<()>) {
+        let mut backoff = Duration::from_secs(1);
+        // Events not yet taken by the UI, kept across reconnects
+        let mut pending = VecDeque::new();
+
+        loop {
+            let result = self.session(&tx, &mut rx_shutdown, &mut pending).await;
+
+            // Only a session that got going resets the backoff, so that a server closing
+            // connections right away is not hammered
+            if self
+                .ready_since
+                .take()
+                .is_some_and(|since| since.elapsed() >= STABLE_SESSION)
+            {
+                backoff = Duration::from_secs(1);
+            }
+
+            let end = match result {
+                Ok(end) => end,
+                Err(e) => {
+                    tx.send(AppAction::GatewayDisconnected(format!(
+                        "Gateway connection lost, retrying in {}s. {e}",
+                        backoff.as_secs()
+                    )))
+                    .await
+                    .ok();
+                    SessionEnd::Resume
+                }
+            };
+
+            match end {
+                SessionEnd::Shutdown => return,
+                SessionEnd::Resume => {}
+                SessionEnd::Reidentify => {
+                    self.session_id = None;
+                    self.resume_url = None;
+                    self.sequence = None;
+                }
+                SessionEnd::Unauthorized => {
+                    tx.send(AppAction::GatewayUnauthorized).await.ok();
+                    // Reconnecting with the same token would be rejected again
+                    tokio::select! {
+                        _ = rx_shutdown.recv() => return,
+                        changed = self.tokens.changed() => {
+                            if changed.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    self.session_id = None;
+                    self.resume_url = None;
+                    self.sequence = None;
+                    backoff = Duration::from_secs(1);
+                    continue;
+                }
+                SessionEnd::Fatal(reason) => {
+                    tx.send(AppAction::GatewayDisconnected(reason)).await.ok();
+                    return;
+                }
+            }
+
+            tokio::select! {
+                _ = rx_shutdown.recv() => return,
+                _ = time::sleep(backoff) => {}
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    fn can_resume(&self) -> bool {
+        self.session_id.is_some() && self.sequence.is_some()
+    }
+
+    fn connect_url(&self) -> String {
+        let base = match (&self.resume_url, self.can_resume()) {
+            (Some(url), true) => url.as_str(),
+            _ => self.url.as_str(),
+        };
+        format!(
+            "{}/?v={GATEWAY_VERSION}&encoding=json",
+            base.trim_end_matches('/')
+        )
+    }
+
+    async fn session(
+        &mut self,
+        tx: &Sender<AppAction>,
+        rx_shutdown: &mut broadcast::Receiver<()>,
+        pending: &mut VecDeque<AppAction>,
+    ) -> Result<SessionEnd, Error> {
+        let (mut socket, _) = connect_async(self.connect_url()).await?;
+
+        let heartbeat_interval = loop {
+            match socket.next().await {
+                Some(Ok(WsMessage::Text(text))) => {
+                    let payload: Payload = serde_json::from_str(&text)?;
+                    if payload.op == OP_HELLO {
+                        let interval = payload.d["heartbeat_interval"].as_u64().unwrap_or(41_250);
+                        break Duration::from_millis(interval);
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err("Gateway closed before HELLO".into()),
+            }
+        };
+
+        if self.can_resume() {
+            send_payload(
+                &mut socket,
+                OP_RESUME,
+                json!({
+                    "token": self.identify_token(),
+                    "session_id": self.session_id,
+                    "seq": self.sequence,
+                }),
+            )
+            .await?;
+        } else {
+            send_payload(&mut socket, OP_IDENTIFY, self.identify_data()).await?;
+        }
+
+        // The first heartbeat is jittered so that clients reconnecting together spread out
+        let jitter = f64::from(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos(),
+        ) / 1_000_000_000.0;
+        let mut heartbeat = time::interval_at(
+            Instant::now() + heartbeat_interval.mul_f64(jitter),
+            heartbeat_interval,
+        );
+        let mut awaiting_ack = false;
+
+        loop {
+            tokio::select! {
+                _ = rx_shutdown.recv() => {
+                    socket.close(None).await.ok();
+                    return Ok(SessionEnd::Shutdown);
+                }
+
+                _ = heartbeat.tick() => {
+                    if awaiting_ack {
+                        // No ACK since the last heartbeat: the connection is a zombie
+                        socket.close(None).await.ok();
+                        return Ok(SessionEnd::Resume);
+                    }
+                    send_payload(&mut socket, OP_HEARTBEAT, json!(self.sequence)).await?;
+                    awaiting_ack = true;
+                }
+
+                // Events are queued rather than sent in place, so that heartbeats go on while
+                // the UI is not reading actions
+                permit = tx.reserve(), if !pending.is_empty() => {
+                    let Ok(permit) = permit else {
+                        return Ok(SessionEnd::Shutdown);
+                    };
+                    if let Some(action) = pending.pop_front() {
+                        permit.send(action);
+                    }
+                }
+
+                message = socket.next() => {
+                    let text = match message {
+                        Some(Ok(WsMessage::Text(text))) => text,
+                        Some(Ok(WsMessage::Close(frame))) => {
+                            return Ok(close_code_action(frame.map(|f| f.code)));
+                        }
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Ok(SessionEnd::Resume),
+                    };
+
+                    let payload: Payload = match serde_json::from_str(&text) {
+                        Ok(p) => p,
+                        Err(_) => continue,
+                    };
+
+                    if let Some(s) = payload.s {
+                        self.sequence = Some(s);
+                    }
+
+                    match payload.op {
+                        OP_DISPATCH => {
+                            let event = payload.t.unwrap_or_default();
+                            if event == "READY" {
+                                if let Ok(ready) = serde_json::from_value::<Ready>(payload.d) {
+                                    self.session_id = Some(ready.session_id);
+                                    self.resume_url = Some(ready.resume_gateway_url);
+                                    self.ready_since = Some(Instant::now());
+                                }
+                            } else if event == "RESUMED" {
+                                self.ready_since = Some(Instant::now());
+                            } else if let Some(action) = dispatch_action(&event, payload.d) {
+                                queue_event(pending, action);
+                            }
+                        }
+                        OP_HEARTBEAT => {
+                            send_payload(&mut socket, OP_HEARTBEAT, json!(self.sequence)).await?;
+                        }
+                        OP_RECONNECT => {
+                            socket.close(None).await.ok();
+                            return Ok(SessionEnd::Resume);
+                        }
+                        OP_INVALID_SESSION => {
+                            let resumable = payload.d.as_bool().unwrap_or(false);
+                            socket.close(None).await.ok();
+                            // Discord asks for a random 1-5 second wait before identifying again
+                            tokio::select! {
+                                _ = rx_shutdown.recv() => return Ok(SessionEnd::Shutdown),
+                                _ = time::sleep(Duration::from_secs_f64(1.0 + jitter * 4.0)) => {}
+                            }
+                            return Ok(if resumable {
+                                SessionEnd::Resume
+                            } else {
+                                SessionEnd::Reidentify
+                            });
+                        }
+                        OP_HEARTBEAT_ACK => {
+                            awaiting_ack = false;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// The gateway expects the bare token, without the `Bot ` prefix used for REST.
+    fn identify_token(&mut self) -> String {
+        let token = self.tokens.borrow_and_update();
+        token.trim_start_matches("Bot ").to_string()
+    }
+
+    fn identify_data(&mut self) -> Value {
+        json!({
+            "token": self.identify_token(),
+            "intents": if self.presences {
+                INTENTS | INTENT_GUILD_PRESENCES
+            } else {
+                INTENTS
+            },
+            "properties": {
+                "os": std::env::consts::OS,
+                "browser": "vimcord",
+                "device": "vimcord",
+            },
+        })
+    }
+}
+
+/// Queues an event for the UI. When the UI fell too far behind, the backlog is dropped
+/// for a notice, as the events left would not describe the current state anyway.
+fn queue_event(pending: &mut VecDeque<AppAction>, action: AppAction) {
+    if pending.len() >= MAX_PENDING_EVENTS {
+        pending.clear();
+        pending.push_back(AppAction::GatewayDisconnected(
+            "Missed updates while busy, reopen the channel to refresh it.".to_string(),
+        ));
+    }
+    pending.push_back(action);
+}
+
+async fn send_payload(socket: &mut Socket, op: u8, d: Value) -> Result<(), Error> {
+    let payload = json!({ "op": op, "d": d });
+    socket
+        .send(WsMessage::Text(payload.to_string().into()))
+        .await?;
+    Ok(())
+}
+
+fn close_code_action(code: Option<CloseCode>) -> SessionEnd {
+    match code.map(u16::from) {
+        Some(4004) => SessionEnd::Unauthorized,
+        Some(4013) | Some(4014) => SessionEnd::Fatal(
+            "Gateway rejected the requested intents. Enable the Message Content intent, and the Presence intent if presences are on.".into(),
+        ),
+        Some(code @ (4010..=4012)) => {
+            SessionEnd::Fatal(format!("Gateway closed the connection (code {code})."))
+        }
+        Some(4007) | Some(4009) => SessionEnd::Reidentify,
+        _ => SessionEnd::Resume,
+    }
+}
+
+/// Maps a gateway dispatch event to the `AppAction` the UI loop understands.
+fn dispatch_action(event: &str, data: Value) -> Option<AppAction> {
+    match event {
+        "MESSAGE_CREATE" => serde_json::from_value::<Message>(data)
+            .ok()
+            .map(AppAction::GatewayMessageCreate),
+        "MESSAGE_UPDATE" => serde_json::from_value::<MessageUpdate>(data)
+            .ok()
+            .map(AppAction::GatewayMessageUpdate),
+        "MESSAGE_DELETE" => serde_json::from_value::<MessageDelete>(data)
+            .ok()
+            .map(|m| AppAction::GatewayMessageDelete(m.channel_id, m.id)),
+        "MESSAGE_REACTION_ADD" => serde_json::from_value::<ReactionUpdate>(data)
+            .ok()
+            .map(AppAction::GatewayReactionAdd),
+        "MESSAGE_REACTION_REMOVE" => serde_json::from_value::<ReactionUpdate>(data)
+            .ok()
+            .map(AppAction::GatewayReactionRemove),
+        "CHANNEL_CREATE" | "CHANNEL_UPDATE" => {
+            let channel_type = data["type"].as_u64().unwrap_or_default();
+            if channel_type == 1 || channel_type == 3 {
+                serde_json::from_value::<DM>(data)
+                    .ok()
+                    .map(AppAction::GatewayDMUpdate)
+            } else {
+                serde_json::from_value::<Channel>(data)
+                    .ok()
+                    .map(AppAction::GatewayChannelUpdate)
+            }
+        }
+        "THREAD_CREATE" | "THREAD_UPDATE" => serde_json::from_value::<Channel>(data)
+            .ok()
+            .map(AppAction::GatewayThreadUpdate),
+        "PRESENCE_UPDATE" => serde_json::from_value::<PresenceUpdate>(data)
+            .ok()
+            .map(|p| AppAction::GatewayPresenceUpdate(vec![p])),
+        "GUILD_CREATE" => serde_json::from_value::<Vec<PresenceUpdate>>(data["presences"].clone())
+            .ok()
+            .filter(|presences| !presences.is_empty())
+            .map(AppAction::GatewayPresenceUpdate),
+        "CHANNEL_DELETE" | "THREAD_DELETE" => data["id"]
+            .as_str()
+            .map(|id| AppAction::GatewayChannelDelete(id.to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tokio_tungstenite::{accept_async, tungstenite::protocol::CloseFrame};
+
+    use super::*;
+
+    type ServerSocket = WebSocketStream<TcpStream>;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn accept(listener: &TcpListener) -> ServerSocket {
+        let (stream, _) = time::timeout(TIMEOUT, listener.accept())
+            .await
+            .expect("the client to connect")
+            .unwrap();
+        accept_async(stream).await.unwrap()
+    }
+
+    async fn send(socket: &mut ServerSocket, payload: Value) {
+        socket
+            .send(WsMessage::Text(payload.to_string().into()))
+            .await
+            .unwrap();
+    }
+
+    async fn hello(socket: &mut ServerSocket) {
+        send(
+            socket,
+            json!({ "op": OP_HELLO, "d": { "heartbeat_interval": 50 } }),
+        )
+        .await;
+    }
+
+    /// Data of the next payload with this op, acknowledging the heartbeats sent meanwhile.
+    async fn expect_op(socket: &mut ServerSocket, op: u8) -> Value {
+        loop {
+            let message = time::timeout(TIMEOUT, socket.next())
+                .await
+                .expect("the client to send a payload")
+                .expect("the connection to stay open")
+                .unwrap();
+            let WsMessage::Text(text) = message else {
+                continue;
+            };
+            let payload: Value = serde_json::from_str(&text).unwrap();
+            if payload["op"] == op {
+                return payload["d"].clone();
+            }
+            if payload["op"] == OP_HEARTBEAT {
+                send(socket, json!({ "op": OP_HEARTBEAT_ACK })).await;
+            }
+        }
+    }
+
+    async fn close(socket: &mut ServerSocket, code: u16) {
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: "".into(),
+        };
+        socket.close(Some(frame)).await.ok();
+    }
+
+    #[tokio::test]
+    async fn identifies_heartbeats_resumes_and_waits_for_a_new_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, mut rx) = mpsc::channel(8);
+        let (_tx_shutdown, rx_shutdown) = broadcast::channel(1);
+        let (tx_token, rx_token) = watch::channel("Bot token".to_string());
+        let gateway = Gateway::new(rx_token, url.clone(), false);
+        let gateway = tokio::spawn(gateway.run(tx, rx_shutdown));
+
+        // HELLO is answered by IDENTIFY, then by heartbeats
+        let mut socket = accept(&listener).await;
+        hello(&mut socket).await;
+        let identify = expect_op(&mut socket, OP_IDENTIFY).await;
+        assert_eq!(identify["token"], "token");
+        assert_eq!(identify["intents"], INTENTS);
+        assert_eq!(expect_op(&mut socket, OP_HEARTBEAT).await, Value::Null);
+        send(&mut socket, json!({ "op": OP_HEARTBEAT_ACK })).await;
+
+        let ready = json!({ "session_id": "session", "resume_gateway_url": url });
+        send(
+            &mut socket,
+            json!({ "op": OP_DISPATCH, "t": "READY", "s": 1, "d": ready }),
+        )
+        .await;
+        // Heartbeats carry the last sequence once the dispatch is read
+        let sequenced = async {
+            while expect_op(&mut socket, OP_HEARTBEAT).await != 1 {
+                send(&mut socket, json!({ "op": OP_HEARTBEAT_ACK })).await;
+            }
+        };
+        time::timeout(TIMEOUT, sequenced)
+            .await
+            .expect("a heartbeat with the sequence");
+
+        // A close that is not fatal is resumed where it stopped
+        close(&mut socket, 4000).await;
+        let mut socket = accept(&listener).await;
+        hello(&mut socket).await;
+        let resume = expect_op(&mut socket, OP_RESUME).await;
+        assert_eq!(resume["session_id"], "session");
+        assert_eq!(resume["seq"], 1);
+
+        // A rejected token is not retried until a new one is entered, which identifies again
+        close(&mut socket, 4004).await;
+        assert!(matches!(
+            rx.recv().await,
+            Some(AppAction::GatewayUnauthorized)
+        ));
+        assert!(
+            time::timeout(Duration::from_millis(200), listener.accept())
+                .await
+                .is_err()
+        );
+        tx_token.send_replace("Bot fresh".to_string());
+        let mut socket = accept(&listener).await;
+        hello(&mut socket).await;
+        let identify = expect_op(&mut socket, OP_IDENTIFY).await;
+        assert_eq!(identify["token"], "fresh");
+
+        // A fatal close ends the gateway for good
+        close(&mut socket, 4014).await;
+        time::timeout(TIMEOUT, gateway)
+            .await
+            .expect("the gateway to stop")
+            .unwrap();
+        assert!(matches!(
+            rx.recv().await,
+            Some(AppAction::GatewayDisconnected(_))
+        ));
+        assert!(
+            time::timeout(Duration::from_millis(200), listener.accept())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn a_full_queue_is_dropped_for_a_notice() {
+        let mut pending = VecDeque::new();
+        for _ in 0..MAX_PENDING_EVENTS {
+            queue_event(&mut pending, AppAction::Tick);
+        }
+        assert_eq!(pending.len(), MAX_PENDING_EVENTS);
+
+        queue_event(&mut pending, AppAction::SigInt);
+
+        assert_eq!(pending.len(), 2);
+        assert!(matches!(pending[0], AppAction::GatewayDisconnected(_)));
+        assert!(matches!(pending[1], AppAction::SigInt));
+    }
+}
@@ -6,6 +6,11 @@ use crate::api::User;
 pub struct GuildMember {
     pub user: User,
     pub roles: Vec<String>,
+    #[serde(default)]
+    pub nick: Option<String>,
+    /// ISO 8601 timestamp of when the user joined the guild
+    #[serde(default)]
+    pub joined_at: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -5,30 +5,41 @@ use crate::api::User;
 #[derive(Debug, Deserialize, Clone)]
 pub struct Message {
     pub id: String,
-    //pub channel_id: Snowflake,
+    pub channel_id: String,
+    /// Only sent with gateway events, REST responses leave it out.
+    pub guild_id: Option<String>,
     pub author: User,
     pub content: Option<String>,
     pub timestamp: String,
-    /*pub edited_timestamp: Option<Timestamp>,
-    pub tts: bool,
-    pub mention_everyone: bool,
-    pub mentions: Vec<User>,
-    pub mention_roles: Vec<Role>,
-    pub mention_channels: Vec<ChannelMention>,
+    pub edited_timestamp: Option<String>,
+    #[serde(rename = "type", default)]
+    pub message_type: u8,
+    pub message_reference: Option<MessageReference>,
+    pub referenced_message: Option<Box<Message>>,
+    #[serde(default)]
     pub attachments: Vec<Attachment>,
+    #[serde(default)]
     pub embeds: Vec<Embed>,
+    #[serde(default)]
+    pub mentions: Vec<User>,
+    /// Ids of the mentioned roles
+    #[serde(default)]
+    pub mention_roles: Vec<String>,
+    #[serde(default)]
+    pub mention_everyone: bool,
+    #[serde(default)]
     pub reactions: Vec<Reaction>,
+    /*
+    pub tts: bool,
+    pub mention_channels: Vec<ChannelMention>,
     pub nonce: Nonce,
     pub pinned: bool,
     pub webhook_id: Option<Snowflake>,
-    pub message_type: i32,
     pub activity: Option<MessageActivity>,
     pub application: Option<Application>,
     pub application_id: Snowflake,
     pub flags: Option<i32>,
-    pub message_reference: Option<MessageReference>,
     pub message_snapshots: Option<Vec<MessageSnapshot>>,
-    pub referenced_message: Option<Box<Message>>,
     pub interaction_metadata: Option<Box<MessageInteractionMetadata>>,
     pub interaction: Option<Box<MessageInteraction>>,
     pub thread: Option<Channel>,
@@ -41,3 +52,329 @@ pub struct Message {
     pub poll: Option<Box<Poll>>,
     pub call: Option<MessageCall>,*/
 }
+
+/// Payload of `MESSAGE_UPDATE`. Discord may leave out anything but the ids, e.g. when
+/// only the embeds of a link were resolved.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MessageUpdate {
+    pub id: String,
+    pub channel_id: String,
+    pub content: Option<String>,
+    pub edited_timestamp: Option<String>,
+    pub attachments: Option<Vec<Attachment>>,
+    pub embeds: Option<Vec<Embed>>,
+    pub mentions: Option<Vec<User>>,
+    pub mention_roles: Option<Vec<String>>,
+    pub mention_everyone: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MessageReference {
+    pub message_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub guild_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    /// Size in bytes
+    pub size: u64,
+    pub url: String,
+    pub content_type: Option<String>,
+}
+
+impl Attachment {
+    /// Human readable size, e.g. `1.4 MB`.
+    pub fn display_size(&self) -> String {
+        const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+        let mut size = self.size as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            format!("{} {}", self.size, UNITS[0])
+        } else {
+            format!("{size:.1} {}", UNITS[unit])
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Embed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<EmbedField>,
+    pub footer: Option<EmbedFooter>,
+    pub author: Option<EmbedAuthor>,
+    /// RGB color of the side bar
+    pub color: Option<u32>,
+    /// `rich` for bot embeds, `image`, `gifv`, `video` or `link` for previews of links
+    #[serde(rename = "type")]
+    pub embed_type: Option<String>,
+    pub image: Option<EmbedMedia>,
+    pub thumbnail: Option<EmbedMedia>,
+    pub video: Option<EmbedMedia>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmbedMedia {
+    pub url: String,
+}
+
+impl Embed {
+    /// Whether there is any text to show, image and gif previews only point to media.
+    pub fn has_text(&self) -> bool {
+        self.author.is_some()
+            || self.title.is_some()
+            || self.description.is_some()
+            || !self.fields.is_empty()
+            || self.footer.is_some()
+    }
+
+    /// The link or media shown for an embed without text.
+    pub fn preview_url(&self) -> Option<&str> {
+        self.url
+            .as_ref()
+            .or(self.video.as_ref().map(|m| &m.url))
+            .or(self.image.as_ref().map(|m| &m.url))
+            .or(self.thumbnail.as_ref().map(|m| &m.url))
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmbedFooter {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmbedAuthor {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Reaction {
+    pub count: u32,
+    /// Whether the current user added this reaction
+    pub me: bool,
+    pub emoji: ReactionEmoji,
+}
+
+/// The emoji of a reaction: a unicode glyph in `name`, or a custom emoji with an `id`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReactionEmoji {
+    pub id: Option<String>,
+    /// `None` when the custom emoji was deleted
+    pub name: Option<String>,
+}
+
+impl ReactionEmoji {
+    /// Custom emojis are compared by id since they can be renamed.
+    pub fn same_as(&self, other: &ReactionEmoji) -> bool {
+        match (&self.id, &other.id) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.name == other.name,
+            _ => false,
+        }
+    }
+
+    /// How the emoji appears in reaction endpoints: the glyph, or `name:id` for custom emojis.
+    pub fn path_segment(&self) -> String {
+        let name = self.name.as_deref().unwrap_or("_");
+        match &self.id {
+            Some(id) => super::percent_encode(&format!("{name}:{id}")),
+            None => super::percent_encode(name),
+        }
+    }
+}
+
+pub const DEFAULT: u8 = 0;
+pub const RECIPIENT_ADD: u8 = 1;
+pub const RECIPIENT_REMOVE: u8 = 2;
+pub const CALL: u8 = 3;
+pub const CHANNEL_NAME_CHANGE: u8 = 4;
+pub const CHANNEL_ICON_CHANGE: u8 = 5;
+pub const CHANNEL_PINNED_MESSAGE: u8 = 6;
+pub const USER_JOIN: u8 = 7;
+pub const GUILD_BOOST: u8 = 8;
+pub const GUILD_BOOST_TIER_1: u8 = 9;
+pub const GUILD_BOOST_TIER_2: u8 = 10;
+pub const GUILD_BOOST_TIER_3: u8 = 11;
+pub const CHANNEL_FOLLOW_ADD: u8 = 12;
+pub const THREAD_CREATED: u8 = 18;
+pub const REPLY: u8 = 19;
+pub const CHAT_INPUT_COMMAND: u8 = 20;
+pub const THREAD_STARTER_MESSAGE: u8 = 21;
+pub const CONTEXT_MENU_COMMAND: u8 = 23;
+
+impl Message {
+    /// Counts a reaction added by someone, `me` being whether it was the current user.
+    pub fn add_reaction(&mut self, emoji: ReactionEmoji, me: bool) {
+        match self.reactions.iter_mut().find(|r| r.emoji.same_as(&emoji)) {
+            Some(reaction) if me && reaction.me => {}
+            Some(reaction) => {
+                reaction.count += 1;
+                reaction.me |= me;
+            }
+            None => self.reactions.push(Reaction {
+                count: 1,
+                me,
+                emoji,
+            }),
+        }
+    }
+
+    /// Uncounts a reaction, dropping it once nobody reacts with that emoji anymore.
+    pub fn remove_reaction(&mut self, emoji: &ReactionEmoji, me: bool) {
+        let Some(index) = self.reactions.iter().position(|r| r.emoji.same_as(emoji)) else {
+            return;
+        };
+
+        let reaction = &mut self.reactions[index];
+        if me && !reaction.me {
+            return;
+        }
+        reaction.count = reaction.count.saturating_sub(1);
+        reaction.me &= !me;
+        if reaction.count == 0 {
+            self.reactions.remove(index);
+        }
+    }
+
+    /// Takes the fields an edit sent, keeping the rest (reactions are never part of it).
+    pub fn apply_update(&mut self, update: MessageUpdate) {
+        if let Some(content) = update.content {
+            self.content = Some(content);
+        }
+        if let Some(edited_timestamp) = update.edited_timestamp {
+            self.edited_timestamp = Some(edited_timestamp);
+        }
+        if let Some(attachments) = update.attachments {
+            self.attachments = attachments;
+        }
+        if let Some(embeds) = update.embeds {
+            self.embeds = embeds;
+        }
+        if let Some(mentions) = update.mentions {
+            self.mentions = mentions;
+        }
+        if let Some(mention_roles) = update.mention_roles {
+            self.mention_roles = mention_roles;
+        }
+        if let Some(mention_everyone) = update.mention_everyone {
+            self.mention_everyone = mention_everyone;
+        }
+    }
+
+    /// Whether Discord generated this message (joins, pins, ...) rather than a user writing it.
+    pub fn is_system(&self) -> bool {
+        !matches!(
+            self.message_type,
+            DEFAULT | REPLY | CHAT_INPUT_COMMAND | THREAD_STARTER_MESSAGE | CONTEXT_MENU_COMMAND
+        )
+    }
+
+    /// Whether this message answers another one.
+    pub fn is_reply(&self) -> bool {
+        self.message_type == REPLY && self.message_reference.is_some()
+    }
+
+    /// Describes a system message the way the official client does, `None` for user messages.
+    pub fn system_text(&self) -> Option<String> {
+        if !self.is_system() {
+            return None;
+        }
+
+        let content = self.content.as_deref().unwrap_or("");
+        let text = match self.message_type {
+            RECIPIENT_ADD => "added someone to the group.".to_string(),
+            RECIPIENT_REMOVE => "removed someone from the group.".to_string(),
+            CALL => "started a call.".to_string(),
+            CHANNEL_NAME_CHANGE => format!("changed the channel name: {content}"),
+            CHANNEL_ICON_CHANGE => "changed the channel icon.".to_string(),
+            CHANNEL_PINNED_MESSAGE => "pinned a message to this channel.".to_string(),
+            USER_JOIN => "joined the server.".to_string(),
+            GUILD_BOOST => "boosted the server!".to_string(),
+            GUILD_BOOST_TIER_1 | GUILD_BOOST_TIER_2 | GUILD_BOOST_TIER_3 => {
+                format!(
+                    "boosted the server, it reached level {}!",
+                    self.message_type - GUILD_BOOST
+                )
+            }
+            CHANNEL_FOLLOW_ADD => format!("added {content} to this channel."),
+            THREAD_CREATED => format!("started a thread: {content}"),
+            _ if content.is_empty() => "did something Discord doesn't describe.".to_string(),
+            _ => content.to_string(),
+        };
+
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "author": {"id": "3", "username": "alice"},
+            "content": "hello",
+            "timestamp": "2024-01-01T00:00:00+00:00",
+            "edited_timestamp": null,
+            "embeds": [{"title": "link"}],
+            "reactions": [{"count": 2, "me": true, "emoji": {"id": null, "name": "👍"}}],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn updates_keep_what_they_leave_out() {
+        let mut message = message();
+        let update: MessageUpdate = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "content": "hello there",
+            "edited_timestamp": "2024-01-01T00:01:00+00:00",
+        }))
+        .unwrap();
+
+        message.apply_update(update);
+
+        assert_eq!(message.content.as_deref(), Some("hello there"));
+        assert!(message.edited_timestamp.is_some());
+        assert_eq!(message.author.username, "alice");
+        assert_eq!(message.embeds.len(), 1);
+        assert_eq!(message.reactions.len(), 1);
+        assert_eq!(message.reactions[0].count, 2);
+
+        let update: MessageUpdate = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "embeds": [],
+        }))
+        .unwrap();
+        message.apply_update(update);
+
+        assert!(message.embeds.is_empty());
+        assert_eq!(message.content.as_deref(), Some("hello there"));
+    }
+}
@@ -1,33 +1,90 @@
 pub mod channel;
 pub mod dm;
 pub mod emoji;
+pub mod error;
+pub mod gateway;
 pub mod guild;
 pub mod message;
+pub mod ratelimit;
 pub mod user;
 
-use reqwest::{Client, Method};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use reqwest::{
+    Client, Method, StatusCode,
+    multipart::{Form, Part},
+};
 
 pub use channel::Channel;
+use chrono::{DateTime, Utc};
 pub use dm::DM;
 pub use emoji::Emoji;
+pub use error::ApiError;
 pub use guild::Guild;
-pub use message::Message;
+pub use message::{Attachment, Message};
 use serde::de::DeserializeOwned;
 pub use user::User;
 
-use crate::{
-    Error,
-    api::{
-        channel::{PermissionContext, Role},
-        guild::GuildMember,
-    },
+use crate::api::{
+    channel::{PermissionContext, Role, ThreadList},
+    guild::GuildMember,
+    message::ReactionEmoji,
+    ratelimit::{RateLimiter, route_key},
 };
 
+/// A local file attached to an outgoing message.
+#[derive(Debug, Clone)]
+pub struct UploadFile {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl UploadFile {
+    pub async fn read(path: &Path) -> Result<Self, ApiError> {
+        let data = tokio::fs::read(path).await?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+
+        Ok(Self { filename, data })
+    }
+}
+
+/// Percent-encodes `text` for use in a URL path segment or query value.
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Unix time in milliseconds of the first second of 2015, where snowflakes start counting.
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// When the object with the given snowflake id was created.
+pub fn snowflake_time(id: &str) -> Option<DateTime<Utc>> {
+    let id: u64 = id.parse().ok()?;
+    DateTime::from_timestamp_millis((id >> 22) as i64 + DISCORD_EPOCH_MS)
+}
+
+/// How many times a request is retried after being rate limited before giving up.
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
 #[derive(Debug, Clone)]
 pub struct ApiClient {
     pub http_client: Client,
     pub auth_token: String,
     pub base_url: String,
+    pub rate_limiter: Arc<RateLimiter>,
 }
 
 impl ApiClient {
@@ -36,6 +93,7 @@ impl ApiClient {
             http_client,
             auth_token,
             base_url,
+            rate_limiter: Arc::new(RateLimiter::default()),
         }
     }
 
@@ -44,46 +102,112 @@ impl ApiClient {
         endpoint: &str,
         method: Method,
         body: Option<serde_json::Value>,
-    ) -> Result<T, Error> {
-        let url = format!("{}/{}", self.base_url, endpoint);
-        let mut request = self
-            .http_client
-            .request(method, &url)
-            .header("Authorization", self.auth_token.as_str());
-
-        if let Some(data) = body {
-            request = request.json(&data);
-        }
+    ) -> Result<T, ApiError> {
+        self.send_request(endpoint, method, body, &[]).await
+    }
 
-        let response = request.send().await?;
-        let status = response.status();
+    /// Sends a request, as multipart with `body` as `payload_json` when `files` isn't empty.
+    async fn send_request<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        method: Method,
+        body: Option<serde_json::Value>,
+        files: &[UploadFile],
+    ) -> Result<T, ApiError> {
+        let url = format!("{}/{}", self.base_url, endpoint.trim_start_matches('/'));
+        let route = route_key(&method, endpoint);
+
+        let mut attempts = 0;
+        loop {
+            let queue_slot = self.rate_limiter.acquire(&route).await;
+            let mut request = self
+                .http_client
+                .request(method.clone(), &url)
+                .header("Authorization", self.auth_token.as_str());
+
+            if !files.is_empty() {
+                // Forms can't be cloned, so one is built for every attempt
+                let mut form =
+                    Form::new().text("payload_json", body.clone().unwrap_or_default().to_string());
+                for (i, file) in files.iter().enumerate() {
+                    form = form.part(
+                        format!("files[{i}]"),
+                        Part::bytes(file.data.clone()).file_name(file.filename.clone()),
+                    );
+                }
+                request = request.multipart(form);
+            } else if let Some(data) = &body {
+                request = request.json(data);
+            }
+
+            let response = request.send().await?;
+            let status = response.status();
+            self.rate_limiter.update(&route, response.headers());
+
+            if status == StatusCode::TOO_MANY_REQUESTS && attempts < MAX_RATE_LIMIT_RETRIES {
+                let header_retry_after = response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<f64>().ok());
+                let limit: serde_json::Value = response.json().await.unwrap_or_default();
 
-        if status.is_success() {
-            Ok(response.json::<T>().await?)
-        } else {
-            let body = response
-                .text()
-                .await
-                .unwrap_or("Failed to read error body".to_string());
-            Err(format!("API Error: Status {status}. Details: {body}").into())
+                let retry_after = limit["retry_after"]
+                    .as_f64()
+                    .or(header_retry_after)
+                    .unwrap_or(1.0);
+                let global = limit["global"].as_bool().unwrap_or(false);
+
+                self.rate_limiter
+                    .limited(&route, Duration::from_secs_f64(retry_after), global);
+                // The next `acquire` waits for the limit to reset, without keeping the
+                // route from requests that are allowed sooner
+                drop(queue_slot);
+
+                attempts += 1;
+                continue;
+            }
+
+            let text = response.text().await?;
+
+            if !status.is_success() {
+                return Err(ApiError::from_response(status, &text));
+            }
+
+            // Endpoints answering 204 No Content have an empty body
+            let text = if text.is_empty() { "null" } else { &text };
+            return serde_json::from_str::<T>(text).map_err(|error| ApiError::Decode {
+                error,
+                body: text.to_string(),
+            });
         }
     }
 
-    pub async fn get_current_user(&self) -> Result<User, Error> {
+    pub async fn get_current_user(&self) -> Result<User, ApiError> {
         self.api_request("users/@me", Method::GET, None).await
     }
 
-    pub async fn get_channel(&self, channel_id: &str) -> Result<Channel, Error> {
+    pub async fn get_channel(&self, channel_id: &str) -> Result<Channel, ApiError> {
         self.api_request(format!("channels/{channel_id}").as_str(), Method::GET, None)
             .await
     }
 
-    pub async fn get_dms(&self) -> Result<Vec<DM>, Error> {
+    /// Opens the DM channel with a user, or returns the existing one.
+    pub async fn create_dm(&self, recipient_id: &str) -> Result<DM, ApiError> {
+        self.api_request(
+            "users/@me/channels",
+            Method::POST,
+            Some(serde_json::json!({ "recipient_id": recipient_id })),
+        )
+        .await
+    }
+
+    pub async fn get_dms(&self) -> Result<Vec<DM>, ApiError> {
         self.api_request("users/@me/channels", Method::GET, None)
             .await
     }
 
-    pub async fn get_guild_emojis(&self, guild_id: &str) -> Result<Vec<Emoji>, Error> {
+    pub async fn get_guild_emojis(&self, guild_id: &str) -> Result<Vec<Emoji>, ApiError> {
         self.api_request(
             format!("guilds/{guild_id}/emojis").as_str(),
             Method::GET,
@@ -92,7 +216,7 @@ impl ApiClient {
         .await
     }
 
-    pub async fn get_guild_channels(&self, guild_id: &str) -> Result<Vec<Channel>, Error> {
+    pub async fn get_guild_channels(&self, guild_id: &str) -> Result<Vec<Channel>, ApiError> {
         self.api_request(
             format!("guilds/{guild_id}/channels").as_str(),
             Method::GET,
@@ -101,7 +225,47 @@ impl ApiClient {
         .await
     }
 
-    pub async fn get_guild_roles(&self, guild_id: &str) -> Result<Vec<Role>, Error> {
+    /// Every active thread of the guild the current user can see.
+    pub async fn get_active_threads(&self, guild_id: &str) -> Result<Vec<Channel>, ApiError> {
+        self.api_request::<ThreadList>(
+            format!("guilds/{guild_id}/threads/active").as_str(),
+            Method::GET,
+            None,
+        )
+        .await
+        .map(|list| list.threads)
+    }
+
+    /// The most recently archived public threads of a channel.
+    pub async fn get_archived_threads(
+        &self,
+        channel_id: &str,
+        limit: usize,
+    ) -> Result<Vec<Channel>, ApiError> {
+        self.api_request::<ThreadList>(
+            format!("channels/{channel_id}/threads/archived/public?limit={limit}").as_str(),
+            Method::GET,
+            None,
+        )
+        .await
+        .map(|list| list.threads)
+    }
+
+    pub async fn start_thread_from_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        name: String,
+    ) -> Result<Channel, ApiError> {
+        self.api_request(
+            format!("channels/{channel_id}/messages/{message_id}/threads").as_str(),
+            Method::POST,
+            Some(serde_json::json!({ "name": name })),
+        )
+        .await
+    }
+
+    pub async fn get_guild_roles(&self, guild_id: &str) -> Result<Vec<Role>, ApiError> {
         self.api_request(
             format!("guilds/{guild_id}/roles").as_str(),
             Method::GET,
@@ -110,17 +274,54 @@ impl ApiClient {
         .await
     }
 
-    pub async fn get_guild_member(&self, guild_id: &str) -> Result<GuildMember, Error> {
+    pub async fn get_guild_member(&self, guild_id: &str) -> Result<GuildMember, ApiError> {
         let user = self.get_current_user().await?;
+        self.get_member(guild_id, &user.id).await
+    }
+
+    pub async fn get_member(&self, guild_id: &str, user_id: &str) -> Result<GuildMember, ApiError> {
+        self.api_request(
+            format!("guilds/{guild_id}/members/{user_id}").as_str(),
+            Method::GET,
+            None,
+        )
+        .await
+    }
+
+    /// Members whose username or nickname starts with `query`, which must be URL encoded.
+    pub async fn search_guild_members(
+        &self,
+        guild_id: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<GuildMember>, ApiError> {
+        self.api_request(
+            format!("guilds/{guild_id}/members/search?query={query}&limit={limit}").as_str(),
+            Method::GET,
+            None,
+        )
+        .await
+    }
+
+    /// The first `limit` members of a guild, at most 1000. Bots need the Server Members
+    /// intent enabled for this.
+    pub async fn get_guild_members(
+        &self,
+        guild_id: &str,
+        limit: usize,
+    ) -> Result<Vec<GuildMember>, ApiError> {
         self.api_request(
-            format!("guilds/{guild_id}/members/{}", user.id).as_str(),
+            format!("guilds/{guild_id}/members?limit={limit}").as_str(),
             Method::GET,
             None,
         )
         .await
     }
 
-    pub async fn get_permission_context(&self, guild_id: &str) -> Result<PermissionContext, Error> {
+    pub async fn get_permission_context(
+        &self,
+        guild_id: &str,
+    ) -> Result<PermissionContext, ApiError> {
         let all_guild_roles: Vec<Role> = self.get_guild_roles(guild_id).await?;
         let member_info: GuildMember = self.get_guild_member(guild_id).await?;
 
@@ -144,13 +345,143 @@ impl ApiClient {
         channel_id: &str,
         content: Option<String>,
         tts: bool,
-    ) -> Result<Message, Error> {
+        reply_to: Option<&str>,
+    ) -> Result<Message, ApiError> {
+        let mut body = serde_json::json!({ "content": content, "tts": tts });
+        if let Some(message_id) = reply_to {
+            body["message_reference"] = serde_json::json!({ "message_id": message_id });
+        }
+
         self.api_request(
             format!("channels/{channel_id}/messages").as_str(),
             Method::POST,
-            Some(serde_json::json!({ "content": content, "tts": tts })),
+            Some(body),
+        )
+        .await
+    }
+
+    pub async fn create_message_with_files(
+        &self,
+        channel_id: &str,
+        content: Option<String>,
+        files: &[UploadFile],
+        reply_to: Option<&str>,
+    ) -> Result<Message, ApiError> {
+        let mut body = serde_json::json!({
+            "content": content,
+            "attachments": files
+                .iter()
+                .enumerate()
+                .map(|(i, file)| serde_json::json!({ "id": i, "filename": file.filename }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(message_id) = reply_to {
+            body["message_reference"] = serde_json::json!({ "message_id": message_id });
+        }
+
+        self.send_request(
+            format!("channels/{channel_id}/messages").as_str(),
+            Method::POST,
+            Some(body),
+            files,
+        )
+        .await
+    }
+
+    /// Downloads an attachment into `directory`, returning the path it was written to.
+    pub async fn download_attachment(
+        &self,
+        attachment: &Attachment,
+        directory: &Path,
+    ) -> Result<PathBuf, ApiError> {
+        let response = self.http_client.get(&attachment.url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await?;
+            return Err(ApiError::from_response(status, &text));
+        }
+        let bytes = response.bytes().await?;
+
+        tokio::fs::create_dir_all(directory).await?;
+        // Keep only the last component so a crafted filename can't escape the directory
+        let filename = Path::new(&attachment.filename)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| attachment.id.clone());
+        let path = directory.join(filename);
+        tokio::fs::write(&path, bytes).await?;
+
+        Ok(path)
+    }
+
+    pub async fn edit_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        content: String,
+    ) -> Result<Message, ApiError> {
+        self.api_request(
+            format!("channels/{channel_id}/messages/{message_id}").as_str(),
+            Method::PATCH,
+            Some(serde_json::json!({ "content": content })),
+        )
+        .await
+    }
+
+    pub async fn delete_message(&self, channel_id: &str, message_id: &str) -> Result<(), ApiError> {
+        self.api_request(
+            format!("channels/{channel_id}/messages/{message_id}").as_str(),
+            Method::DELETE,
+            None,
+        )
+        .await
+    }
+
+    pub async fn add_reaction(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        emoji: &ReactionEmoji,
+    ) -> Result<(), ApiError> {
+        self.api_request(
+            format!(
+                "channels/{channel_id}/messages/{message_id}/reactions/{}/@me",
+                emoji.path_segment()
+            )
+            .as_str(),
+            Method::PUT,
+            None,
+        )
+        .await
+    }
+
+    pub async fn remove_reaction(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        emoji: &ReactionEmoji,
+    ) -> Result<(), ApiError> {
+        self.api_request(
+            format!(
+                "channels/{channel_id}/messages/{message_id}/reactions/{}/@me",
+                emoji.path_segment()
+            )
+            .as_str(),
+            Method::DELETE,
+            None,
+        )
+        .await
+    }
+
+    /// Marks the channel read on Discord up to `message_id`.
+    pub async fn ack_message(&self, channel_id: &str, message_id: &str) -> Result<(), ApiError> {
+        self.api_request::<serde_json::Value>(
+            format!("channels/{channel_id}/messages/{message_id}/ack").as_str(),
+            Method::POST,
+            Some(serde_json::json!({ "token": null })),
         )
         .await
+        .map(|_| ())
     }
 
     pub async fn get_channel_messages(
@@ -160,7 +491,7 @@ impl ApiClient {
         before: Option<String>,
         after: Option<String>,
         limit: Option<usize>,
-    ) -> Result<Vec<Message>, Error> {
+    ) -> Result<Vec<Message>, ApiError> {
         let mut endpoint = format!("channels/{channel_id}/messages");
         let mut query = Vec::new();
 
@@ -185,8 +516,84 @@ impl ApiClient {
         self.api_request(&endpoint, Method::GET, None).await
     }
 
-    pub async fn get_current_user_guilds(&self) -> Result<Vec<Guild>, Error> {
+    pub async fn get_current_user_guilds(&self) -> Result<Vec<Guild>, ApiError> {
         self.api_request("/users/@me/guilds", Method::GET, None)
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// Answers each connection with the next response, returning how many were served.
+    async fn serve(listener: TcpListener, responses: Vec<String>) -> usize {
+        let mut served = 0;
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // Only GETs are served, so the request ends with its headers
+            let mut request = Vec::new();
+            let mut chunk = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut chunk).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&chunk[..read]);
+            }
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.ok();
+            served += 1;
+        }
+        served
+    }
+
+    fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried_after_the_wait() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve(
+            listener,
+            vec![
+                response(
+                    "429 Too Many Requests",
+                    r#"{"retry_after": 0.1, "global": false}"#,
+                ),
+                response("200 OK", r#"{"id": "1", "username": "alice"}"#),
+            ],
+        ));
+        let client = ApiClient::new(Client::new(), "token".to_string(), base_url);
+
+        let start = Instant::now();
+        let user = client.get_current_user().await.unwrap();
+
+        assert_eq!(user.username, "alice");
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(server.await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_give_up_after_the_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let limited = response("429 Too Many Requests", r#"{"retry_after": 0.01}"#);
+        let server = tokio::spawn(serve(listener, vec![limited; MAX_RATE_LIMIT_RETRIES + 1]));
+        let client = ApiClient::new(Client::new(), "token".to_string(), base_url);
+
+        assert!(client.get_current_user().await.is_err());
+        assert_eq!(server.await.unwrap(), MAX_RATE_LIMIT_RETRIES + 1);
+    }
+}
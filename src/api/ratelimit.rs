@@ -0,0 +1,338 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::{Method, header::HeaderMap};
+use tokio::{
+    sync::{Mutex as AsyncMutex, OwnedMutexGuard},
+    time::{self, Instant},
+};
+
+/// Path segments whose following id is a "major parameter": Discord gives every
+/// channel, guild and webhook its own bucket for the same route.
+const MAJOR_PARAMETERS: [&str; 3] = ["channels", "guilds", "webhooks"];
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    remaining: u64,
+    reset_at: Instant,
+}
+
+/// Tracks Discord's per-route and global rate limits for an `ApiClient`.
+///
+/// Requests on the same route wait their turn in a FIFO queue, so background refreshes and
+/// user actions are served in order instead of racing each other into a 429.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Route key -> bucket hash reported in `X-RateLimit-Bucket`
+    route_buckets: Mutex<HashMap<String, String>>,
+    /// Bucket hash and major parameter (or route key until the hash is known) -> remaining
+    /// quota
+    buckets: Mutex<HashMap<String, Bucket>>,
+    global_reset_at: Mutex<Option<Instant>>,
+    queues: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+/// Builds the key identifying the rate limit route of a request, e.g.
+/// `GET channels/123/messages/:id`. Query strings are ignored.
+pub fn route_key(method: &Method, endpoint: &str) -> String {
+    let path = endpoint.split('?').next().unwrap_or("");
+    let mut previous = "";
+    let segments: Vec<&str> = path
+        .trim_start_matches('/')
+        .split('/')
+        .map(|segment| {
+            let is_id = !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit());
+            let key = if previous == "reactions" {
+                // Every emoji of a message shares the reaction bucket
+                ":emoji"
+            } else if is_id && !MAJOR_PARAMETERS.contains(&previous) {
+                ":id"
+            } else {
+                segment
+            };
+            previous = segment;
+            key
+        })
+        .collect();
+
+    format!("{method} {}", segments.join("/"))
+}
+
+/// The major parameter of a route key, e.g. `channels/123` in `GET channels/123/messages`.
+fn major_parameter(route: &str) -> Option<String> {
+    let path = route.split_once(' ').map_or(route, |(_, path)| path);
+    let segments: Vec<&str> = path.split('/').collect();
+    segments
+        .windows(2)
+        .find(|pair| MAJOR_PARAMETERS.contains(&pair[0]))
+        .map(|pair| pair.join("/"))
+}
+
+impl RateLimiter {
+    /// Discord shares a bucket hash between the routes of every channel, guild or webhook,
+    /// but counts each of them apart.
+    fn bucket_key(&self, route: &str) -> String {
+        let hash = self.route_buckets.lock().unwrap().get(route).cloned();
+        match (hash, major_parameter(route)) {
+            (Some(hash), Some(major)) => format!("{hash}:{major}"),
+            (Some(hash), None) => hash,
+            (None, _) => route.to_string(),
+        }
+    }
+
+    /// Waits for this route's turn in the queue and for any known limit to reset.
+    ///
+    /// The returned guard keeps the route reserved until the response headers have been
+    /// recorded with `update`.
+    pub async fn acquire(&self, route: &str) -> OwnedMutexGuard<()> {
+        let queue = {
+            let mut queues = self.queues.lock().unwrap();
+            // Nobody holds or waits for a queue only the map refers to
+            queues.retain(|_, queue| Arc::strong_count(queue) > 1);
+            queues.entry(route.to_string()).or_default().clone()
+        };
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| bucket.reset_at > now);
+        let guard = queue.lock_owned().await;
+
+        loop {
+            let now = Instant::now();
+            let global_reset_at = *self.global_reset_at.lock().unwrap();
+            if let Some(reset_at) = global_reset_at
+                && reset_at > now
+            {
+                time::sleep_until(reset_at).await;
+                continue;
+            }
+
+            let key = self.bucket_key(route);
+            let wait_until = {
+                let mut buckets = self.buckets.lock().unwrap();
+                match buckets.get_mut(&key) {
+                    Some(bucket) if bucket.reset_at <= now => {
+                        buckets.remove(&key);
+                        None
+                    }
+                    Some(bucket) if bucket.remaining == 0 => Some(bucket.reset_at),
+                    Some(bucket) => {
+                        bucket.remaining -= 1;
+                        None
+                    }
+                    None => None,
+                }
+            };
+
+            match wait_until {
+                Some(reset_at) => time::sleep_until(reset_at).await,
+                None => return guard,
+            }
+        }
+    }
+
+    /// Records the rate limit headers of a response.
+    pub fn update(&self, route: &str, headers: &HeaderMap) {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        if let Some(bucket) = header("x-ratelimit-bucket") {
+            self.route_buckets
+                .lock()
+                .unwrap()
+                .insert(route.to_string(), bucket.to_string());
+        }
+
+        let remaining = header("x-ratelimit-remaining").and_then(|v| v.parse::<u64>().ok());
+        let reset_after = header("x-ratelimit-reset-after").and_then(|v| v.parse::<f64>().ok());
+
+        if let (Some(remaining), Some(reset_after)) = (remaining, reset_after) {
+            self.buckets.lock().unwrap().insert(
+                self.bucket_key(route),
+                Bucket {
+                    remaining,
+                    reset_at: Instant::now() + Duration::from_secs_f64(reset_after),
+                },
+            );
+        }
+    }
+
+    /// Records a 429 response so that every waiting request backs off for `retry_after`.
+    pub fn limited(&self, route: &str, retry_after: Duration, global: bool) {
+        let reset_at = Instant::now() + retry_after;
+
+        if global {
+            *self.global_reset_at.lock().unwrap() = Some(reset_at);
+        } else {
+            self.buckets.lock().unwrap().insert(
+                self.bucket_key(route),
+                Bucket {
+                    remaining: 0,
+                    reset_at,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn with_bucket(limiter: &RateLimiter, route: &str, hash: &'static str) {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-bucket", HeaderValue::from_static(hash));
+        limiter.update(route, &headers);
+    }
+
+    #[test]
+    fn route_key_keeps_major_parameters_only() {
+        assert_eq!(
+            route_key(&Method::GET, "/channels/1/messages?limit=100"),
+            "GET channels/1/messages"
+        );
+        assert_eq!(
+            route_key(&Method::DELETE, "/channels/1/messages/2"),
+            "DELETE channels/1/messages/:id"
+        );
+        assert_eq!(
+            route_key(&Method::GET, "/guilds/3/members/4"),
+            "GET guilds/3/members/:id"
+        );
+        assert_eq!(
+            route_key(
+                &Method::PUT,
+                "/channels/1/messages/2/reactions/%F0%9F%91%8D/@me"
+            ),
+            "PUT channels/1/messages/:id/reactions/:emoji/@me"
+        );
+        assert_eq!(route_key(&Method::GET, "/users/@me"), "GET users/@me");
+    }
+
+    #[test]
+    fn bucket_key_is_scoped_by_major_parameter() {
+        let limiter = RateLimiter::default();
+        let first = route_key(&Method::GET, "/channels/1/messages");
+        let second = route_key(&Method::GET, "/channels/2/messages");
+        let first_post = route_key(&Method::POST, "/channels/1/messages");
+
+        assert_eq!(limiter.bucket_key(&first), first);
+
+        with_bucket(&limiter, &first, "hash");
+        with_bucket(&limiter, &second, "hash");
+        with_bucket(&limiter, &first_post, "hash");
+        assert_eq!(limiter.bucket_key(&first), "hash:channels/1");
+        assert_eq!(limiter.bucket_key(&second), "hash:channels/2");
+        assert_eq!(limiter.bucket_key(&first_post), limiter.bucket_key(&first));
+
+        let me = route_key(&Method::GET, "/users/@me");
+        with_bucket(&limiter, &me, "users");
+        assert_eq!(limiter.bucket_key(&me), "users");
+    }
+
+    fn with_quota(
+        limiter: &RateLimiter,
+        route: &str,
+        remaining: &'static str,
+        reset_after: &'static str,
+    ) {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static(remaining));
+        headers.insert(
+            "x-ratelimit-reset-after",
+            HeaderValue::from_static(reset_after),
+        );
+        limiter.update(route, &headers);
+    }
+
+    const RESET: Duration = Duration::from_millis(100);
+
+    #[tokio::test]
+    async fn acquire_waits_for_an_exhausted_bucket() {
+        let limiter = RateLimiter::default();
+        let route = route_key(&Method::GET, "/channels/1/messages");
+
+        let start = Instant::now();
+        with_quota(&limiter, &route, "1", "0.1");
+        drop(limiter.acquire(&route).await);
+        assert!(start.elapsed() < RESET);
+
+        drop(limiter.acquire(&route).await);
+        assert!(start.elapsed() >= RESET);
+    }
+
+    #[tokio::test]
+    async fn requests_on_a_route_take_turns() {
+        let limiter = Arc::new(RateLimiter::default());
+        let route = route_key(&Method::GET, "/users/@me");
+
+        let first = limiter.acquire(&route).await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            let route = route.clone();
+            async move { drop(limiter.acquire(&route).await) }
+        });
+        time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        time::timeout(RESET, waiting).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn routes_sharing_a_bucket_hash_share_its_quota() {
+        let limiter = RateLimiter::default();
+        let get = route_key(&Method::GET, "/channels/1/messages");
+        let post = route_key(&Method::POST, "/channels/1/messages");
+        let other_channel = route_key(&Method::POST, "/channels/2/messages");
+        with_bucket(&limiter, &get, "hash");
+        with_bucket(&limiter, &post, "hash");
+        with_bucket(&limiter, &other_channel, "hash");
+
+        let start = Instant::now();
+        with_quota(&limiter, &get, "0", "0.1");
+        drop(limiter.acquire(&other_channel).await);
+        assert!(start.elapsed() < RESET);
+
+        drop(limiter.acquire(&post).await);
+        assert!(start.elapsed() >= RESET);
+    }
+
+    #[tokio::test]
+    async fn the_global_limit_holds_every_route() {
+        let limiter = RateLimiter::default();
+        let route = route_key(&Method::GET, "/channels/1/messages");
+
+        let start = Instant::now();
+        limiter.limited(&route, RESET, true);
+        drop(
+            limiter
+                .acquire(&route_key(&Method::GET, "/users/@me"))
+                .await,
+        );
+
+        assert!(start.elapsed() >= RESET);
+    }
+
+    #[tokio::test]
+    async fn idle_queues_and_expired_buckets_are_dropped() {
+        let limiter = RateLimiter::default();
+        let first = route_key(&Method::GET, "/channels/1/messages");
+        let second = route_key(&Method::GET, "/channels/2/messages");
+        with_quota(&limiter, &first, "5", "0");
+
+        drop(limiter.acquire(&first).await);
+        let _held = limiter.acquire(&second).await;
+
+        assert_eq!(
+            limiter.queues.lock().unwrap().keys().collect::<Vec<_>>(),
+            [&second]
+        );
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+use std::{
+    io::{self, Write},
+    sync::{Mutex, OnceLock},
+};
+
+use arboard::Clipboard;
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+/// The native clipboard, kept open since on X11 the copied text is served by it.
+fn native() -> &'static Mutex<Option<Clipboard>> {
+    static NATIVE: OnceLock<Mutex<Option<Clipboard>>> = OnceLock::new();
+    NATIVE.get_or_init(|| Mutex::new(Clipboard::new().ok()))
+}
+
+/// Copies text to the system clipboard through the terminal using the OSC 52 escape
+/// sequence, which also works over SSH, and to the native clipboard when there is one for
+/// terminals ignoring it.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let osc52 =
+        write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text)).and_then(|_| stdout.flush());
+
+    let copied_natively = native()
+        .lock()
+        .ok()
+        .and_then(|mut clipboard| clipboard.as_mut()?.set_text(text).ok())
+        .is_some();
+
+    if copied_natively { Ok(()) } else { osc52 }
+}
+
+/// Text of the native clipboard, `None` without one, over SSH for instance.
+pub fn paste() -> Option<String> {
+    native().lock().ok()?.as_mut()?.get_text().ok()
+}
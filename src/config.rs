@@ -1,5 +1,9 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use serde::{Deserialize, Serialize};
 
+const APP_NAME: &str = "rivetui";
+
 const DEFAULT_EMOJIS_JSON: &str = include_str!("../emojis.json");
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -9,6 +13,17 @@ pub struct Config {
     pub vim_mode: bool,
     #[serde(default)]
     pub discreet_notifs: bool,
+    /// Where attachments get saved, defaults to the system download directory
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+    /// Also mark channels read on Discord when they are viewed
+    #[serde(default)]
+    pub ack_messages: bool,
+    #[serde(default)]
+    pub panes: PaneConfig,
+    /// Show who is online in the member list, needs the privileged Presence intent
+    #[serde(default)]
+    pub presences: bool,
     pub emoji_map: Vec<(String, String)>,
 }
 
@@ -28,18 +43,58 @@ impl Default for Config {
             version: 1,
             vim_mode: true,
             discreet_notifs: false,
+            download_dir: None,
+            ack_messages: false,
+            panes: PaneConfig::default(),
+            presences: false,
             emoji_map: Vec::new(),
         }
     }
 }
 
+/// Side by side guild, channel and chat panes instead of one screen at a time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PaneConfig {
+    pub enabled: bool,
+    /// Width in columns of the guild and DM list
+    pub guild_width: u16,
+    /// Width in columns of the channel list
+    pub channel_width: u16,
+    /// Narrower terminals fall back to one screen at a time
+    pub min_width: u16,
+    /// Width in columns of the member list next to the chat
+    pub member_width: u16,
+}
+
+impl Default for PaneConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            guild_width: 22,
+            channel_width: 28,
+            min_width: 100,
+            member_width: 26,
+        }
+    }
+}
+
+impl Config {
+    pub fn download_dir(&self) -> PathBuf {
+        self.download_dir
+            .clone()
+            .or_else(dirs::download_dir)
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+}
+
 pub fn load_config() -> Config {
-    let app_name = "rivetui";
-    match confy::load::<Config>(app_name, "config") {
+    match confy::load::<Config>(APP_NAME, "config") {
         Ok(mut cfg) => {
             if cfg.emoji_map.is_empty() {
                 cfg.emoji_map = load_emojis();
-                if let Err(e) = confy::store::<Config>(app_name, "config", cfg.clone()) {
+                if let Err(e) = confy::store::<Config>(APP_NAME, "config", cfg.clone()) {
                     eprintln!("Error storing config: {e}");
                 }
             }
@@ -51,3 +106,86 @@ pub fn load_config() -> Config {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EmojiUse {
+    pub count: u32,
+    /// Unix timestamp in seconds
+    pub last_used: i64,
+}
+
+/// How often each emoji was picked, keyed by glyph for unicode emojis and id for custom ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EmojiUsage {
+    #[serde(default)]
+    pub emojis: HashMap<String, EmojiUse>,
+    /// Picked since it was last written, written along with the read state
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl EmojiUsage {
+    pub fn record(&mut self, key: &str) {
+        let entry = self.emojis.entry(key.to_string()).or_default();
+        entry.count += 1;
+        entry.last_used = chrono::Utc::now().timestamp();
+        self.dirty = true;
+    }
+
+    /// Writes the usage if an emoji was picked since the last time.
+    pub fn flush(&mut self) -> Result<(), confy::ConfyError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+        confy::store(APP_NAME, "emoji_usage", &*self)
+    }
+
+    /// Ranks frequent and recent picks first, a use from today weighs about as much as
+    /// ten from last month.
+    pub fn score(&self, key: &str) -> f64 {
+        self.emojis.get(key).map_or(0.0, |usage| {
+            let age_days =
+                (chrono::Utc::now().timestamp() - usage.last_used).max(0) as f64 / 86_400.0;
+            f64::from(usage.count) * 10.0 / (1.0 + age_days / 3.0)
+        })
+    }
+}
+
+pub fn load_emoji_usage() -> Result<EmojiUsage, confy::ConfyError> {
+    confy::load(APP_NAME, "emoji_usage")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChannelReadState {
+    /// Id of the newest message seen in the channel
+    pub last_read: String,
+    /// Messages mentioning the current user since `last_read`
+    #[serde(default)]
+    pub mentions: u32,
+}
+
+/// What was last read in each channel, so unread markers survive restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReadState {
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelReadState>,
+    /// Changed since it was last written, messages come in too often to write each time
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl ReadState {
+    /// Writes the read state if it changed since the last time.
+    pub fn flush(&mut self) -> Result<(), confy::ConfyError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+        confy::store(APP_NAME, "read_state", &*self)
+    }
+}
+
+pub fn load_read_state() -> Result<ReadState, confy::ConfyError> {
+    confy::load(APP_NAME, "read_state")
+}
@@ -1,34 +1,43 @@
-use std::{collections::HashMap, env, io, process, sync::Arc, time::Duration};
+use std::{collections::HashMap, env, io, path::PathBuf, process, sync::Arc, time::Duration};
 
-use crossterm::{
-    cursor::SetCursorStyle,
-    event::EnableBracketedPaste,
-    execute,
-    terminal::{EnterAlternateScreen, enable_raw_mode},
-};
+use crossterm::{cursor::SetCursorStyle, execute};
 use ratatui::{Terminal, prelude::CrosstermBackend};
 use reqwest::Client;
 use tokio::{
     sync::{
         Mutex,
-        mpsc::{self},
+        mpsc::{self, error::TrySendError},
+        watch,
     },
     task::JoinHandle,
     time::{self},
 };
 
 use crate::{
-    api::{ApiClient, Channel, Emoji, Guild, Message, User, channel::PermissionContext, dm::DM},
-    signals::{restore_terminal, setup_ctrlc_handler},
-    ui::{draw_ui, handle_input_events, handle_keys_events, vim::VimState},
+    api::{
+        ApiClient, ApiError, Channel, Emoji, Guild, Message, User,
+        channel::PermissionContext,
+        dm::DM,
+        gateway::{Gateway, PresenceUpdate, ReactionUpdate},
+        guild::GuildMember,
+        message::MessageUpdate,
+    },
+    signals::{enter_terminal, restore_terminal, setup_ctrlc_handler},
+    ui::{
+        command::CommandLine, draw_ui, events, handle_input_events, handle_keys_events,
+        profile::Profile, selection::MessageSelection, switcher::Switcher, undo::UndoHistory,
+        vim::VimState,
+    },
 };
 
 mod api;
+mod clipboard;
 mod config;
 mod signals;
 mod ui;
 
 const DISCORD_BASE_URL: &str = "https://discord.com/api/v10";
+const DISCORD_GATEWAY_URL: &str = "wss://gateway.discord.gg";
 
 pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 
@@ -47,34 +56,81 @@ pub enum Window {
     Chat(String),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum ChatScroll {
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    Top,
+    Bottom,
+}
+
 #[derive(Debug, Clone)]
 pub enum AppState {
     Home,
     SelectingGuild,
     SelectingDM,
     SelectingChannel(String),
+    /// Posts of a forum channel
+    Forum(String),
     Chatting(String),
     EmojiSelection(String),
+    MentionSelection(String),
     Loading(Window),
+    /// Asking for a new token after Discord rejected the current one
+    EnteringToken,
 }
 
 #[derive(Debug)]
 pub enum AppAction {
     SigInt,
     InputChar(char),
+    InputCtrl(char),
     InputBackspace,
     InputEscape,
     InputSubmit,
+    InputTab,
     SelectNext,
     SelectPrevious,
+    ScrollChat(ChatScroll),
     ApiUpdateMessages(Vec<Message>),
+    ApiUpdateOlderMessages(String, Result<Vec<Message>, ApiError>),
     ApiUpdateChannel(Vec<Channel>),
+    ApiUpdateThreads(Vec<Channel>),
+    /// Channels of a guild fetched in the background to know where new messages are, also
+    /// searched by the quick switcher
+    ApiUpdateGuildActivity(Vec<Channel>),
+    ApiThreadCreated(Channel),
     ApiUpdateEmojis(Vec<Emoji>),
     ApiUpdateGuilds(Vec<Guild>),
     ApiUpdateDMs(Vec<DM>),
     ApiUpdateContext(Option<PermissionContext>),
     ApiUpdateCurrentUser(User),
-    ApiUpdateUnreadMessages(String, Vec<Message>),
+    ApiUpdateMemberSearch(String, Vec<GuildMember>),
+    /// Members of a guild, for the member list
+    ApiUpdateMembers(String, Vec<GuildMember>),
+    /// Membership of the user whose profile is open
+    ApiUpdateProfileMember(GuildMember),
+    /// A DM opened from a profile, existing or just created
+    ApiDMOpened(DM),
+    /// A message the user deleted from the selection
+    ApiMessageDeleted,
+    ApiFailure(String, ApiError),
+    GatewayMessageCreate(Message),
+    GatewayMessageUpdate(MessageUpdate),
+    GatewayMessageDelete(String, String),
+    GatewayReactionAdd(ReactionUpdate),
+    GatewayReactionRemove(ReactionUpdate),
+    GatewayChannelUpdate(Channel),
+    GatewayThreadUpdate(Channel),
+    GatewayChannelDelete(String),
+    GatewayDMUpdate(DM),
+    GatewayPresenceUpdate(Vec<PresenceUpdate>),
+    GatewayDisconnected(String),
+    /// The gateway rejected the token and waits for a new one
+    GatewayUnauthorized,
+    AttachmentDownloaded(PathBuf),
     TransitionToChat(String),
     TransitionToChannels(String),
     TransitionToGuilds,
@@ -83,6 +139,7 @@ pub enum AppAction {
     TransitionToLoading(Window),
     EndLoading,
     SelectEmoji,
+    SelectMention(char),
     Paste(String),
     Tick,
 }
@@ -91,15 +148,48 @@ pub enum AppAction {
 pub enum InputMode {
     Normal,
     Insert,
+    /// Typing an ex command after `:`
+    Command,
 }
 
 #[derive(Debug, Clone)]
 pub struct App {
     api_client: ApiClient,
+    /// Hands the gateway a token entered after the previous one was rejected
+    gateway_token: watch::Sender<String>,
     state: AppState,
     guilds: Vec<Guild>,
     channels: Vec<Channel>,
+    /// Threads and forum posts of the open guild, each pointing at its channel in `parent_id`
+    threads: Vec<Channel>,
+    /// Flat channel list of every guild fetched so far, for the quick switcher
+    guild_channels: HashMap<String, Vec<Channel>>,
+    /// Members of every guild whose member list was shown, empty while loading
+    guild_members: HashMap<String, Vec<GuildMember>>,
+    /// Status of users by id, only filled when presences are enabled
+    presences: HashMap<String, String>,
+    track_presences: bool,
+    show_members: bool,
+    /// Index into the flattened member list while picking a member
+    member_selection: Option<usize>,
+    profile: Option<Profile>,
     messages: Vec<Message>,
+    /// How many lines the chat view is scrolled up from the newest message
+    chat_scroll: usize,
+    /// Largest useful `chat_scroll`, recomputed by `draw_ui` on every frame
+    chat_max_scroll: usize,
+    loading_older_messages: bool,
+    /// Set once a backfill request returns fewer messages than asked for
+    history_exhausted: bool,
+    message_selection: Option<MessageSelection>,
+    replying_to: Option<Message>,
+    /// Id of the message being edited, submitting the input saves it instead of sending
+    editing_message: Option<String>,
+    /// Id of the message the emoji picker reacts to, instead of inserting into the input
+    reacting_to: Option<String>,
+    /// Id of the message a thread is being started from, submitting the input names it
+    starting_thread: Option<String>,
+    switcher: Option<Switcher>,
     custom_emojis: Vec<Emoji>,
     dms: Vec<DM>,
     input: String,
@@ -111,58 +201,149 @@ pub struct App {
     emoji_filter: String,
     /// Byte position where the emoji filter started (position of the ':')
     emoji_filter_start: Option<usize>,
+    mention_filter: String,
+    /// Byte position of the `@` or `#` that opened the mention popup
+    mention_filter_start: Option<usize>,
+    member_search_results: Vec<GuildMember>,
+    /// Names inserted by the mention popup and the `<@id>` syntax they stand for
+    input_mentions: Vec<(String, String)>,
     tick_count: usize,
     context: Option<PermissionContext>,
     mode: InputMode,
+    command_line: CommandLine,
+    undo_history: UndoHistory,
     cursor_position: usize,
     vim_mode: bool,
     vim_state: Option<VimState>,
     current_user: Option<User>,
+    /// Newest known message of each channel
     last_message_ids: HashMap<String, String>,
+    read_state: config::ReadState,
+    /// Guild of every guild channel with known messages, to mark guilds with unreads
+    channel_guilds: HashMap<String, String>,
+    ack_messages: bool,
+    panes: config::PaneConfig,
+    /// Channel whose messages are loaded, it stays visible in the chat pane while another
+    /// pane has focus
+    chat_channel: Option<String>,
+    /// Unsent input of a chat that was left, restored when it is opened again
+    chat_draft: Option<(String, String)>,
+    /// Width of the whole terminal, to decide whether panes fit
+    screen_width: u16,
     discreet_notifs: bool,
+    download_dir: PathBuf,
+    emoji_usage: config::EmojiUsage,
+    /// Set when the screen was used by another program, it is redrawn from scratch
+    full_redraw: bool,
+}
+
+impl App {
+    /// The app as it starts, loading the home screen, before the read state and emoji usage
+    /// saved by the last session are loaded.
+    fn new(token: String, config: config::Config, gateway_token: watch::Sender<String>) -> Self {
+        let download_dir = config.download_dir();
+        Self {
+            api_client: ApiClient::new(Client::new(), token, DISCORD_BASE_URL.to_string()),
+            gateway_token,
+            state: AppState::Loading(Window::Home),
+            guilds: Vec::new(),
+            channels: Vec::new(),
+            threads: Vec::new(),
+            guild_channels: HashMap::new(),
+            guild_members: HashMap::new(),
+            presences: HashMap::new(),
+            track_presences: config.presences,
+            show_members: false,
+            member_selection: None,
+            profile: None,
+            messages: Vec::new(),
+            chat_scroll: 0,
+            chat_max_scroll: 0,
+            loading_older_messages: false,
+            history_exhausted: false,
+            message_selection: None,
+            replying_to: None,
+            editing_message: None,
+            reacting_to: None,
+            starting_thread: None,
+            switcher: None,
+            custom_emojis: Vec::new(),
+            dms: Vec::new(),
+            input: String::new(),
+            selection_index: 0,
+            status_message:
+                "Browse either DMs or Servers. Use arrows to navigate, Enter to select & Esc to quit"
+                    .to_string(),
+            terminal_height: 20,
+            terminal_width: 80,
+            emoji_map: config.emoji_map,
+            emoji_filter: String::new(),
+            emoji_filter_start: None,
+            mention_filter: String::new(),
+            mention_filter_start: None,
+            member_search_results: Vec::new(),
+            input_mentions: Vec::new(),
+            tick_count: 0,
+            context: None,
+            mode: InputMode::Normal,
+            command_line: CommandLine::default(),
+            undo_history: UndoHistory::default(),
+            cursor_position: 0,
+            vim_mode: config.vim_mode,
+            vim_state: if config.vim_mode {
+                Some(VimState::default())
+            } else {
+                None
+            },
+            current_user: None,
+            last_message_ids: HashMap::new(),
+            read_state: config::ReadState::default(),
+            channel_guilds: HashMap::new(),
+            ack_messages: config.ack_messages,
+            panes: config.panes,
+            chat_channel: None,
+            chat_draft: None,
+            screen_width: 0,
+            discreet_notifs: config.discreet_notifs,
+            download_dir,
+            emoji_usage: config::EmojiUsage::default(),
+            full_redraw: false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl App {
+    /// An app with the default config and no token, for unit tests.
+    pub fn for_tests() -> Self {
+        Self::new(
+            String::new(),
+            config::Config::default(),
+            watch::channel(String::new()).0,
+        )
+    }
 }
 
-async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
-    let backend = CrosstermBackend::new(stdout);
+async fn run_app(token: String, mut config: config::Config) -> Result<(), Error> {
+    enter_terminal()?;
+    let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
 
-    let vim_mode = config.vim_mode || env::args().any(|arg| arg == "--vim");
-
-    let app_state = Arc::new(Mutex::new(App {
-        api_client: ApiClient::new(Client::new(), token.clone(), DISCORD_BASE_URL.to_string()),
-        state: AppState::Loading(Window::Home),
-        guilds: Vec::new(),
-        channels: Vec::new(),
-        messages: Vec::new(),
-        custom_emojis: Vec::new(),
-        dms: Vec::new(),
-        input: String::new(),
-        selection_index: 0,
-        status_message:
-            "Browse either DMs or Servers. Use arrows to navigate, Enter to select & Esc to quit"
-                .to_string(),
-        terminal_height: 20,
-        terminal_width: 80,
-        emoji_map: config.emoji_map,
-        emoji_filter: String::new(),
-        emoji_filter_start: None,
-        tick_count: 0,
-        context: None,
-        mode: InputMode::Normal,
-        cursor_position: 0,
-        vim_mode,
-        vim_state: if vim_mode {
-            Some(VimState::default())
-        } else {
-            None
-        },
-        current_user: None,
-        last_message_ids: HashMap::new(),
-        discreet_notifs: config.discreet_notifs,
-    }));
+    config.vim_mode |= env::args().any(|arg| arg == "--vim");
+    let presences = config.presences;
+
+    let (gateway_token, rx_gateway_token) = watch::channel(token.clone());
+
+    let mut app = App::new(token, config, gateway_token);
+    app.read_state = config::load_read_state().unwrap_or_else(|e| {
+        app.status_message = format!("Failed to load which messages were read: {e}");
+        config::ReadState::default()
+    });
+    app.emoji_usage = config::load_emoji_usage().unwrap_or_else(|e| {
+        app.status_message = format!("Failed to load emoji usage: {e}");
+        config::EmojiUsage::default()
+    });
+    let app_state = Arc::new(Mutex::new(app));
 
     let (tx_action, mut rx_action) = mpsc::channel::<AppAction>(32);
     let (tx_shutdown, _) = tokio::sync::broadcast::channel::<()>(1);
@@ -181,7 +362,9 @@ async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
                     return;
                 }
                 _ = interval.tick() => {
-                    if let Err(e) = tx_ticker.send(AppAction::Tick).await {
+                    // Ticks are skipped while the UI is not reading actions, during an external
+                    // edit for instance, instead of filling the channel
+                    if let Err(e @ TrySendError::Closed(_)) = tx_ticker.try_send(AppAction::Tick) {
                         eprintln!("Failed to send tick action: {e}");
                         return;
                     }
@@ -200,9 +383,6 @@ async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
 
     let api_state = Arc::clone(&app_state);
     let tx_api = tx_action.clone();
-    let mut rx_shutdown_api = tx_shutdown.subscribe();
-
-    let mut interval = time::interval(Duration::from_secs(2));
 
     let api_handle: JoinHandle<()> = tokio::spawn(async move {
         let api_client_clone;
@@ -211,154 +391,20 @@ async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
             api_client_clone = state.api_client.clone();
         }
 
-        match api_client_clone.get_current_user().await {
-            Ok(user) => {
-                if let Err(e) = tx_api.send(AppAction::ApiUpdateCurrentUser(user)).await {
-                    eprintln!("Failed to send current user update action: {e}");
-                }
-            }
-            Err(e) => {
-                let mut state = api_state.lock().await;
-                state.status_message = format!("Failed to load current user. {e}");
-            }
-        }
-
-        match api_client_clone.get_current_user_guilds().await {
-            Ok(guilds) => {
-                if let Err(e) = tx_api.send(AppAction::ApiUpdateGuilds(guilds)).await {
-                    eprintln!("Failed to send guild update action: {e}");
-                }
-            }
-            Err(e) => {
-                let mut state = api_state.lock().await;
-                state.status_message = format!("Failed to load servers. {e}");
-            }
-        }
-
-        match api_client_clone.get_dms().await {
-            Ok(dms) => {
-                if let Err(e) = tx_api.send(AppAction::ApiUpdateDMs(dms)).await {
-                    eprintln!("Failed to send DM update action: {e}");
-                }
-            }
-            Err(e) => {
-                let mut state = api_state.lock().await;
-                state.status_message = format!("Failed to load DMs. {e}");
-            }
-        }
-
-        tx_api.send(AppAction::EndLoading).await.ok();
-
-        loop {
-            tokio::select! {
-                _ = rx_shutdown_api.recv() => {
-                    return;
-                }
-
-                _ = interval.tick() => {
-                    let current_channel_id = {
-                        let state = api_state.lock().await;
-                        match &state.state {
-                            AppState::Chatting(id) => Some(id.clone()),
-                            _ => None,
-                        }
-                    };
-
-                    if let Some(channel_id) = current_channel_id {
-                        const MESSAGE_LIMIT: usize = 100;
-
-                        match api_client_clone.get_channel_messages(
-                            &channel_id,
-                            None,
-                            None,
-                            None,
-                            Some(MESSAGE_LIMIT),
-                        )
-                        .await
-                        {
-                            Ok(messages) => {
-                                if let Err(e) = tx_api.send(AppAction::ApiUpdateMessages(messages)).await {
-                                    eprintln!("Failed to send message update action: {e}");
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                api_state.lock().await.status_message = format!("Error loading chat: {e}");
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        events::load_account(&api_client_clone, &tx_api).await;
     });
 
-    let background_state = Arc::clone(&app_state);
-    let tx_background = tx_action.clone();
-    let mut rx_shutdown_background = tx_shutdown.subscribe();
-    let mut background_interval = time::interval(Duration::from_secs(3));
-
-    let background_handle: JoinHandle<()> = tokio::spawn(async move {
-        let api_client_clone;
-        {
-            let state = background_state.lock().await;
-            api_client_clone = state.api_client.clone();
-        }
-
-        // Wait a bit before starting background loops so we don't clobber initial requests
-        tokio::time::sleep(Duration::from_secs(5)).await;
-
-        loop {
-            tokio::select! {
-                _ = rx_shutdown_background.recv() => {
-                    return;
-                }
-
-                _ = background_interval.tick() => {
-                    // Fetch DMs to get the latest last_message_id for each
-                    if let Ok(dms) = api_client_clone.get_dms().await {
-                        let mut dms_to_fetch = Vec::new();
-
-                        {
-                            let state = background_state.lock().await;
-
-                            for dm in dms {
-                                if let Some(new_last_id) = &dm.last_message_id {
-                                    // Only fetch messages if we know we have a new message
-                                    let should_fetch = match state.last_message_ids.get(&dm.id) {
-                                        Some(tracked_id) => new_last_id.parse::<u64>().unwrap_or_default() > tracked_id.parse::<u64>().unwrap_or_default(),
-                                        None => true,
-                                    };
-
-                                    if should_fetch {
-                                        dms_to_fetch.push(dm.id.clone());
-                                    }
-                                }
-                            }
-                        }
-
-                        for channel_id in dms_to_fetch {
-                            if let Ok(messages) = api_client_clone.get_channel_messages(
-                                &channel_id,
-                                None,
-                                None,
-                                None,
-                                Some(5),
-                            ).await
-                                && let Err(e) = tx_background.send(AppAction::ApiUpdateUnreadMessages(channel_id, messages)).await
-                            {
-                                eprintln!("Failed to send unread message update action: {e}");
-                                return;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    });
+    let gateway = Gateway::new(rx_gateway_token, DISCORD_GATEWAY_URL.to_string(), presences);
+    let gateway_handle: JoinHandle<()> =
+        tokio::spawn(gateway.run(tx_action.clone(), tx_shutdown.subscribe()));
 
     loop {
         {
             let mut state_guard = app_state.lock().await;
+            if state_guard.full_redraw {
+                terminal.clear().ok();
+                state_guard.full_redraw = false;
+            }
             terminal
                 .draw(|f| {
                     draw_ui(f, &mut state_guard);
@@ -372,7 +418,7 @@ async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
                     InputMode::Normal => {
                         execute!(io::stdout(), SetCursorStyle::BlinkingBlock).ok();
                     }
-                    InputMode::Insert => {
+                    InputMode::Insert | InputMode::Command => {
                         execute!(io::stdout(), SetCursorStyle::BlinkingBar).ok();
                     }
                 }
@@ -391,10 +437,19 @@ async fn run_app(token: String, config: config::Config) -> Result<(), Error> {
 
     drop(rx_action);
 
+    // Read markers and emoji picks changed since the last periodic write would be lost
+    // otherwise
+    let (read_saved, usage_saved) = {
+        let mut app = app_state.lock().await;
+        (app.read_state.flush(), app.emoji_usage.flush())
+    };
+
     let _ = tx_shutdown.send(());
 
-    let _ = tokio::join!(input_handle, api_handle, ticker_handle, background_handle);
+    let _ = tokio::join!(input_handle, api_handle, ticker_handle, gateway_handle);
 
+    read_saved.map_err(|e| format!("Failed to save which messages were read: {e}"))?;
+    usage_saved.map_err(|e| format!("Failed to save emoji usage: {e}"))?;
     Ok(())
 }
 
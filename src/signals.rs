@@ -1,29 +1,39 @@
 use std::sync::Once;
 use std::{io, process};
 
-use crossterm::terminal::disable_raw_mode;
-use crossterm::{event::DisableBracketedPaste, execute, terminal::LeaveAlternateScreen};
+use crossterm::terminal::{EnterAlternateScreen, disable_raw_mode, enable_raw_mode};
+use crossterm::{
+    cursor::{SetCursorStyle, Show},
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    terminal::LeaveAlternateScreen,
+};
 
 static INIT: Once = Once::new();
 
+/// Takes the terminal over for the UI: raw mode, alternate screen and bracketed paste.
+pub fn enter_terminal() -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)
+}
+
+/// Gives the terminal back as it was, for good or while another program uses it.
+pub fn leave_terminal() -> io::Result<()> {
+    let raw_mode = disable_raw_mode();
+
+    let mut stdout = io::stdout();
+    let screen = execute!(stdout, LeaveAlternateScreen, DisableBracketedPaste);
+    let cursor = execute!(stdout, SetCursorStyle::DefaultUserShape, Show);
+
+    raw_mode.and(screen).and(cursor)
+}
+
 pub fn restore_terminal() {
     INIT.call_once(|| {
         eprintln!("\nAttempting terminal cleanup...");
 
-        match disable_raw_mode() {
-            Ok(_) => (),
-            Err(e) => eprintln!("Failed to disable raw mode: {e}"),
-        }
-
-        let mut stdout = io::stdout();
-        match execute!(stdout, LeaveAlternateScreen, DisableBracketedPaste) {
-            Ok(_) => (),
-            Err(e) => eprintln!("Failed to leave alternate screen: {e}"),
-        }
-
-        match execute!(stdout, crossterm::cursor::Show) {
-            Ok(_) => (),
-            Err(e) => eprintln!("Failed to show cursor: {e}"),
+        if let Err(e) = leave_terminal() {
+            eprintln!("Failed to restore the terminal: {e}");
         }
     });
 }
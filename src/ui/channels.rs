@@ -0,0 +1,127 @@
+use std::cmp::Reverse;
+
+use crate::{
+    App,
+    api::{
+        Channel,
+        channel::{GUILD_CATEGORY, GUILD_FORUM},
+    },
+};
+
+/// How many archived threads are fetched when a channel or forum is opened.
+pub const ARCHIVED_THREAD_LIMIT: usize = 25;
+
+/// A selectable row of the channel list.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelRow<'a> {
+    pub channel: &'a Channel,
+    /// 0 for top level channels, 1 inside a category, one more for threads
+    pub depth: usize,
+}
+
+fn matches_filter(name: &str, filter: &str) -> bool {
+    filter.is_empty() || name.to_lowercase().contains(filter)
+}
+
+/// Threads of `parent_id`, active ones first, newest first.
+pub fn threads_of<'a>(app: &'a App, parent_id: &str) -> Vec<&'a Channel> {
+    let mut threads: Vec<&Channel> = app
+        .threads
+        .iter()
+        .filter(|t| t.parent_id.as_deref() == Some(parent_id))
+        .collect();
+
+    threads.sort_by_key(|t| {
+        let last_activity = t.last_message_id.as_deref().unwrap_or(&t.id);
+        (
+            t.is_archived(),
+            Reverse(last_activity.parse::<u64>().unwrap_or_default()),
+        )
+    });
+    threads
+}
+
+/// Appends `channel` and, unless it is a forum whose posts have their own view, its threads.
+fn push_with_threads<'a>(
+    rows: &mut Vec<ChannelRow<'a>>,
+    app: &'a App,
+    channel: &'a Channel,
+    depth: usize,
+    filter: &str,
+) {
+    let threads: Vec<&Channel> = if channel.channel_type == GUILD_FORUM {
+        Vec::new()
+    } else {
+        threads_of(app, &channel.id)
+            .into_iter()
+            .filter(|t| matches_filter(&t.name, filter))
+            .collect()
+    };
+
+    if !matches_filter(&channel.name, filter) && threads.is_empty() {
+        return;
+    }
+
+    rows.push(ChannelRow { channel, depth });
+    rows.extend(threads.into_iter().map(|thread| ChannelRow {
+        channel: thread,
+        depth: depth + 1,
+    }));
+}
+
+/// The readable channels of the open guild matching the filter typed in the input, with
+/// threads nested under their parent. Drawing, moving the cursor and selecting all index
+/// into this list.
+pub fn visible_channels(app: &App) -> Vec<ChannelRow<'_>> {
+    channel_rows(app, &app.input.to_lowercase())
+}
+
+/// Same as `visible_channels` with an explicit, lowercase filter.
+pub fn channel_rows<'a>(app: &'a App, filter: &str) -> Vec<ChannelRow<'a>> {
+    let Some(context) = &app.context else {
+        return Vec::new();
+    };
+
+    let mut rows = Vec::new();
+    for channel in &app.channels {
+        if channel.channel_type != GUILD_CATEGORY {
+            if channel.is_readable(context) {
+                push_with_threads(&mut rows, app, channel, 0, filter);
+            }
+            continue;
+        }
+
+        let mut children = Vec::new();
+        for child in channel.children.as_deref().unwrap_or_default() {
+            if child.is_readable(context) {
+                push_with_threads(&mut children, app, child, 1, filter);
+            }
+        }
+
+        if matches_filter(&channel.name, filter) || !children.is_empty() {
+            rows.push(ChannelRow { channel, depth: 0 });
+            rows.extend(children);
+        }
+    }
+
+    rows
+}
+
+/// Posts of a forum channel matching the filter typed in the input.
+pub fn forum_posts<'a>(app: &'a App, forum_id: &str) -> Vec<&'a Channel> {
+    let filter = app.input.to_lowercase();
+    threads_of(app, forum_id)
+        .into_iter()
+        .filter(|t| matches_filter(&t.name, &filter))
+        .collect()
+}
+
+/// Adds or replaces threads, keeping a single copy of each.
+pub fn merge_threads(app: &mut App, threads: Vec<Channel>) {
+    for thread in threads {
+        match app.threads.iter_mut().find(|t| t.id == thread.id) {
+            Some(existing) => *existing = thread,
+            None => app.threads.push(thread),
+        }
+    }
+}
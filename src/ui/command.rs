@@ -0,0 +1,809 @@
+use std::{fs, path::Path};
+
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, AppState, InputMode, KeywordAction,
+    api::User,
+    ui::{
+        editor, events, layout, members, profile,
+        selection::{self, MessageSelection},
+        switcher::{self, Target},
+    },
+};
+
+/// The `:` line of vim mode, kept between uses for its history.
+#[derive(Debug, Clone, Default)]
+pub struct CommandLine {
+    pub input: String,
+    /// Commands run so far, oldest first
+    history: Vec<String>,
+    /// Entry shown while browsing the history, with what was typed before browsing
+    browsing: Option<(usize, String)>,
+    /// Candidates of the last Tab and the one inserted, pressing Tab again cycles them
+    pub completion: Option<(Vec<String>, usize)>,
+    /// Term of the last `:search`, repeated by a bare `:search`
+    last_search: Option<String>,
+}
+
+/// What the argument of a command is completed against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Argument {
+    None,
+    Channel,
+    User,
+    Guild,
+    Option,
+    Path,
+    Command,
+    Text,
+}
+
+/// What is left to do once a command ran, besides what it changed in the app.
+pub enum Outcome {
+    Done,
+    Quit,
+    Jump(Box<Target>),
+    /// The input is to be written in the external editor
+    EditExternal,
+}
+
+type Run = fn(&mut MutexGuard<'_, App>, &Sender<AppAction>, &str) -> Result<Outcome, String>;
+
+/// An ex command, run with its name or one of its aliases.
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub argument: Argument,
+    pub run: Run,
+}
+
+/// Every command of the `:` line. Features add theirs here.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "quit",
+        aliases: &["q"],
+        usage: ":q",
+        description: "Quits the client",
+        argument: Argument::None,
+        run: |_, _, _| Ok(Outcome::Quit),
+    },
+    Command {
+        name: "join",
+        aliases: &["j"],
+        usage: ":join #channel",
+        description: "Opens a channel, of the open server first",
+        argument: Argument::Channel,
+        run: join,
+    },
+    Command {
+        name: "dm",
+        aliases: &[],
+        usage: ":dm user",
+        description: "Opens the DM with a user, creating it if needed",
+        argument: Argument::User,
+        run: dm,
+    },
+    Command {
+        name: "guild",
+        aliases: &["g", "server"],
+        usage: ":guild name",
+        description: "Opens the channel list of a server",
+        argument: Argument::Guild,
+        run: guild,
+    },
+    Command {
+        name: "set",
+        aliases: &[],
+        usage: ":set option [value]",
+        description: "Changes an option for this session, :set option? shows it",
+        argument: Argument::Option,
+        run: set,
+    },
+    Command {
+        name: "upload",
+        aliases: &["up"],
+        usage: ":upload path",
+        description: "Sends a file, with the input as its message",
+        argument: Argument::Path,
+        run: upload,
+    },
+    Command {
+        name: "reply",
+        aliases: &["r"],
+        usage: ":reply",
+        description: "Replies to the selected or the newest message",
+        argument: Argument::None,
+        run: reply,
+    },
+    Command {
+        name: "edit",
+        aliases: &["e"],
+        usage: ":edit",
+        description: "Edits the selected or your newest message",
+        argument: Argument::None,
+        run: edit,
+    },
+    Command {
+        name: "edit-external",
+        aliases: &[],
+        usage: ":edit-external",
+        description: "Writes the input in $VISUAL or $EDITOR",
+        argument: Argument::None,
+        run: edit_external,
+    },
+    Command {
+        name: "search",
+        aliases: &["s"],
+        usage: ":search term",
+        description: "Selects loaded messages containing the term, again for the next one",
+        argument: Argument::Text,
+        run: search,
+    },
+    Command {
+        name: "help",
+        aliases: &["h"],
+        usage: ":help [command]",
+        description: "Lists the commands or describes one",
+        argument: Argument::Command,
+        run: help,
+    },
+];
+
+/// Options of `:set`, booleans unless noted.
+const OPTIONS: &[&str] = &["ack", "discreet", "downloads", "members", "panes"];
+
+const COMMAND_STATUS: &str = "Command: Tab completes, Up/Down for history, Enter runs, Esc cancels";
+
+/// A command by name, alias or unambiguous prefix of its name.
+pub fn find(name: &str) -> Option<&'static Command> {
+    let exact = COMMANDS
+        .iter()
+        .find(|c| c.name == name || c.aliases.contains(&name));
+    if exact.is_some() {
+        return exact;
+    }
+
+    let mut prefixed = COMMANDS.iter().filter(|c| c.name.starts_with(name));
+    match (prefixed.next(), prefixed.next()) {
+        (Some(command), None) if !name.is_empty() => Some(command),
+        _ => None,
+    }
+}
+
+pub fn open(state: &mut MutexGuard<'_, App>) {
+    state.command_line.input.clear();
+    state.command_line.browsing = None;
+    state.command_line.completion = None;
+    state.mode = InputMode::Command;
+}
+
+/// Opens the command line with `:upload ` typed, the way to send files outside vim mode.
+pub fn start_upload(state: &mut MutexGuard<'_, App>) {
+    open(state);
+    state.command_line.input = "upload ".to_string();
+}
+
+pub fn close(state: &mut MutexGuard<'_, App>) {
+    state.command_line.input.clear();
+    state.command_line.browsing = None;
+    state.command_line.completion = None;
+    state.mode = InputMode::Normal;
+}
+
+/// Hint shown above the command line, the completion position while cycling.
+pub fn status(app: &App) -> String {
+    match &app.command_line.completion {
+        Some((candidates, index)) => format!(
+            "Command: {}/{} {}",
+            index + 1,
+            candidates.len(),
+            candidates.join(" ")
+        ),
+        None => COMMAND_STATUS.to_string(),
+    }
+}
+
+pub fn insert(state: &mut MutexGuard<'_, App>, text: &str) {
+    let line = &mut state.command_line;
+    line.input.push_str(text);
+    line.completion = None;
+}
+
+/// Deletes the last character, or leaves the command line when it is empty like vim does.
+pub fn backspace(state: &mut MutexGuard<'_, App>) {
+    if state.command_line.input.pop().is_none() {
+        close(state);
+        return;
+    }
+    state.command_line.completion = None;
+}
+
+/// Steps through the history, negative towards older commands.
+pub fn browse_history(state: &mut MutexGuard<'_, App>, n: i32) {
+    let line = &mut state.command_line;
+    if line.history.is_empty() {
+        return;
+    }
+
+    let (index, typed) = match line.browsing.take() {
+        Some(browsing) => browsing,
+        None => (line.history.len(), line.input.clone()),
+    };
+    let target = (index as i64 + i64::from(n)).clamp(0, line.history.len() as i64) as usize;
+
+    line.completion = None;
+    if target == line.history.len() {
+        line.input = typed;
+    } else {
+        line.input = line.history[target].clone();
+        line.browsing = Some((target, typed));
+    }
+}
+
+/// Completes the command name, or the argument of the command typed so far. Pressing Tab
+/// again cycles through the other candidates.
+pub fn complete(state: &mut MutexGuard<'_, App>) {
+    if let Some((candidates, index)) = &mut state.command_line.completion {
+        *index = (*index + 1) % candidates.len();
+        let candidate = candidates[*index].clone();
+        replace_completed_word(&mut state.command_line.input, &candidate);
+        return;
+    }
+
+    let input = state.command_line.input.clone();
+    let candidates = match input.split_once(' ') {
+        None => COMMANDS
+            .iter()
+            .filter(|c| c.name.starts_with(&input))
+            .map(|c| c.name.to_string())
+            .collect(),
+        Some((name, argument)) => match find(name) {
+            Some(command) => argument_candidates(state, command.argument, argument),
+            None => Vec::new(),
+        },
+    };
+
+    let Some(first) = candidates.first().cloned() else {
+        return;
+    };
+    replace_completed_word(&mut state.command_line.input, &first);
+    if candidates.len() > 1 {
+        state.command_line.completion = Some((candidates, 0));
+    }
+}
+
+/// Swaps what follows the command name for `candidate`, or the name itself.
+fn replace_completed_word(input: &mut String, candidate: &str) {
+    *input = match input.split_once(' ') {
+        Some((name, _)) => format!("{name} {candidate}"),
+        None => format!("{candidate} "),
+    };
+}
+
+fn argument_candidates(app: &App, argument: Argument, typed: &str) -> Vec<String> {
+    let query = typed.trim_start_matches('#').to_lowercase();
+    let mut candidates: Vec<String> = match argument {
+        Argument::Channel => {
+            let loaded = layout::loaded_guild(app);
+            let mut guilds: Vec<&String> = app.guild_channels.keys().collect();
+            guilds.sort_by_key(|id| Some(*id) != loaded.as_ref());
+
+            guilds
+                .into_iter()
+                .flat_map(|id| &app.guild_channels[id])
+                .chain(&app.threads)
+                .filter(|c| c.name.to_lowercase().starts_with(&query))
+                .map(|c| format!("#{}", c.name))
+                .collect()
+        }
+        Argument::User => app
+            .dms
+            .iter()
+            .map(|d| d.get_name())
+            .chain(known_users(app).into_iter().map(|u| u.username))
+            .filter(|name| name.to_lowercase().starts_with(&query))
+            .collect(),
+        Argument::Guild => app
+            .guilds
+            .iter()
+            .map(|g| g.name.clone())
+            .filter(|name| name.to_lowercase().starts_with(&query))
+            .collect(),
+        Argument::Option => OPTIONS
+            .iter()
+            .filter(|o| o.starts_with(&query))
+            .map(|o| o.to_string())
+            .collect(),
+        Argument::Command => COMMANDS
+            .iter()
+            .filter(|c| c.name.starts_with(&query))
+            .map(|c| c.name.to_string())
+            .collect(),
+        Argument::Path => path_candidates(typed),
+        Argument::None | Argument::Text => Vec::new(),
+    };
+
+    let mut seen = Vec::new();
+    candidates.retain(|c| {
+        let new = !seen.contains(c);
+        seen.push(c.clone());
+        new
+    });
+    candidates
+}
+
+/// Entries of the directory being typed whose name starts with the rest, directories
+/// ending with `/` so completing can go on inside them.
+fn path_candidates(typed: &str) -> Vec<String> {
+    let (dir, prefix) = match typed.rfind('/') {
+        Some(i) => (&typed[..=i], &typed[i + 1..]),
+        None => ("", typed),
+    };
+    let search_dir = match dir {
+        "" => Path::new(".").to_path_buf(),
+        _ => events::expand_home(dir),
+    };
+    let Ok(entries) = fs::read_dir(search_dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{name}{slash}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Runs the typed command and leaves the command line.
+pub fn run(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+) -> Option<KeywordAction> {
+    let input = state.command_line.input.trim().to_string();
+    close(state);
+    if input.is_empty() {
+        return None;
+    }
+
+    let history = &mut state.command_line.history;
+    if history.last() != Some(&input) {
+        history.push(input.clone());
+    }
+
+    let (name, argument) = input.split_once(' ').unwrap_or((&input, ""));
+    let Some(command) = find(name) else {
+        state.status_message = format!("Not a command: {name}. :help lists them.");
+        return None;
+    };
+
+    match (command.run)(state, tx_action, argument.trim()) {
+        Ok(Outcome::Done) => None,
+        Ok(Outcome::Quit) => Some(KeywordAction::Break),
+        Ok(Outcome::Jump(target)) => switcher::jump(state, tx_action, *target),
+        Ok(Outcome::EditExternal) => {
+            editor::edit_input(state);
+            None
+        }
+        Err(message) => {
+            state.status_message = message;
+            None
+        }
+    }
+}
+
+/// The best target matching `query` among those `keep` accepts: exact names before fuzzy
+/// matches, and channels of the open server before others.
+fn find_target(app: &App, query: &str, keep: fn(&Target) -> bool) -> Option<Target> {
+    let targets: Vec<Target> = switcher::ranked(app, query)
+        .into_iter()
+        .filter(keep)
+        .collect();
+    let loaded = layout::loaded_guild(app);
+    let exact = |t: &&Target| t.name().eq_ignore_ascii_case(query);
+    let in_loaded_guild = |t: &&Target| matches!(t, Target::Channel(c, _) if c.guild_id == loaded);
+
+    targets
+        .iter()
+        .filter(exact)
+        .find(in_loaded_guild)
+        .or_else(|| targets.iter().find(exact))
+        .or(targets.first())
+        .cloned()
+}
+
+/// Users seen in the open server's member list or in the loaded messages.
+fn known_users(app: &App) -> Vec<User> {
+    let members = members::chat_guild_id(app)
+        .and_then(|id| app.guild_members.get(&id))
+        .into_iter()
+        .flatten()
+        .map(|m| m.user.clone());
+    let authors = app.messages.iter().map(|m| m.author.clone());
+
+    members.chain(authors).collect()
+}
+
+fn join(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    let name = argument.trim_start_matches('#');
+    if name.is_empty() {
+        return Err("Usage: :join #channel".to_string());
+    }
+
+    find_target(state, name, |t| matches!(t, Target::Channel(..)))
+        .map(|target| Outcome::Jump(Box::new(target)))
+        .ok_or_else(|| format!("No channel matches {argument}."))
+}
+
+fn dm(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    if argument.is_empty() {
+        return Err("Usage: :dm user".to_string());
+    }
+    let name = argument.trim_start_matches('@');
+
+    let user = known_users(state).into_iter().find(|u| {
+        u.username.eq_ignore_ascii_case(name)
+            || u.global_name
+                .as_ref()
+                .is_some_and(|g| g.eq_ignore_ascii_case(name))
+    });
+    if let Some(user) = user {
+        return match profile::existing_dm(state, &user.id) {
+            Some(dm) => Ok(Outcome::Jump(Box::new(Target::DM(dm)))),
+            None => {
+                profile::create_dm(state, tx_action, &user);
+                Ok(Outcome::Done)
+            }
+        };
+    }
+
+    find_target(state, name, |t| matches!(t, Target::DM(_)))
+        .map(|target| Outcome::Jump(Box::new(target)))
+        .ok_or_else(|| format!("No DM or known user matches {argument}."))
+}
+
+fn guild(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    if argument.is_empty() {
+        return Err("Usage: :guild name".to_string());
+    }
+
+    find_target(state, argument, |t| matches!(t, Target::Guild(_)))
+        .map(|target| Outcome::Jump(Box::new(target)))
+        .ok_or_else(|| format!("No server matches {argument}."))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "" | "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Not a boolean: {value}")),
+    }
+}
+
+/// `:set name value`, `:set name` and `:set noname` for booleans, `:set name?` to show.
+fn set(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    let (name, value) = argument.split_once(' ').unwrap_or((argument, ""));
+    let value = value.trim();
+    if let Some(name) = name.strip_suffix('?') {
+        let shown = match name {
+            "ack" => state.ack_messages.to_string(),
+            "discreet" => state.discreet_notifs.to_string(),
+            "downloads" => state.download_dir.display().to_string(),
+            "members" => state.show_members.to_string(),
+            "panes" => state.panes.enabled.to_string(),
+            _ => return Err(format!("Unknown option: {name}")),
+        };
+        state.status_message = format!("{name} = {shown}");
+        return Ok(Outcome::Done);
+    }
+
+    let (name, enable) = match name.strip_prefix("no") {
+        Some(option) if OPTIONS.contains(&option) => (option, Ok(false)),
+        _ => (name, parse_bool(value)),
+    };
+    match name {
+        "ack" => state.ack_messages = enable?,
+        "discreet" => state.discreet_notifs = enable?,
+        "panes" => state.panes.enabled = enable?,
+        "members" => {
+            state.show_members = enable?;
+            state.member_selection = None;
+            members::load(state, tx_action);
+        }
+        "downloads" if value.is_empty() => return Err("Usage: :set downloads path".to_string()),
+        "downloads" => state.download_dir = events::expand_home(value),
+        "" => return Err(format!("Options: {}", OPTIONS.join(", "))),
+        _ => return Err(format!("Unknown option: {name}")),
+    }
+
+    state.status_message = format!("Set {name} for this session.");
+    Ok(Outcome::Done)
+}
+
+fn upload(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    let AppState::Chatting(channel_id) = state.state.clone() else {
+        return Err("Open a chat to upload to.".to_string());
+    };
+    if argument.is_empty() {
+        return Err("Usage: :upload path".to_string());
+    }
+
+    let text = std::mem::take(&mut state.input);
+    state.cursor_position = 0;
+    let text = text.trim().to_string();
+    let reply_to = state.replying_to.take().map(|m| m.id);
+    events::upload_file(
+        state,
+        tx_action,
+        channel_id,
+        events::expand_home(argument),
+        (!text.is_empty()).then_some(text),
+        reply_to,
+    );
+    Ok(Outcome::Done)
+}
+
+fn reply(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    _: &str,
+) -> Result<Outcome, String> {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return Err("Open a chat to reply in.".to_string());
+    }
+    let message = selection::selected_message(state)
+        .or(state.messages.first())
+        .cloned()
+        .ok_or("No message to reply to.")?;
+
+    selection::start_reply(state, message);
+    Ok(Outcome::Done)
+}
+
+fn edit(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    _: &str,
+) -> Result<Outcome, String> {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return Err("Open a chat to edit in.".to_string());
+    }
+    let message = selection::selected_message(state)
+        .or_else(|| {
+            state
+                .messages
+                .iter()
+                .find(|m| selection::is_own_message(state, m))
+        })
+        .cloned()
+        .ok_or("No message of yours is loaded.")?;
+
+    selection::start_edit(state, &message);
+    Ok(Outcome::Done)
+}
+
+fn edit_external(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    _: &str,
+) -> Result<Outcome, String> {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return Err("Open a chat to write in.".to_string());
+    }
+    Ok(Outcome::EditExternal)
+}
+
+/// Selects the newest loaded message containing the term. A bare `:search` goes on to the
+/// next older match, wrapping around.
+fn search(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return Err("Open a chat to search it.".to_string());
+    }
+    let term = match argument {
+        "" => state
+            .command_line
+            .last_search
+            .clone()
+            .ok_or("Usage: :search term")?,
+        term => term.to_string(),
+    };
+
+    let needle = term.to_lowercase();
+    let matches: Vec<usize> = state
+        .messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| {
+            m.content
+                .as_ref()
+                .is_some_and(|c| c.to_lowercase().contains(&needle))
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let start = match (argument, &state.message_selection) {
+        ("", Some(selection)) => selection.index + 1,
+        _ => 0,
+    };
+    let position = matches.iter().position(|i| *i >= start).unwrap_or(0);
+    let Some(&index) = matches.get(position) else {
+        return Err(format!("No loaded message contains \"{term}\"."));
+    };
+
+    state.command_line.last_search = Some(term.clone());
+    state.message_selection = Some(MessageSelection {
+        index,
+        ..Default::default()
+    });
+    state.status_message = format!(
+        "Match {}/{} for \"{term}\". :s for the next one, Esc to stop.",
+        position + 1,
+        matches.len()
+    );
+    Ok(Outcome::Done)
+}
+
+fn help(
+    state: &mut MutexGuard<'_, App>,
+    _: &Sender<AppAction>,
+    argument: &str,
+) -> Result<Outcome, String> {
+    state.status_message = match argument {
+        "" => format!(
+            "Commands: {}. :help command for details.",
+            COMMANDS
+                .iter()
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        name => {
+            let command = find(name).ok_or_else(|| format!("Not a command: {name}"))?;
+            format!("{} - {}", command.usage, command.description)
+        }
+    };
+    Ok(Outcome::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{Mutex, mpsc};
+
+    use super::*;
+    use crate::ui::events::handle_keys_events;
+
+    fn name(command: Option<&Command>) -> Option<&str> {
+        command.map(|c| c.name)
+    }
+
+    #[test]
+    fn find_by_name_alias_or_unambiguous_prefix() {
+        assert_eq!(name(find("upload")), Some("upload"));
+        assert_eq!(name(find("q")), Some("quit"));
+        assert_eq!(name(find("server")), Some("guild"));
+        assert_eq!(name(find("upl")), Some("upload"));
+        // An exact name wins over the longer names it prefixes
+        assert_eq!(name(find("edit")), Some("edit"));
+        assert_eq!(name(find("edit-")), Some("edit-external"));
+    }
+
+    #[test]
+    fn find_rejects_ambiguous_or_unknown_names() {
+        assert_eq!(name(find("")), None);
+        assert_eq!(name(find("se")), None);
+        assert_eq!(name(find("ed")), None);
+        assert_eq!(name(find("nope")), None);
+    }
+
+    #[test]
+    fn path_candidates_list_matching_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("alpha.txt"), "").unwrap();
+        fs::write(dir.path().join("beta.txt"), "").unwrap();
+        fs::write(dir.path().join(".alpine"), "").unwrap();
+        fs::create_dir(dir.path().join("alps")).unwrap();
+        let root = format!("{}/", dir.path().display());
+
+        assert_eq!(
+            path_candidates(&format!("{root}al")),
+            vec![format!("{root}alpha.txt"), format!("{root}alps/")]
+        );
+        // Hidden entries only when asked for
+        assert_eq!(
+            path_candidates(&format!("{root}.al")),
+            vec![format!("{root}.alpine")]
+        );
+        assert_eq!(path_candidates(&root).len(), 3);
+        assert!(path_candidates(&format!("{root}missing/")).is_empty());
+    }
+
+    #[test]
+    fn completing_replaces_the_word_and_cycles() {
+        let app = Mutex::new(App::for_tests());
+        let mut state = app.try_lock().unwrap();
+
+        state.command_line.input = "he".to_string();
+        complete(&mut state);
+        assert_eq!(state.command_line.input, "help ");
+
+        state.command_line.input = "help ed".to_string();
+        complete(&mut state);
+        assert_eq!(state.command_line.input, "help edit");
+        complete(&mut state);
+        assert_eq!(state.command_line.input, "help edit-external");
+        complete(&mut state);
+        assert_eq!(state.command_line.input, "help edit");
+    }
+
+    #[tokio::test]
+    async fn uploads_without_vim_mode() {
+        let mut app = App::for_tests();
+        app.vim_mode = false;
+        app.vim_state = None;
+        app.state = AppState::Chatting("1".to_string());
+        app.input = "caption".to_string();
+        app.cursor_position = app.input.len();
+        let app = Mutex::new(app);
+        let (tx, _rx) = mpsc::channel(32);
+
+        let path = "/nonexistent/cat.png";
+        handle_keys_events(app.lock().await, AppAction::InputCtrl('o'), tx.clone()).await;
+        handle_keys_events(app.lock().await, AppAction::Paste(path.into()), tx.clone()).await;
+        assert_eq!(
+            app.lock().await.command_line.input,
+            format!("upload {path}")
+        );
+
+        handle_keys_events(app.lock().await, AppAction::InputSubmit, tx.clone()).await;
+        let state = app.lock().await;
+        assert_eq!(state.status_message, format!("Uploading {path}..."));
+        assert_eq!(state.mode, InputMode::Normal);
+        // The input went along as the message of the file
+        assert!(state.input.is_empty());
+    }
+
+    #[test]
+    fn booleans() {
+        for value in ["", "on", "true", "yes", "1"] {
+            assert_eq!(parse_bool(value), Ok(true));
+        }
+        for value in ["off", "false", "no", "0"] {
+            assert_eq!(parse_bool(value), Ok(false));
+        }
+        assert!(parse_bool("maybe").is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, AppState,
+    api::{Channel, User, percent_encode},
+    ui::mentions::find_channel,
+};
+
+/// How many members a search asks for.
+const MEMBER_SEARCH_LIMIT: usize = 10;
+
+/// Channel types that can be linked with `<#id>`: text, announcement and forum channels.
+const LINKABLE_CHANNEL_TYPES: [u8; 3] = [0, 5, 15];
+
+/// Something the `@`/`#` popup can insert.
+#[derive(Debug, Clone)]
+pub struct MentionCandidate {
+    /// Shown in the popup and the input box, e.g. `@alice` or `#general`
+    pub display: String,
+    /// What gets sent in its place, e.g. `<@123>`
+    pub syntax: String,
+    pub kind: &'static str,
+}
+
+fn user_candidate(user: &User, nick: Option<&str>) -> MentionCandidate {
+    let name = nick
+        .or(user.global_name.as_deref())
+        .unwrap_or(&user.username);
+
+    MentionCandidate {
+        display: format!("@{name}"),
+        syntax: format!("<@{}>", user.id),
+        kind: "Member",
+    }
+}
+
+fn matches(name: &str, filter: &str) -> bool {
+    name.to_lowercase().contains(filter)
+}
+
+/// The character that opened the popup, `@` or `#`.
+fn trigger(state: &App) -> Option<char> {
+    let start = state.mention_filter_start?;
+    state.input.get(start..)?.chars().next()
+}
+
+fn current_channel_id(state: &App) -> Option<&str> {
+    match &state.state {
+        AppState::Chatting(id) | AppState::MentionSelection(id) => Some(id),
+        _ => None,
+    }
+}
+
+/// Guild of the open channel, `None` in DMs.
+fn current_guild_id(state: &App) -> Option<String> {
+    find_channel(&state.channels, current_channel_id(state)?)?
+        .guild_id
+        .clone()
+}
+
+/// Everything matching the current filter, in the order shown in the popup.
+pub fn candidates(state: &App) -> Vec<MentionCandidate> {
+    let filter = state.mention_filter.to_lowercase();
+    let mut candidates: Vec<MentionCandidate> = Vec::new();
+
+    match trigger(state) {
+        Some('#') => {
+            candidates.extend(
+                Channel::flatten_categories(state.channels.clone())
+                    .into_iter()
+                    .filter(|c| LINKABLE_CHANNEL_TYPES.contains(&c.channel_type))
+                    .filter(|c| matches(&c.name, &filter))
+                    .map(|c| MentionCandidate {
+                        display: format!("#{}", c.name),
+                        syntax: format!("<#{}>", c.id),
+                        kind: "Channel",
+                    }),
+            );
+        }
+        Some('@') => {
+            let channel_id = current_channel_id(state).unwrap_or_default();
+            let mut push_user = |candidate: MentionCandidate| {
+                if !candidates.iter().any(|c| c.syntax == candidate.syntax) {
+                    candidates.push(candidate);
+                }
+            };
+
+            if let Some(dm) = state.dms.iter().find(|d| d.id == channel_id) {
+                dm.recipients
+                    .iter()
+                    .filter(|u| matches(&u.username, &filter))
+                    .for_each(|u| push_user(user_candidate(u, None)));
+            }
+
+            // Members from the search endpoint, then recent authors while it answers
+            for member in &state.member_search_results {
+                let user = &member.user;
+                let names = [
+                    Some(user.username.as_str()),
+                    user.global_name.as_deref(),
+                    member.nick.as_deref(),
+                ];
+                if names.into_iter().flatten().any(|n| matches(n, &filter)) {
+                    push_user(user_candidate(user, member.nick.as_deref()));
+                }
+            }
+            for message in &state.messages {
+                let author = &message.author;
+                let name = author.global_name.as_deref().unwrap_or(&author.username);
+                if matches(&author.username, &filter) || matches(name, &filter) {
+                    push_user(user_candidate(author, None));
+                }
+            }
+
+            if let (Some(context), Some(guild_id)) = (&state.context, current_guild_id(state)) {
+                candidates.extend(
+                    context
+                        .all_guild_roles
+                        .iter()
+                        .filter(|r| r.id != guild_id && matches(&r.name, &filter))
+                        .map(|r| MentionCandidate {
+                            display: format!("@{}", r.name),
+                            syntax: format!("<@&{}>", r.id),
+                            kind: "Role",
+                        }),
+                );
+            }
+        }
+        _ => {}
+    }
+
+    candidates
+}
+
+/// Opens the popup when `trigger` is typed at the start of a word, otherwise just inserts it.
+pub fn start(state: &mut MutexGuard<'_, App>, trigger: char) {
+    let pos = state.cursor_position.min(state.input.len());
+    state.input.insert(pos, trigger);
+    state.cursor_position = pos + trigger.len_utf8();
+
+    let AppState::Chatting(channel_id) = state.state.clone() else {
+        return;
+    };
+    let at_word_start = pos == 0 || state.input[..pos].ends_with([' ', '\n']);
+    if !at_word_start {
+        return;
+    }
+
+    state.mention_filter_start = Some(pos);
+    state.mention_filter.clear();
+    state.member_search_results.clear();
+    state.selection_index = 0;
+    state.state = AppState::MentionSelection(channel_id);
+    state.status_message = "Type to filter. Enter to select, Esc to cancel.".to_string();
+}
+
+/// Leaves the popup, keeping whatever was typed.
+pub fn stop(state: &mut MutexGuard<'_, App>) {
+    if let AppState::MentionSelection(channel_id) = state.state.clone() {
+        state.state = AppState::Chatting(channel_id);
+    }
+    state.mention_filter.clear();
+    state.mention_filter_start = None;
+    state.member_search_results.clear();
+    state.selection_index = 0;
+    state.status_message =
+        "Chatting in channel. Press Enter to send message, Esc to return to channels.".to_string();
+}
+
+/// Recomputes the filter after the input changed, closing the popup once the cursor
+/// leaves the mention, and searches guild members for `@`.
+pub fn update_filter(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    let Some(start) = state.mention_filter_start else {
+        stop(state);
+        return;
+    };
+    let filter_start = start + 1;
+    if state.cursor_position < filter_start || filter_start > state.input.len() {
+        stop(state);
+        return;
+    }
+
+    state.mention_filter = state.input[filter_start..state.cursor_position].to_string();
+    state.selection_index = 0;
+
+    if trigger(state) != Some('@') || state.mention_filter.is_empty() {
+        return;
+    }
+    let Some(guild_id) = current_guild_id(state) else {
+        return;
+    };
+
+    let query = state.mention_filter.clone();
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        match api_client_clone
+            .search_guild_members(&guild_id, &percent_encode(&query), MEMBER_SEARCH_LIMIT)
+            .await
+        {
+            Ok(members) => {
+                tx_clone
+                    .send(AppAction::ApiUpdateMemberSearch(query, members))
+                    .await
+                    .ok();
+            }
+            Err(e) => {
+                tx_clone
+                    .send(AppAction::ApiFailure(
+                        "Failed to search members".to_string(),
+                        e,
+                    ))
+                    .await
+                    .ok();
+            }
+        }
+    });
+}
+
+pub fn move_selection(state: &mut MutexGuard<'_, App>, n: i32) {
+    let total = candidates(state).len();
+    if total == 0 {
+        return;
+    }
+
+    state.selection_index =
+        (state.selection_index as i64 + i64::from(n)).rem_euclid(total as i64) as usize;
+}
+
+/// Replaces the typed `@filter` with the selected candidate's name and remembers what to
+/// send in its place.
+pub fn accept(state: &mut MutexGuard<'_, App>) {
+    let Some(start) = state.mention_filter_start else {
+        stop(state);
+        return;
+    };
+    let Some(candidate) = candidates(state).into_iter().nth(state.selection_index) else {
+        stop(state);
+        return;
+    };
+
+    let end = state.cursor_position.min(state.input.len());
+    let inserted = format!("{} ", candidate.display);
+    state.input.replace_range(start..end, &inserted);
+    state.cursor_position = start + inserted.len();
+    state
+        .input_mentions
+        .push((candidate.display, candidate.syntax));
+
+    stop(state);
+}
+
+/// Swaps the names inserted by the popup back to Discord's mention syntax before sending.
+///
+/// Only whole `@name`/`#name` words outside code are replaced. Repeated names are paired
+/// with the mentions in the order they were picked, so a role and a member sharing a
+/// name each keep their own syntax.
+pub fn expand_mentions(content: &str, mentions: &[(String, String)]) -> String {
+    let mut displays: Vec<&str> = mentions.iter().map(|(d, _)| d.as_str()).collect();
+    // Longest first so `@Bob Smith` wins over `@Bob`
+    displays.sort_by_key(|d| std::cmp::Reverse(d.len()));
+    displays.dedup();
+    let mut used = vec![false; mentions.len()];
+
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(c) = rest.chars().next() {
+        if let Some(skip) = code_span_len(rest) {
+            out.push_str(&rest[..skip]);
+            rest = &rest[skip..];
+            continue;
+        }
+
+        let at_word_start = out.is_empty() || out.ends_with(char::is_whitespace);
+        let matched = displays.iter().find(|d| {
+            at_word_start
+                && rest.starts_with(**d)
+                && !rest[d.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        });
+        if let Some(display) = matched {
+            // The next unused mention with this name, or the last one once all are used
+            let index = (0..mentions.len())
+                .find(|&i| !used[i] && mentions[i].0 == *display)
+                .or_else(|| mentions.iter().rposition(|(d, _)| d == display));
+            if let Some(index) = index {
+                used[index] = true;
+                out.push_str(&mentions[index].1);
+                rest = &rest[display.len()..];
+                continue;
+            }
+        }
+
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Length of the code block or inline code `text` starts with, running to the end of the
+/// text when it is never closed.
+fn code_span_len(text: &str) -> Option<usize> {
+    let fence = ["```", "``", "`"]
+        .into_iter()
+        .find(|f| text.starts_with(f))?;
+    let body = &text[fence.len()..];
+    Some(
+        body.find(fence)
+            .map_or(text.len(), |end| fence.len() + end + fence.len()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(display: &str, syntax: &str) -> (String, String) {
+        (display.to_string(), syntax.to_string())
+    }
+
+    #[test]
+    fn only_whole_names_are_expanded() {
+        let mentions = [mention("@bob", "<@1>")];
+
+        assert_eq!(
+            expand_mentions("@bob, ask @bobby or me@bob", &mentions),
+            "<@1>, ask @bobby or me@bob"
+        );
+    }
+
+    #[test]
+    fn longer_names_win() {
+        let mentions = [mention("@Bob", "<@1>"), mention("@Bob Smith", "<@2>")];
+
+        assert_eq!(
+            expand_mentions("@Bob Smith and @Bob", &mentions),
+            "<@2> and <@1>"
+        );
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let mentions = [mention("@bob", "<@1>"), mention("#general", "<#9>")];
+
+        assert_eq!(
+            expand_mentions("`@bob` @bob\n```\n@bob #general\n```\n#general", &mentions),
+            "`@bob` <@1>\n```\n@bob #general\n```\n<#9>"
+        );
+        assert_eq!(expand_mentions("```@bob", &mentions), "```@bob");
+    }
+
+    #[test]
+    fn same_names_follow_the_picking_order() {
+        let mentions = [mention("@mods", "<@&5>"), mention("@mods", "<@7>")];
+
+        assert_eq!(
+            expand_mentions("@mods and @mods", &mentions),
+            "<@&5> and <@7>"
+        );
+    }
+}
@@ -1,20 +1,42 @@
 use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
-    text::Span,
-    widgets::{BorderType, Clear, List, ListItem, ListState},
+    text::{Line, Span, Text},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph},
 };
 use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    App, AppState,
-    api::{Channel, DM, Emoji, Guild, Message},
+    App, AppState, InputMode, Window,
+    api::{
+        Attachment, Channel, DM, Message,
+        channel::Role,
+        message::{Embed, Reaction},
+        snowflake_time,
+    },
+    ui::{
+        channels, command, completion, embed, emoji_picker,
+        layout::{self, Pane, SidebarEntry},
+        markdown::{self, MarkdownLine, RenderOptions},
+        members,
+        mentions::{self, Mentions},
+        profile::PROFILE_ACTIONS,
+        switcher::{self, Target},
+        unread, vim,
+    },
 };
 
-pub fn draw_ui(f: &mut ratatui::Frame, app: &mut App) {
-    use ratatui::layout::{Constraint, Direction, Layout};
-    use ratatui::text::{Line, Text};
-    use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+/// Background of messages that mention the current user.
+const MENTION_BACKGROUND: Color = Color::Rgb(60, 52, 25);
 
+/// Narrowest chat left when the member list is shown next to it.
+const MIN_CHAT_WIDTH: u16 = 40;
+
+/// How many characters of the replied-to message are quoted above a reply.
+const REPLY_SNIPPET_LENGTH: usize = 60;
+
+pub fn draw_ui(f: &mut Frame, app: &mut App) {
     let area = f.area();
 
     let chunks = Layout::default()
@@ -22,104 +44,77 @@ pub fn draw_ui(f: &mut ratatui::Frame, app: &mut App) {
         .constraints([Constraint::Percentage(90), Constraint::Percentage(10)].as_ref())
         .split(area);
 
-    app.terminal_height = chunks[0].height as usize;
-    app.terminal_width = chunks[0].width as usize;
-
-    let max_height = app.terminal_height.saturating_sub(2);
-    let max_width = app.terminal_width.saturating_sub(2) as u16;
+    app.screen_width = area.width;
+    let full_screen = matches!(
+        app.state,
+        AppState::Home | AppState::Loading(Window::Home) | AppState::EnteringToken
+    );
 
-    match &app.state {
-        AppState::Loading(_) => {
-            let loading_area = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Percentage(40),
-                    Constraint::Length(3),
-                    Constraint::Min(0),
-                ])
-                .split(chunks[0])[1];
-
-            let spinner = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-            let symbol = spinner[app.tick_count % spinner.len()];
-
-            let loading_text = Line::from(vec![
-                Span::styled("Loading", Style::default().fg(Color::LightCyan)),
-                Span::raw(" "),
-                Span::styled(symbol, Style::default().fg(Color::LightCyan)),
-            ]);
+    match &app.state.clone() {
+        _ if layout::panes_active(app) && !full_screen => draw_panes(f, app, chunks[0]),
+        AppState::Loading(_) => draw_loading(f, app, chunks[0]),
+        AppState::Home => draw_home(f, app, chunks[0]),
+        AppState::EnteringToken => draw_token_prompt(f, chunks[0]),
+        AppState::SelectingDM => draw_dms(f, app, chunks[0], true),
+        AppState::SelectingGuild => draw_guilds(f, app, chunks[0], true),
+        AppState::SelectingChannel(guild_id) => draw_channels(f, app, chunks[0], guild_id, true),
+        AppState::Forum(forum_id) => draw_forum(f, app, chunks[0], forum_id),
+        AppState::Chatting(_) | AppState::EmojiSelection(_) | AppState::MentionSelection(_) => {
+            draw_chat(f, app, chunks[0], true)
+        }
+    };
 
-            let loading_paragraph = Paragraph::new(Text::from(vec![loading_text]))
-                .alignment(ratatui::layout::Alignment::Center)
-                .block(Block::default().borders(Borders::NONE));
+    if let AppState::EmojiSelection(_) = &app.state {
+        let input_area = chunks[1];
+        let emoji_popup_height = 8;
 
-            f.render_widget(Clear, chunks[0]);
-            f.render_widget(loading_paragraph, loading_area);
-        }
-        AppState::Home => {
-            let options = [
-                ("Guilds", Color::LightMagenta),
-                ("DMs", Color::LightYellow),
-                ("Quit", Color::LightRed),
-            ];
+        let popup_rect = ratatui::layout::Rect {
+            x: input_area.x + 1,
+            y: input_area.y.saturating_sub(emoji_popup_height + 1),
+            width: input_area.width.saturating_sub(2),
+            height: emoji_popup_height,
+        };
 
-            let items: Vec<ListItem> = options.iter().map(|o| ListItem::new(o.0).fg(o.1)).collect();
+        f.render_widget(Clear, popup_rect);
 
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .title(Span::styled(
-                            "Rivet Client - Home",
-                            Style::default().fg(Color::Yellow),
-                        ))
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Double),
-                )
-                .highlight_style(Style::default().reversed())
-                .highlight_symbol(">> ");
+        let mut filtered_items: Vec<ListItem> = Vec::new();
 
-            app.selection_index = app.selection_index.min(options.len().saturating_sub(1));
+        for emoji in emoji_picker::candidates(app) {
+            let (glyph, kind) = match &emoji.glyph {
+                Some(glyph) => (glyph.clone(), "Unicode"),
+                None => (" ".to_string(), "Guild"),
+            };
+            let aliases = emoji.names[1..]
+                .iter()
+                .map(|n| format!(":{n}:"))
+                .collect::<Vec<_>>()
+                .join(" ");
 
-            let mut state = ListState::default().with_selected(Some(app.selection_index));
-            f.render_widget(Clear, chunks[0]);
-            f.render_stateful_widget(list, chunks[0], &mut state);
+            filtered_items.push(ListItem::new(Line::from(vec![
+                Span::styled(glyph, Style::default().fg(Color::White)),
+                Span::raw(" "),
+                Span::styled(
+                    format!(":{}: ({kind})", emoji.names[0]),
+                    Style::default().fg(Color::LightBlue),
+                ),
+                Span::styled(format!(" {aliases}"), Style::default().fg(Color::DarkGray)),
+            ])));
         }
-        AppState::SelectingDM => {
-            let filter_text = app.input.to_lowercase();
 
-            let filtered_dms: Vec<&DM> = app
-                .dms
-                .iter()
-                .filter(|d| d.get_name().to_lowercase().contains(&filter_text))
-                .collect();
+        // The reaction filter is only shown in the popup title, so it stays open without matches
+        if !filtered_items.is_empty() || app.reacting_to.is_some() {
+            app.selection_index = app
+                .selection_index
+                .min(filtered_items.len().saturating_sub(1));
 
-            let items: Vec<ListItem> = filtered_dms
-                .iter()
-                .map(|d| {
-                    let char = match d.channel_type {
-                        1 => '',
-                        3 => '',
-                        _ => '',
-                    };
-
-                    let color = match d.channel_type {
-                        1 => Color::LightMagenta,
-                        3 => Color::LightBlue,
-                        _ => Color::LightRed,
-                    };
-
-                    ListItem::new(format!("{char} {}", d.get_name()))
-                        .style(Style::default().fg(color))
-                })
-                .collect();
-
-            let num_filtered = items.len();
-            app.selection_index = app.selection_index.min(num_filtered.saturating_sub(1));
-
-            let list = List::new(items)
+            let emoji_list = List::new(filtered_items)
                 .block(
                     Block::default()
                         .title(Span::styled(
-                            "Rivet Client - Direct Messages",
+                            match app.reacting_to {
+                                Some(_) => format!("React :{}", app.emoji_filter),
+                                None => "Select An Emoji".to_string(),
+                            },
                             Style::default().fg(Color::Yellow),
                         ))
                         .borders(Borders::ALL)
@@ -129,459 +124,1265 @@ pub fn draw_ui(f: &mut ratatui::Frame, app: &mut App) {
                 .highlight_symbol(">> ");
 
             let mut state = ListState::default().with_selected(Some(app.selection_index));
-            f.render_widget(Clear, chunks[0]);
-            f.render_stateful_widget(list, chunks[0], &mut state);
+            f.render_stateful_widget(emoji_list, popup_rect, &mut state);
+        } else {
+            app.selection_index = 0;
         }
-        AppState::SelectingGuild => {
-            let filter_text = app.input.to_lowercase();
+    }
 
-            let filtered_guilds: Vec<&Guild> = app
-                .guilds
-                .iter()
-                .filter(|g| g.name.to_lowercase().contains(&filter_text))
-                .collect();
+    if let AppState::MentionSelection(_) = &app.state {
+        draw_mention_popup(f, app, chunks[1]);
+    }
 
-            let mut count = 0;
-            let items: Vec<ListItem> = filtered_guilds
-                .iter()
-                .map(|g| {
-                    let color = if count % 2 == 0 {
-                        Color::LightCyan
-                    } else {
-                        Color::LightYellow
-                    };
+    if app.mode == InputMode::Command {
+        draw_command_line(f, app, chunks[1]);
+    } else {
+        draw_input(f, app, chunks[1]);
+    }
 
-                    count += 1;
+    if app.profile.is_some() {
+        draw_profile(f, app, area);
+    }
+    if app.switcher.is_some() {
+        draw_switcher(f, app, area);
+    }
+}
 
-                    ListItem::new(g.name.as_str()).style(Style::default().fg(color))
-                })
-                .collect();
+/// The input, with the visual mode selection highlighted.
+fn input_text(app: &App) -> Text<'_> {
+    if let AppState::EnteringToken = app.state {
+        return Text::raw("*".repeat(app.input.chars().count()));
+    }
+    let Some((start, end)) = vim::selected_chars(app) else {
+        return Text::raw(app.input.as_str());
+    };
+    let selected = Style::default().fg(Color::Black).bg(Color::LightBlue);
+
+    let mut offset = 0;
+    app.input
+        .split('\n')
+        .map(|line| {
+            let len = line.chars().count();
+            let from = start.clamp(offset, offset + len) - offset;
+            let to = end.clamp(offset, offset + len) - offset;
+            offset += len + 1;
+
+            let byte = |index| {
+                line.char_indices()
+                    .nth(index)
+                    .map_or(line.len(), |(i, _)| i)
+            };
+            Line::from(vec![
+                Span::raw(&line[..byte(from)]),
+                Span::styled(&line[byte(from)..byte(to)], selected),
+                Span::raw(&line[byte(to)..]),
+            ])
+        })
+        .collect()
+}
 
-            let num_filtered = items.len();
-            app.selection_index = app.selection_index.min(num_filtered.saturating_sub(1));
+/// The message input, with the status line as its title.
+fn draw_input(f: &mut Frame, app: &App, area: Rect) {
+    f.render_widget(
+        Paragraph::new(input_text(app)).block(
+            Block::default()
+                .title(Span::styled(
+                    format!("Input: {}", app.status_message),
+                    Style::default().fg(Color::Yellow),
+                ))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double),
+        ),
+        area,
+    );
 
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .title(Span::styled(
-                            "Rivet Client - Guilds",
-                            Style::default().fg(Color::Yellow),
-                        ))
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Double),
-                )
-                .highlight_style(Style::default().reversed())
-                .highlight_symbol(">> ");
+    let input_before_cursor = &app.input[..app.cursor_position];
+    let cursor_lines = input_before_cursor.split('\n').count();
+    let cursor_y = area.y + cursor_lines as u16;
 
-            let mut state = ListState::default().with_selected(Some(app.selection_index));
-            f.render_widget(Clear, chunks[0]);
-            f.render_stateful_widget(list, chunks[0], &mut state);
+    let current_line_start = input_before_cursor.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let cursor_x =
+        area.x + 1 + UnicodeWidthStr::width(&input_before_cursor[current_line_start..]) as u16;
+
+    f.set_cursor_position((cursor_x, cursor_y));
+}
+
+/// The `:` line in place of the input, which keeps its text for when it comes back.
+fn draw_command_line(f: &mut Frame, app: &App, area: Rect) {
+    let line = format!(":{}", app.command_line.input);
+    let cursor_x = area.x + 1 + UnicodeWidthStr::width(line.as_str()) as u16;
+
+    f.render_widget(
+        Paragraph::new(line).block(
+            Block::default()
+                .title(Span::styled(
+                    command::status(app),
+                    Style::default().fg(Color::Yellow),
+                ))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double),
+        ),
+        area,
+    );
+    f.set_cursor_position((cursor_x, area.y + 1));
+}
+
+/// The profile popup: names, roles and dates, then the actions.
+fn draw_profile(f: &mut Frame, app: &App, area: Rect) {
+    let Some(profile) = &app.profile else {
+        return;
+    };
+    let user = &profile.user;
+    let label = Style::default().fg(Color::DarkGray);
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            user.global_name
+                .clone()
+                .unwrap_or_else(|| user.username.clone()),
+            Style::default().fg(Color::White).bold(),
+        )),
+        Line::from(Span::styled(format!("@{}", user.username), label)),
+        Line::default(),
+    ];
+
+    if let Some(member) = &profile.member {
+        if let Some(nick) = &member.nick {
+            lines.push(Line::from(vec![
+                Span::styled("Nickname        ", label),
+                Span::raw(nick.clone()),
+            ]));
+        }
+
+        let mut roles: Vec<&Role> = app
+            .context
+            .iter()
+            .flat_map(|c| &c.all_guild_roles)
+            .filter(|r| member.roles.contains(&r.id))
+            .collect();
+        roles.sort_by_key(|r| std::cmp::Reverse(r.position));
+        let mut spans = vec![Span::styled("Roles           ", label)];
+        if roles.is_empty() {
+            spans.push(Span::styled("none", label));
         }
-        AppState::SelectingChannel(guild_id) => {
-            let filter_text = app.input.to_lowercase();
+        for (i, role) in roles.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::raw(", "));
+            }
+            spans.push(Span::styled(
+                role.name.clone(),
+                Style::default().fg(role_color(role.color)),
+            ));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    if let Some(created) = snowflake_time(&user.id) {
+        lines.push(Line::from(vec![
+            Span::styled("Account created ", label),
+            Span::raw(
+                created
+                    .with_timezone(&chrono::Local)
+                    .format("%-d %B %Y")
+                    .to_string(),
+            ),
+        ]));
+    }
+    let joined = profile
+        .member
+        .as_ref()
+        .and_then(|m| m.joined_at.as_deref())
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok());
+    if let Some(joined) = joined {
+        lines.push(Line::from(vec![
+            Span::styled("Joined server   ", label),
+            Span::raw(
+                joined
+                    .with_timezone(&chrono::Local)
+                    .format("%-d %B %Y")
+                    .to_string(),
+            ),
+        ]));
+    }
+    lines.push(Line::from(vec![
+        Span::styled("User ID         ", label),
+        Span::raw(user.id.clone()),
+    ]));
+    lines.push(Line::default());
+
+    for (i, action) in PROFILE_ACTIONS.iter().enumerate() {
+        lines.push(if i == profile.action {
+            Line::from(Span::styled(
+                format!(">> {action}"),
+                Style::default().reversed(),
+            ))
+        } else {
+            Line::from(format!("   {action}"))
+        });
+    }
 
-            let permission_context = &app.context;
+    let width = (area.width / 2).max(40).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup_rect = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
 
-            let mut list_items: Vec<ListItem> = Vec::new();
+    f.render_widget(Clear, popup_rect);
+    f.render_widget(
+        Paragraph::new(lines).block(pane_block("Profile".to_string(), true)),
+        popup_rect,
+    );
+}
 
-            let should_display_channel_content = |c: &Channel| {
-                let is_readable = permission_context
-                    .as_ref()
-                    .is_some_and(|context| c.is_readable(context));
+/// The quick switcher, centered over everything else with its own query line.
+fn draw_switcher(f: &mut Frame, app: &mut App, area: Rect) {
+    let width = (area.width * 3 / 5).max(40).min(area.width);
+    let height = (area.height * 3 / 5).max(10).min(area.height);
+    let popup_rect = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
 
-                is_readable
-                    && (filter_text.is_empty() || c.name.to_lowercase().contains(&filter_text))
+    let targets = switcher::targets(app);
+    let Some(switcher) = &mut app.switcher else {
+        return;
+    };
+    switcher.selection = switcher.selection.min(targets.len().saturating_sub(1));
+    let (query, selection) = (switcher.query.clone(), switcher.selection);
+
+    let items: Vec<ListItem> = targets
+        .iter()
+        .map(|target| {
+            let ((icon, color), detail, (unread, mentions)) = match target {
+                Target::DM(dm) => (
+                    dm_style(dm),
+                    String::new(),
+                    (
+                        unread::is_unread(app, &dm.id),
+                        unread::mention_count(app, &dm.id),
+                    ),
+                ),
+                Target::Guild(guild) => (
+                    ('\u{f233}', Color::LightCyan),
+                    " server".to_string(),
+                    unread::guild_status(app, &guild.id),
+                ),
+                Target::Channel(channel, guild_name) => (
+                    channel_style(channel),
+                    format!(" in {guild_name}"),
+                    (
+                        unread::is_unread(app, &channel.id),
+                        unread::mention_count(app, &channel.id),
+                    ),
+                ),
             };
 
-            app.channels
-                .iter()
-                .filter(|c| {
-                    if c.children.is_none() && c.channel_type != 4 {
-                        return should_display_channel_content(c);
-                    }
-
-                    if c.channel_type == 4 {
-                        if filter_text.is_empty() || c.name.to_lowercase().contains(&filter_text) {
-                            return true;
-                        }
-
-                        if let Some(children) = &c.children {
-                            return children.iter().any(should_display_channel_content);
-                        }
-                    }
-
-                    false
-                })
-                .for_each(|c| {
-                    let get_channel_style = |channel_type: u8| -> (char, Color) {
-                        match channel_type {
-                            15 => ('', Color::LightYellow),
-                            13 => ('󱝉', Color::LightRed),
-                            5 => ('', Color::LightGreen),
-                            4 => ('', Color::Gray),
-                            2 => ('', Color::LightCyan),
-                            0 => ('', Color::LightBlue),
-                            _ => ('', Color::LightMagenta),
-                        }
-                    };
-
-                    if c.channel_type == 4 {
-                        let (char, color) = get_channel_style(c.channel_type);
-                        list_items.push(
-                            ListItem::new(format!("{char} {}", c.name))
-                                .style(Style::default().fg(color)),
-                        );
-
-                        if let Some(children) = &c.children {
-                            children
-                                .iter()
-                                .filter(|c| should_display_channel_content(c))
-                                .for_each(|child| {
-                                    let (char, color) = get_channel_style(child.channel_type);
-
-                                    list_items.push(
-                                        ListItem::new(format!("  {char} {}", child.name))
-                                            .style(Style::default().fg(color)),
-                                    );
-                                });
-                        }
-                    } else {
-                        let (char, color) = get_channel_style(c.channel_type);
-                        list_items.push(
-                            ListItem::new(format!("{char} {}", c.name))
-                                .style(Style::default().fg(color)),
-                        );
-                    }
-                });
-
-            let num_filtered = list_items.len();
-            app.selection_index = app.selection_index.min(num_filtered.saturating_sub(1));
-
-            let hidden_items: Vec<ListItem> = app
-                .channels
-                .iter()
-                .flat_map(|c| {
-                    if c.channel_type == 4 {
-                        let mut items: Vec<&Channel> = Vec::new();
-
-                        if let Some(children) = &c.children {
-                            items.extend(children.iter().filter(|child| {
-                                permission_context
-                                    .as_ref()
-                                    .is_some_and(|context| !child.is_readable(context))
-                            }));
-                        }
-                        items
-                    } else if permission_context
-                        .as_ref()
-                        .is_some_and(|context| !c.is_readable(context))
-                    {
-                        vec![c]
-                    } else {
-                        vec![]
-                    }
-                })
-                .map(|c| {
-                    let char = match c.channel_type {
-                        15 => '',
-                        13 => '󱝉',
-                        5 => '',
-                        4 => '',
-                        2 => '',
-                        0 => '',
-                        _ => '',
-                    };
-
-                    let color = Color::DarkGray;
-
-                    ListItem::new(format!(" {char} {}", c.name)).style(Style::default().fg(color))
-                })
-                .collect();
-
-            list_items.extend(hidden_items);
-
-            let title = format!(
-                "Channels for Guild: {guild_id} | Channels found: {} | Actual index: {}",
-                num_filtered.saturating_sub(1),
-                app.selection_index
-            );
+            let mut spans = vec![
+                Span::styled(
+                    format!("{icon} {}", target.name()),
+                    unread_style(color, unread),
+                ),
+                Span::styled(detail, Style::default().fg(Color::DarkGray)),
+            ];
+            spans.extend(badge(unread, mentions));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let block = Block::default()
+        .title(Span::styled(
+            format!("Switch to | {} found", items.len()),
+            Style::default().fg(Color::Yellow),
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double);
+    let inner = block.inner(popup_rect);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
 
-            let list = List::new(list_items)
-                .block(
-                    Block::default()
-                        .title(Span::styled(title, Style::default().fg(Color::Yellow)))
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Double),
-                )
-                .highlight_style(Style::default().reversed())
-                .highlight_symbol(">> ");
+    f.render_widget(Clear, popup_rect);
+    f.render_widget(block, popup_rect);
+    f.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Yellow)),
+            Span::raw(query.as_str()),
+        ])),
+        rows[0],
+    );
 
-            let mut state = ListState::default().with_selected(Some(app.selection_index));
-            f.render_widget(Clear, chunks[0]);
-            f.render_stateful_widget(list, chunks[0], &mut state);
+    let list = List::new(items)
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
+    let mut state = ListState::default().with_selected(Some(selection));
+    f.render_stateful_widget(list, rows[1], &mut state);
+
+    f.set_cursor_position((
+        rows[0].x + 2 + UnicodeWidthStr::width(query.as_str()) as u16,
+        rows[0].y,
+    ));
+}
+
+/// Guilds, then DMs or channels, then the chat side by side, only the focused pane takes
+/// the input.
+fn draw_panes(f: &mut Frame, app: &mut App, area: Rect) {
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length(app.panes.guild_width),
+            Constraint::Length(app.panes.channel_width),
+            Constraint::Min(0),
+        ])
+        .split(area);
+    let focus = layout::focused_pane(app);
+
+    draw_guilds(f, app, panes[0], focus == Some(Pane::Sidebar));
+
+    match &app.state.clone() {
+        AppState::Loading(Window::Channel(_)) => draw_loading(f, app, panes[1]),
+        AppState::Forum(forum_id) => draw_forum(f, app, panes[1], forum_id),
+        _ if layout::middle_shows_dms(app) => {
+            draw_dms(f, app, panes[1], focus == Some(Pane::Middle))
         }
-        AppState::Chatting(_) | AppState::EmojiSelection(_) => {
-            if max_width == 0 {
-                return;
-            }
+        _ => {
+            let guild_id = layout::loaded_guild(app).unwrap_or_default();
+            draw_channels(f, app, panes[1], &guild_id, focus == Some(Pane::Middle));
+        }
+    }
 
-            let mut messages_to_render: Vec<Message> = Vec::new();
-            let mut current_height = 0;
-
-            for message in app.messages.iter() {
-                let formatted_text = format!(
-                    "[{}] {}: {}",
-                    message
-                        .timestamp
-                        .split('T')
-                        .next()
-                        .unwrap_or("")
-                        .to_string()
-                        + " "
-                        + message
-                            .timestamp
-                            .split('T')
-                            .nth(1)
-                            .unwrap_or("")
-                            .split('.')
-                            .next()
-                            .unwrap_or(""),
-                    message.author.username,
-                    message.content.as_deref().unwrap_or("(*non-text*)")
-                );
-
-                let text_lines: Vec<&str> = formatted_text.split('\n').collect();
-                let mut estimated_height = 0;
-
-                let safe_max_width = max_width.saturating_sub(4);
-                for line in text_lines {
-                    let width = UnicodeWidthStr::width(line);
-
-                    if width == 0 || safe_max_width == 0 {
-                        estimated_height += 1;
-                        continue;
-                    }
-
-                    let mut current_line_width = 0;
-                    let mut first_word = true;
-
-                    for word in line.split(' ') {
-                        let word_width = UnicodeWidthStr::width(word);
-                        let space_width = if first_word { 0 } else { 1 };
-
-                        if current_line_width + space_width + word_width <= safe_max_width as usize
-                        {
-                            current_line_width += space_width + word_width;
-                        } else {
-                            if current_line_width > 0 {
-                                estimated_height += 1;
-                            }
-
-                            if word_width > safe_max_width as usize {
-                                let chunks = word_width.div_ceil(safe_max_width as usize);
-                                estimated_height += chunks.saturating_sub(1);
-                                current_line_width = word_width % safe_max_width as usize;
-                                if current_line_width == 0 {
-                                    current_line_width = safe_max_width as usize;
-                                }
-                            } else {
-                                current_line_width = word_width;
-                            }
-                        }
-                        first_word = false;
-                    }
-                    if current_line_width > 0 {
-                        estimated_height += 1;
-                    }
-                }
+    match &app.state {
+        AppState::Loading(Window::Chat(_)) => draw_loading(f, app, panes[2]),
+        _ if layout::visible_chat(app).is_some() => {
+            draw_chat(f, app, panes[2], focus == Some(Pane::Chat))
+        }
+        _ => {
+            f.render_widget(Clear, panes[2]);
+            f.render_widget(
+                pane_block("Rivet Client - No chat open".to_string(), false),
+                panes[2],
+            );
+        }
+    }
+}
 
-                messages_to_render.push(message.clone());
-                current_height += estimated_height;
+/// Border of a list or the chat, dimmed while another pane has the focus.
+fn pane_block(title: String, focused: bool) -> Block<'static> {
+    let block = Block::default()
+        .title(Span::styled(title, Style::default().fg(Color::Yellow)))
+        .borders(Borders::ALL);
+
+    if focused {
+        block.border_type(BorderType::Double)
+    } else {
+        block
+            .border_type(BorderType::Plain)
+            .border_style(Style::default().fg(Color::DarkGray))
+    }
+}
 
-                if current_height >= max_height {
-                    break;
-                }
-            }
+fn draw_loading(f: &mut Frame, app: &mut App, area: Rect) {
+    let loading_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Length(3),
+            Constraint::Min(0),
+        ])
+        .split(area)[1];
+
+    let spinner = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+    let symbol = spinner[app.tick_count % spinner.len()];
+
+    let loading_text = Line::from(vec![
+        Span::styled("Loading", Style::default().fg(Color::LightCyan)),
+        Span::raw(" "),
+        Span::styled(symbol, Style::default().fg(Color::LightCyan)),
+    ]);
+
+    let loading_paragraph = Paragraph::new(Text::from(vec![loading_text]))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::NONE));
+
+    f.render_widget(Clear, area);
+    f.render_widget(loading_paragraph, loading_area);
+}
 
-            messages_to_render.reverse();
-
-            let mut final_content: Vec<Line> = Vec::new();
-
-            for message in messages_to_render.into_iter() {
-                let formatted_time = format!(
-                    " {}]",
-                    message
-                        .timestamp
-                        .split('T')
-                        .nth(1)
-                        .unwrap_or("")
-                        .split('.')
-                        .next()
-                        .unwrap_or(""),
-                );
-
-                let formatted_date = message
-                    .timestamp
-                    .split('T')
-                    .next()
-                    .unwrap_or("")
-                    .to_string();
-
-                let author = format!(" {}: ", message.author.username);
-
-                let content = message
-                    .content
-                    .clone()
-                    .unwrap_or("(*non-text*)".to_string());
-
-                let content_lines: Vec<&str> = content.split('\n').collect();
-
-                for (i, line_content) in content_lines.iter().enumerate() {
-                    let mut spans = vec![];
-
-                    if i == 0 {
-                        spans.push(Span::styled(
-                            "[".to_string(),
-                            Style::default().fg(Color::LightBlue),
-                        ));
-                        spans.push(Span::styled(
-                            formatted_date.clone(),
-                            Style::default().fg(Color::LightCyan),
-                        ));
-                        spans.push(Span::styled(
-                            formatted_time.clone(),
-                            Style::default().fg(Color::LightBlue),
-                        ));
-                        spans.push(Span::styled(
-                            author.clone(),
-                            Style::default().fg(Color::Yellow),
-                        ));
-                    }
-
-                    spans.push(Span::styled(
-                        line_content.to_string(),
-                        Style::default().fg(Color::White),
-                    ));
-                    final_content.push(Line::from(spans));
+/// Explains where to find a token while one is typed in the input.
+fn draw_token_prompt(f: &mut Frame, area: Rect) {
+    let text = Text::from(vec![
+        Line::from(Span::styled(
+            "Discord rejected the token.",
+            Style::default().fg(Color::LightRed),
+        )),
+        Line::default(),
+        Line::from("Paste a valid token in the input below and press Enter to log in again."),
+        Line::from(Span::styled(
+            "It is only kept for this session, update DISCORD_TOKEN to use it next time.",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ]);
+
+    let prompt = Paragraph::new(text)
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(
+            Block::default()
+                .title(Span::styled(
+                    "Rivet Client - Token",
+                    Style::default().fg(Color::Yellow),
+                ))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double),
+        );
+
+    f.render_widget(Clear, area);
+    f.render_widget(prompt, area);
+}
+
+fn draw_home(f: &mut Frame, app: &mut App, area: Rect) {
+    let options = [
+        ("Guilds", Color::LightMagenta),
+        ("DMs", Color::LightYellow),
+        ("Quit", Color::LightRed),
+    ];
+
+    let items: Vec<ListItem> = options.iter().map(|o| ListItem::new(o.0).fg(o.1)).collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(Span::styled(
+                    "Rivet Client - Home",
+                    Style::default().fg(Color::Yellow),
+                ))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double),
+        )
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
+
+    app.selection_index = app.selection_index.min(options.len().saturating_sub(1));
+
+    let mut state = ListState::default().with_selected(Some(app.selection_index));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_dms(f: &mut Frame, app: &mut App, area: Rect, focused: bool) {
+    let filter_text = if focused {
+        app.input.to_lowercase()
+    } else {
+        String::new()
+    };
+
+    let filtered_dms: Vec<&DM> = app
+        .dms
+        .iter()
+        .filter(|d| d.get_name().to_lowercase().contains(&filter_text))
+        .collect();
+
+    let items: Vec<ListItem> = filtered_dms
+        .iter()
+        .map(|d| {
+            let (char, color) = dm_style(d);
+
+            let mut spans = vec![Span::raw(format!("{char} {}", d.get_name()))];
+            spans.extend(unread_badge(app, &d.id));
+
+            ListItem::new(Line::from(spans))
+                .style(unread_style(color, unread::is_unread(app, &d.id)))
+        })
+        .collect();
+
+    let selected = if focused {
+        app.selection_index = app.selection_index.min(items.len().saturating_sub(1));
+        Some(app.selection_index)
+    } else {
+        layout::current_dm_index(app)
+    };
+
+    let list = List::new(items)
+        .block(pane_block(
+            "Rivet Client - Direct Messages".to_string(),
+            focused,
+        ))
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
+
+    let mut state = ListState::default().with_selected(selected);
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_guilds(f: &mut Frame, app: &mut App, area: Rect, focused: bool) {
+    let filter_text = if focused {
+        app.input.to_lowercase()
+    } else {
+        String::new()
+    };
+
+    let mut count = 0;
+    let items: Vec<ListItem> = layout::sidebar_entries(app, &filter_text)
+        .into_iter()
+        .map(|entry| {
+            let g = match entry {
+                SidebarEntry::DirectMessages => {
+                    let (unread, mentions) = unread::dm_status(app);
+                    let mut spans = vec![Span::raw("Direct Messages")];
+                    spans.extend(badge(unread, mentions));
+                    return ListItem::new(Line::from(spans))
+                        .style(unread_style(Color::LightMagenta, unread));
                 }
-            }
+                SidebarEntry::Guild(g) => g,
+            };
 
-            let scroll_offset = if current_height > max_height {
-                current_height.saturating_sub(max_height)
+            let color = if count % 2 == 0 {
+                Color::LightCyan
             } else {
-                0
+                Color::LightYellow
             };
 
-            let paragraph = Paragraph::new(final_content)
-                .block(
-                    Block::default()
-                        .title(Span::styled(
-                            "Rivet Client - Chatting",
-                            Style::default().fg(Color::Yellow),
-                        ))
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Double),
-                )
-                .wrap(Wrap { trim: false })
-                .scroll((scroll_offset as u16, 0));
+            count += 1;
 
-            f.render_widget(Clear, chunks[0]);
-            f.render_widget(paragraph, chunks[0]);
-        }
+            let (unread, mentions) = unread::guild_status(app, &g.id);
+            let mut spans = vec![Span::raw(g.name.clone())];
+            spans.extend(badge(unread, mentions));
+
+            ListItem::new(Line::from(spans)).style(unread_style(color, unread))
+        })
+        .collect();
+
+    let selected = if focused {
+        app.selection_index = app.selection_index.min(items.len().saturating_sub(1));
+        Some(app.selection_index)
+    } else {
+        layout::current_sidebar_index(app)
     };
 
-    if let AppState::EmojiSelection(_) = &app.state {
-        let input_area = chunks[1];
-        let emoji_popup_height = 8;
+    let list = List::new(items)
+        .block(pane_block("Rivet Client - Guilds".to_string(), focused))
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
 
-        let popup_rect = ratatui::layout::Rect {
-            x: input_area.x + 1,
-            y: input_area.y.saturating_sub(emoji_popup_height + 1),
-            width: input_area.width.saturating_sub(2),
-            height: emoji_popup_height,
-        };
+    let mut state = ListState::default().with_selected(selected);
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
 
-        f.render_widget(Clear, popup_rect);
+fn draw_channels(f: &mut Frame, app: &mut App, area: Rect, guild_id: &str, focused: bool) {
+    let permission_context = &app.context;
 
-        let mut filtered_items: Vec<ListItem> = Vec::new();
+    let rows = if focused {
+        channels::visible_channels(app)
+    } else {
+        channels::channel_rows(app, "")
+    };
 
-        let app_clone = app.clone();
+    let mut list_items: Vec<ListItem> = rows
+        .into_iter()
+        .map(|row| {
+            let (char, color) = channel_style(row.channel);
+            let color = if row.channel.is_archived() {
+                Color::Gray
+            } else {
+                color
+            };
 
-        let filtered_unicode: Vec<&(String, String)> = app_clone
-            .emoji_map
-            .iter()
-            .filter(|(name, _)| name.starts_with(&app.emoji_filter))
-            .collect();
+            let mut spans = vec![Span::raw(format!(
+                "{}{char} {}",
+                "  ".repeat(row.depth),
+                row.channel.name
+            ))];
+            spans.extend(unread_badge(app, &row.channel.id));
+
+            ListItem::new(Line::from(spans))
+                .style(unread_style(color, unread::is_unread(app, &row.channel.id)))
+        })
+        .collect();
+
+    let num_filtered = list_items.len();
+    let selected = if focused {
+        app.selection_index = app.selection_index.min(num_filtered.saturating_sub(1));
+        Some(app.selection_index)
+    } else {
+        layout::current_channel_index(app)
+    };
 
-        let filtered_custom: Vec<&Emoji> = app_clone
-            .custom_emojis
-            .iter()
-            .filter(|e| e.name.starts_with(&app.emoji_filter))
-            .collect();
+    let hidden_items: Vec<ListItem> = app
+        .channels
+        .iter()
+        .flat_map(|c| {
+            if c.channel_type == 4 {
+                let mut items: Vec<&Channel> = Vec::new();
+
+                if let Some(children) = &c.children {
+                    items.extend(children.iter().filter(|child| {
+                        permission_context
+                            .as_ref()
+                            .is_some_and(|context| !child.is_readable(context))
+                    }));
+                }
+                items
+            } else if permission_context
+                .as_ref()
+                .is_some_and(|context| !c.is_readable(context))
+            {
+                vec![c]
+            } else {
+                vec![]
+            }
+        })
+        .map(|c| {
+            let (char, _) = channel_style(c);
 
-        for (name, char) in filtered_unicode.iter() {
-            filtered_items.push(ListItem::new(Line::from(vec![
-                Span::styled(char.clone(), Style::default().fg(Color::White)),
-                Span::raw(" "),
-                Span::styled(
-                    format!(":{name}: (Unicode)"),
-                    Style::default().fg(Color::LightBlue),
+            let color = Color::DarkGray;
+
+            ListItem::new(format!(" {char} {}", c.name)).style(Style::default().fg(color))
+        })
+        .collect();
+
+    list_items.extend(hidden_items);
+
+    let title = format!(
+        "Channels for Guild: {guild_id} | Channels found: {} | Actual index: {}",
+        num_filtered.saturating_sub(1),
+        app.selection_index
+    );
+
+    let list = List::new(list_items)
+        .block(pane_block(title, focused))
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
+
+    let mut state = ListState::default().with_selected(selected);
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_forum(f: &mut Frame, app: &mut App, area: Rect, forum_id: &str) {
+    let forum = mentions::find_channel(&app.channels, forum_id);
+    let tags = forum
+        .map(|f| f.available_tags.as_slice())
+        .unwrap_or_default();
+
+    let list_items: Vec<ListItem> = channels::forum_posts(app, forum_id)
+        .into_iter()
+        .map(|post| {
+            let mut spans = vec![Span::styled(
+                post.name.clone(),
+                Style::default().fg(if post.is_archived() {
+                    Color::Gray
+                } else {
+                    Color::White
+                }),
+            )];
+
+            for tag in tags.iter().filter(|t| post.applied_tags.contains(&t.id)) {
+                let label = match &tag.emoji_name {
+                    Some(emoji) => format!("{emoji} {}", tag.name),
+                    None => tag.name.clone(),
+                };
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    format!("[{label}]"),
+                    Style::default().fg(Color::LightYellow),
+                ));
+            }
+
+            spans.extend(unread_badge(app, &post.id));
+
+            let replies = post.message_count.unwrap_or_default();
+            spans.push(Span::styled(
+                format!(
+                    "  {replies} {}",
+                    if replies == 1 { "reply" } else { "replies" }
                 ),
-            ])));
+                Style::default().fg(Color::DarkGray),
+            ));
+            if post.is_archived() {
+                spans.push(Span::styled(
+                    " (archived)",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    app.selection_index = app.selection_index.min(list_items.len().saturating_sub(1));
+
+    let title = format!(
+        "Forum: {} | Posts found: {}",
+        forum.map_or(forum_id, |f| f.name.as_str()),
+        list_items.len()
+    );
+
+    let list = List::new(list_items)
+        .block(
+            Block::default()
+                .title(Span::styled(title, Style::default().fg(Color::Yellow)))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double),
+        )
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
+
+    let mut state = ListState::default().with_selected(Some(app.selection_index));
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_chat(f: &mut Frame, app: &mut App, area: Rect, focused: bool) {
+    let area = if app.show_members && area.width >= app.panes.member_width + MIN_CHAT_WIDTH {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(app.panes.member_width),
+            ])
+            .split(area);
+        draw_members(f, app, columns[1]);
+        columns[0]
+    } else {
+        area
+    };
+
+    app.terminal_height = area.height as usize;
+    app.terminal_width = area.width as usize;
+
+    let max_height = app.terminal_height.saturating_sub(2);
+    let max_width = app.terminal_width.saturating_sub(2) as u16;
+
+    if max_width == 0 {
+        return;
+    }
+
+    let safe_max_width = max_width.saturating_sub(4) as usize;
+    let selected_index = app.message_selection.as_ref().map(|s| s.index);
+    let selected_attachment = app
+        .message_selection
+        .as_ref()
+        .map(|s| s.attachment.unwrap_or_default());
+    let rendered: Vec<Vec<Line>> = app
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(index, m)| {
+            let selected = selected_attachment.filter(|_| selected_index == Some(index));
+            message_lines(m, Mentions::new(app, m), selected, safe_max_width)
+        })
+        .collect();
+    let heights: Vec<usize> = rendered.iter().map(Vec::len).collect();
+
+    let total_height: usize = heights.iter().sum();
+    app.chat_max_scroll = total_height.saturating_sub(max_height);
+
+    // Keep the selected message inside the window
+    if let Some(index) = selected_index.filter(|i| *i < heights.len()) {
+        let below: usize = heights[..index].iter().sum();
+        let height = heights[index];
+        if below < app.chat_scroll {
+            app.chat_scroll = below;
+        } else if below + height > app.chat_scroll + max_height {
+            app.chat_scroll = (below + height).saturating_sub(max_height);
         }
+    }
+    app.chat_scroll = app.chat_scroll.min(app.chat_max_scroll);
 
-        for emoji in filtered_custom.iter() {
-            filtered_items.push(ListItem::new(Line::from(vec![Span::styled(
-                format!("  :{}: (Guild)", emoji.name),
-                Style::default().fg(Color::LightBlue),
-            )])));
+    let mut messages_to_render: Vec<(usize, Vec<Line>)> = Vec::new();
+    let mut current_height = 0;
+
+    for (index, lines) in rendered.into_iter().enumerate() {
+        current_height += lines.len();
+        messages_to_render.push((index, lines));
+
+        if current_height >= max_height + app.chat_scroll {
+            break;
         }
+    }
 
-        if !filtered_items.is_empty() {
-            app.selection_index = app
-                .selection_index
-                .min(filtered_items.len().saturating_sub(1));
+    messages_to_render.reverse();
 
-            let emoji_list = List::new(filtered_items)
-                .block(
-                    Block::default()
-                        .title(Span::styled(
-                            "Select An Emoji",
-                            Style::default().fg(Color::Yellow),
-                        ))
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Double),
-                )
-                .highlight_style(Style::default().reversed())
-                .highlight_symbol(">> ");
+    let mut final_content: Vec<Line> = Vec::new();
 
-            let mut state = ListState::default().with_selected(Some(app.selection_index));
-            f.render_stateful_widget(emoji_list, popup_rect, &mut state);
+    for (index, lines) in messages_to_render.into_iter() {
+        let highlight = if selected_index == Some(index) {
+            Some(Style::default().bg(Color::DarkGray))
+        } else if mentions::mentions_current_user(app, &app.messages[index]) {
+            Some(Style::default().bg(MENTION_BACKGROUND))
         } else {
-            app.selection_index = 0;
+            None
+        };
+
+        match highlight {
+            Some(style) => final_content.extend(lines.into_iter().map(|l| l.style(style))),
+            None => final_content.extend(lines),
         }
     }
 
-    f.render_widget(
-        Paragraph::new(app.input.as_str()).block(
+    let scroll_offset = current_height.saturating_sub(max_height + app.chat_scroll);
+
+    let mut title = "Rivet Client - Chatting".to_string();
+    if app.loading_older_messages {
+        let spinner = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+        title.push_str(&format!(
+            " | Loading older messages {}",
+            spinner[app.tick_count % spinner.len()]
+        ));
+    } else if app.history_exhausted && app.chat_scroll > 0 && app.chat_scroll >= app.chat_max_scroll
+    {
+        title.push_str(" | Beginning of the conversation");
+    }
+    if app.chat_scroll > 0 {
+        title.push_str(&format!(" | Scrolled up {} lines", app.chat_scroll));
+    }
+
+    let paragraph = Paragraph::new(final_content)
+        .block(pane_block(title, focused))
+        .scroll((scroll_offset as u16, 0));
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
+/// A role color, 0 meaning the role has none.
+fn role_color(rgb: u32) -> Color {
+    match rgb {
+        0 => Color::White,
+        rgb => Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8),
+    }
+}
+
+/// Who can see the chat, by group, with a presence dot when presences are known.
+fn draw_members(f: &mut Frame, app: &App, area: Rect) {
+    let groups = members::member_groups(app);
+    let total: usize = groups.iter().map(|g| g.members.len()).sum();
+
+    let mut items: Vec<ListItem> = Vec::new();
+    let mut selected_row = None;
+    let mut member_index = 0;
+    for group in groups {
+        items.push(ListItem::new(Span::styled(
+            format!("{} — {}", group.name.to_uppercase(), group.members.len()),
+            Style::default().fg(Color::DarkGray).bold(),
+        )));
+
+        for member in group.members {
+            if app.member_selection == Some(member_index) {
+                selected_row = Some(items.len());
+            }
+            member_index += 1;
+
+            let mut spans = Vec::new();
+            if let Some(status) = &member.status {
+                let color = match status.as_str() {
+                    "online" => Color::Green,
+                    "idle" => Color::Yellow,
+                    "dnd" => Color::Red,
+                    _ => Color::DarkGray,
+                };
+                spans.push(Span::styled("● ", Style::default().fg(color)));
+            }
+
+            let style = if member.status.as_deref() == Some("offline") {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default().fg(role_color(member.color))
+            };
+            spans.push(Span::styled(member.name, style));
+
+            items.push(ListItem::new(Line::from(spans)));
+        }
+    }
+
+    let list = List::new(items)
+        .block(pane_block(
+            format!("Members | {total}"),
+            app.member_selection.is_some(),
+        ))
+        .highlight_style(Style::default().reversed());
+    let mut state = ListState::default().with_selected(selected_row);
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Lists the `@`/`#` completion candidates above the input box.
+fn draw_mention_popup(f: &mut Frame, app: &mut App, input_area: Rect) {
+    let popup_height = 8;
+    let popup_rect = Rect {
+        x: input_area.x + 1,
+        y: input_area.y.saturating_sub(popup_height + 1),
+        width: input_area.width.saturating_sub(2),
+        height: popup_height,
+    };
+
+    let candidates = completion::candidates(app);
+    if candidates.is_empty() {
+        app.selection_index = 0;
+        return;
+    }
+    app.selection_index = app.selection_index.min(candidates.len() - 1);
+
+    let items: Vec<ListItem> = candidates
+        .into_iter()
+        .map(|c| {
+            ListItem::new(Line::from(vec![
+                Span::styled(c.display, Style::default().fg(Color::White)),
+                Span::styled(
+                    format!(" ({})", c.kind),
+                    Style::default().fg(Color::LightBlue),
+                ),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
             Block::default()
-                .title(Span::styled(
-                    format!("Input: {}", app.status_message),
-                    Style::default().fg(Color::Yellow),
-                ))
+                .title(Span::styled("Mention", Style::default().fg(Color::Yellow)))
                 .borders(Borders::ALL)
                 .border_type(BorderType::Double),
-        ),
-        chunks[1],
-    );
+        )
+        .highlight_style(Style::default().reversed())
+        .highlight_symbol(">> ");
 
-    let input_before_cursor = &app.input[..app.cursor_position];
-    let cursor_lines = input_before_cursor.split('\n').count();
-    let cursor_y = chunks[1].y + cursor_lines as u16;
+    let mut state = ListState::default().with_selected(Some(app.selection_index));
+    f.render_widget(Clear, popup_rect);
+    f.render_stateful_widget(list, popup_rect, &mut state);
+}
 
-    let current_line_start = input_before_cursor.rfind('\n').map(|i| i + 1).unwrap_or(0);
-    let cursor_x =
-        chunks[1].x + 1 + UnicodeWidthStr::width(&input_before_cursor[current_line_start..]) as u16;
+/// Text shown for a user message, with a placeholder when it only carries non-text parts.
+fn message_content(message: &Message) -> String {
+    let mut lines: Vec<String> = message
+        .content
+        .iter()
+        .filter(|c| !c.is_empty())
+        .cloned()
+        .collect();
+    lines.extend(message.attachments.iter().map(attachment_line));
+
+    if lines.is_empty() && shown_embeds(message).next().is_some() {
+        // The embeds rendered under the message stand in for its content
+        String::new()
+    } else if lines.is_empty() {
+        "(*non-text*)".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
 
-    f.set_cursor_position((cursor_x, cursor_y));
+/// Embeds drawn under a message, leaving out previews of links already in its text.
+fn shown_embeds(message: &Message) -> impl Iterator<Item = &Embed> {
+    let content = message.content.as_deref().unwrap_or_default();
+    message
+        .embeds
+        .iter()
+        .filter(move |e| e.has_text() || e.preview_url().is_some_and(|url| !content.contains(url)))
+}
+
+fn attachment_line(attachment: &Attachment) -> String {
+    format!("📎 {} ({})", attachment.filename, attachment.display_size())
+}
+
+/// The dimmed "↳ replying to" line shown above replies.
+fn reply_context(message: &Message) -> Option<String> {
+    if !message.is_reply() {
+        return None;
+    }
+
+    let Some(referenced) = &message.referenced_message else {
+        return Some("↳ replying to a deleted message".to_string());
+    };
+
+    let mut content = message_content(referenced);
+    if content.is_empty() {
+        content = referenced
+            .embeds
+            .iter()
+            .find_map(|e| e.title.clone().or(e.description.clone()))
+            .unwrap_or_default();
+    }
+    let first_line = content.lines().next().unwrap_or("");
+    let mut snippet: String = first_line.chars().take(REPLY_SNIPPET_LENGTH).collect();
+    if snippet.len() < content.len() {
+        snippet.push('…');
+    }
+
+    Some(format!(
+        "↳ replying to @{}: {snippet}",
+        referenced.author.username
+    ))
+}
+
+/// Builds the rows of a message exactly as they are drawn, wrapped to `max_width` columns.
+///
+/// `selected_attachment` is set when the message is selected, which also reveals spoilers.
+pub fn message_lines(
+    message: &Message,
+    mentions: Mentions,
+    selected_attachment: Option<usize>,
+    max_width: usize,
+) -> Vec<Line<'static>> {
+    let options = RenderOptions {
+        reveal_spoilers: selected_attachment.is_some(),
+        mentions,
+    };
+    let mut lines = Vec::new();
+    let dimmed = Style::default().fg(Color::DarkGray);
+
+    if let Some(context) = reply_context(message) {
+        lines.extend(markdown::wrap_line(
+            Line::from(Span::styled(context, dimmed.italic())),
+            &[],
+            max_width,
+        ));
+    }
+
+    let (date, time) = message.timestamp.split_once('T').unwrap_or(("", ""));
+    let time = time.split('.').next().unwrap_or("");
+    let header = vec![
+        Span::styled("[", Style::default().fg(Color::LightBlue)),
+        Span::styled(date.to_string(), Style::default().fg(Color::LightCyan)),
+        Span::styled(format!(" {time}]"), Style::default().fg(Color::LightBlue)),
+    ];
+
+    if let Some(text) = message.system_text() {
+        let mut spans = header;
+        spans.push(Span::styled(
+            format!(" → {} {text}", message.author.username),
+            Style::default().fg(Color::Magenta).italic(),
+        ));
+        lines.extend(markdown::wrap_line(Line::from(spans), &[], max_width));
+        return lines;
+    }
+
+    let mut body = match message.content.as_deref().filter(|c| !c.is_empty()) {
+        Some(content) => markdown::render(content, Style::default().fg(Color::White), &options),
+        None => Vec::new(),
+    };
+
+    for (i, attachment) in message.attachments.iter().enumerate() {
+        let style = Style::default().fg(Color::LightGreen);
+        body.push(MarkdownLine {
+            line: Line::styled(
+                attachment_line(attachment),
+                if selected_attachment == Some(i) {
+                    style.reversed()
+                } else {
+                    style
+                },
+            ),
+            indent: Vec::new(),
+            code: false,
+        });
+    }
+
+    if body.is_empty() {
+        body.push(MarkdownLine {
+            line: Line::styled(message_content(message), Style::default().fg(Color::White)),
+            indent: Vec::new(),
+            code: false,
+        });
+    }
+
+    if message.edited_timestamp.is_some()
+        && let Some(last) = body.last_mut()
+    {
+        last.line.spans.push(Span::styled(" (edited)", dimmed));
+    }
+
+    let mut header = header;
+    header.push(Span::styled(
+        format!(" {}: ", message.author.username),
+        Style::default().fg(Color::Yellow),
+    ));
+
+    // Code blocks start on their own row like in the official client
+    match body.first_mut() {
+        Some(first) if !first.code => {
+            header.append(&mut first.line.spans);
+            first.line.spans = header;
+        }
+        _ => lines.push(Line::from(header)),
+    }
+
+    lines.extend(markdown::wrap(body, max_width));
+
+    for embed in shown_embeds(message) {
+        lines.extend(embed::embed_lines(embed, &options, max_width));
+    }
+
+    if !message.reactions.is_empty() {
+        lines.extend(markdown::wrap_line(
+            reaction_line(&message.reactions),
+            &[],
+            max_width,
+        ));
+    }
+
+    lines
+}
+
+/// `●` for unread channels, or the number of unread mentions.
+fn badge(unread: bool, mentions: u32) -> Option<Span<'static>> {
+    if mentions > 0 {
+        Some(Span::styled(
+            format!(" {mentions} "),
+            Style::default().fg(Color::White).bg(Color::Red).bold(),
+        ))
+    } else if unread {
+        Some(Span::styled(" ●", Style::default().fg(Color::White)))
+    } else {
+        None
+    }
+}
+
+fn unread_badge(app: &App, channel_id: &str) -> Option<Span<'static>> {
+    badge(
+        unread::is_unread(app, channel_id),
+        unread::mention_count(app, channel_id),
+    )
+}
+
+/// Unread entries stand out in bold.
+fn unread_style(color: Color, unread: bool) -> Style {
+    let style = Style::default().fg(color);
+    if unread { style.bold() } else { style }
+}
+
+/// Icon and color of a DM, by whether it is a group.
+fn dm_style(dm: &DM) -> (char, Color) {
+    let char = match dm.channel_type {
+        1 => '',
+        3 => '',
+        _ => '',
+    };
+
+    let color = match dm.channel_type {
+        1 => Color::LightMagenta,
+        3 => Color::LightBlue,
+        _ => Color::LightRed,
+    };
+
+    (char, color)
+}
+
+/// Icon and color of a channel in the channel list.
+fn channel_style(channel: &Channel) -> (char, Color) {
+    if channel.is_thread() {
+        return ('󰗚', Color::LightBlue);
+    }
+
+    match channel.channel_type {
+        15 => ('', Color::LightYellow),
+        13 => ('󱝉', Color::LightRed),
+        5 => ('', Color::LightGreen),
+        4 => ('', Color::Gray),
+        2 => ('', Color::LightCyan),
+        0 => ('', Color::LightBlue),
+        _ => ('', Color::LightMagenta),
+    }
+}
+
+/// `👍 3  :custom: 1`, with the reactions the current user added highlighted.
+fn reaction_line(reactions: &[Reaction]) -> Line<'static> {
+    let mut spans = Vec::new();
+    for reaction in reactions {
+        let emoji = match (&reaction.emoji.id, &reaction.emoji.name) {
+            (None, Some(glyph)) => glyph.clone(),
+            (_, Some(name)) => format!(":{name}:"),
+            (_, None) => ":deleted:".to_string(),
+        };
+        let style = if reaction.me {
+            Style::default().fg(Color::Black).bg(Color::LightBlue)
+        } else {
+            Style::default().fg(Color::Gray).bg(Color::DarkGray)
+        };
+
+        if !spans.is_empty() {
+            spans.push(Span::raw("  "));
+        }
+        spans.push(Span::styled(format!(" {emoji} {} ", reaction.count), style));
+    }
+
+    Line::from(spans)
+}
+
+/// How many terminal rows a message takes once wrapped to `max_width`.
+pub fn estimate_message_height(app: &App, message: &Message, max_width: usize) -> usize {
+    message_lines(message, Mentions::new(app, message), None, max_width).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(content: &str) -> Message {
+        serde_json::from_value(json!({
+            "id": "2",
+            "channel_id": "1",
+            "author": { "id": "3", "username": "alice" },
+            "content": content,
+            "timestamp": "2024-01-01T12:00:00.000000+00:00",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn short_message_shares_the_header_row() {
+        let app = App::for_tests();
+        assert_eq!(estimate_message_height(&app, &message("hello"), 80), 1);
+    }
+
+    #[test]
+    fn long_message_wraps_to_the_width() {
+        let app = App::for_tests();
+        let long = message(&"word ".repeat(40));
+        // The header takes 29 columns, then 200 of text
+        assert_eq!(estimate_message_height(&app, &long, 80), 3);
+        assert_eq!(estimate_message_height(&app, &long, 40), 6);
+    }
+
+    #[test]
+    fn code_blocks_start_on_their_own_row() {
+        let app = App::for_tests();
+        let code = message("```\nlet a = 1;\nlet b = 2;\n```");
+        assert_eq!(estimate_message_height(&app, &code, 80), 3);
+    }
+
+    #[test]
+    fn replies_attachments_and_reactions_add_rows() {
+        let app = App::for_tests();
+        let mut reply = message("hello");
+        reply.message_type = crate::api::message::REPLY;
+        reply.message_reference = Some(serde_json::from_value(json!({})).unwrap());
+        reply.referenced_message = Some(Box::new(message("earlier")));
+        reply.attachments = vec![
+            serde_json::from_value(json!({
+                "id": "4",
+                "filename": "a.png",
+                "size": 1024,
+                "url": "https://example.com/a.png",
+            }))
+            .unwrap(),
+        ];
+        reply.reactions = vec![
+            serde_json::from_value(json!({
+                "count": 1,
+                "me": false,
+                "emoji": { "id": null, "name": "👍" },
+            }))
+            .unwrap(),
+        ];
+
+        assert_eq!(estimate_message_height(&app, &reply, 80), 4);
+    }
 }
@@ -0,0 +1,97 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::{Command, ExitStatus},
+};
+
+use tokio::sync::MutexGuard;
+
+use crate::{
+    App, AppState, InputMode, signals,
+    ui::{
+        events,
+        undo::{self, EditKind},
+        vim,
+    },
+};
+
+/// `$VISUAL`, else `$EDITOR`, else vi.
+fn editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Runs the editor on the file, the terminal handed over to it until it exits. The UI is
+/// stopped meanwhile, so waiting blocks.
+fn run_editor(editor: &str, path: &Path) -> io::Result<ExitStatus> {
+    let _paused = events::pause_input();
+
+    // The variable may hold arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = signals::leave_terminal().and_then(|_| {
+        tokio::task::block_in_place(|| Command::new(program).args(words).arg(path).status())
+    });
+
+    signals::enter_terminal()?;
+    status
+}
+
+/// Writes the input in the external editor and takes back what it saved.
+pub fn edit_input(state: &mut MutexGuard<'_, App>) {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return;
+    }
+
+    let editor = editor();
+    // Created with a random name and only readable by the user, it is deleted when dropped
+    let file = tempfile::Builder::new()
+        .prefix("vimcord-")
+        .suffix(".md")
+        .tempfile()
+        .and_then(|mut file| {
+            file.write_all(state.input.as_bytes())?;
+            file.flush()?;
+            Ok(file)
+        });
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            state.status_message = format!("Failed to write the input for {editor}: {e}");
+            return;
+        }
+    };
+
+    let result = run_editor(&editor, file.path());
+    state.full_redraw = true;
+    let text = match result {
+        Ok(status) if status.success() => fs::read_to_string(file.path()),
+        Ok(status) => Err(io::Error::other(format!("{editor} exited with {status}"))),
+        Err(e) => Err(e),
+    };
+    drop(file);
+
+    let text = match text {
+        Ok(text) => text,
+        Err(e) => {
+            state.status_message = format!("Input left unchanged: {e}");
+            return;
+        }
+    };
+
+    // Editors end the file with a newline, which is not part of the message
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    undo::record(state, EditKind::Other);
+    state.input = text.to_string();
+    state.cursor_position = state.input.len();
+    undo::commit(state);
+    if state.vim_mode && state.mode == InputMode::Normal {
+        vim::clamp_cursor(state);
+    }
+    state.status_message = format!("Input written in {editor}.");
+}
@@ -0,0 +1,169 @@
+use crate::{
+    api::message::Embed,
+    ui::markdown::{self, RenderOptions},
+};
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+};
+
+/// Widest an embed box gets, wider terminals leave the rest of the row empty.
+const MAX_EMBED_WIDTH: usize = 80;
+
+fn side_bar_color(embed: &Embed) -> Color {
+    match embed.color {
+        Some(color) if color != 0 => Color::Rgb(
+            ((color >> 16) & 0xff) as u8,
+            ((color >> 8) & 0xff) as u8,
+            (color & 0xff) as u8,
+        ),
+        _ => Color::DarkGray,
+    }
+}
+
+/// Renders an embed as a box whose left border takes the embed color, pre-wrapped to fit
+/// `max_width` columns so each returned line is exactly one terminal row.
+///
+/// Embeds without text show their type and URL, and nothing at all when they have neither.
+pub fn embed_lines(embed: &Embed, options: &RenderOptions, max_width: usize) -> Vec<Line<'static>> {
+    // Two columns for each border and one for each padding space
+    let inner_width = max_width.min(MAX_EMBED_WIDTH).saturating_sub(4).max(1);
+    let mut rows: Vec<Line<'static>> = Vec::new();
+    let push_plain = |rows: &mut Vec<Line<'static>>, text: &str, style: Style| {
+        rows.extend(markdown::wrap_line(
+            Line::styled(text.to_string(), style),
+            &[],
+            inner_width,
+        ));
+    };
+    let push_markdown = |rows: &mut Vec<Line<'static>>, text: &str| {
+        rows.extend(markdown::wrap(
+            markdown::render(text, Style::default().fg(Color::White), options),
+            inner_width,
+        ));
+    };
+
+    if !embed.has_text() {
+        let Some(url) = embed.preview_url() else {
+            return Vec::new();
+        };
+        let text = match &embed.embed_type {
+            Some(kind) => format!("[{kind}] {url}"),
+            None => url.to_string(),
+        };
+        push_plain(&mut rows, &text, Style::default().fg(Color::LightBlue));
+    }
+    if let Some(author) = &embed.author {
+        push_plain(
+            &mut rows,
+            &author.name,
+            Style::default().fg(Color::White).bold(),
+        );
+    }
+    match (&embed.title, &embed.url) {
+        (Some(title), Some(_)) => push_plain(
+            &mut rows,
+            title,
+            Style::default().fg(Color::LightBlue).bold(),
+        ),
+        (Some(title), None) => {
+            push_plain(&mut rows, title, Style::default().fg(Color::White).bold())
+        }
+        (None, Some(url)) if embed.has_text() => {
+            push_plain(&mut rows, url, Style::default().fg(Color::LightBlue))
+        }
+        (None, _) => {}
+    }
+    if let Some(description) = &embed.description {
+        push_markdown(&mut rows, description);
+    }
+    for field in &embed.fields {
+        push_plain(
+            &mut rows,
+            &field.name,
+            Style::default().fg(Color::Gray).bold(),
+        );
+        push_markdown(&mut rows, &field.value);
+    }
+    if let Some(footer) = &embed.footer {
+        push_plain(
+            &mut rows,
+            &footer.text,
+            Style::default().fg(Color::DarkGray),
+        );
+    }
+
+    let content_width = rows.iter().map(Line::width).max().unwrap_or(0);
+    let border = Style::default().fg(Color::DarkGray);
+    let bar = Style::default().fg(side_bar_color(embed));
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled("╭", bar),
+        Span::styled(format!("{}╮", "─".repeat(content_width + 2)), border),
+    ])];
+    for row in rows {
+        let padding = content_width.saturating_sub(row.width());
+        let mut spans = vec![Span::styled("┃ ", bar)];
+        spans.extend(row.spans);
+        spans.push(Span::styled(format!("{} │", " ".repeat(padding)), border));
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::from(vec![
+        Span::styled("╰", bar),
+        Span::styled(format!("{}╯", "─".repeat(content_width + 2)), border),
+    ]));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(value: serde_json::Value) -> Embed {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn rows(embed: &Embed) -> Vec<String> {
+        embed_lines(embed, &RenderOptions::default(), 60)
+            .iter()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn text_embeds_are_boxed() {
+        let rows = rows(&embed(serde_json::json!({
+            "type": "rich",
+            "title": "Title",
+            "description": "Body",
+        })));
+
+        assert_eq!(rows.len(), 4);
+        assert!(rows[1].contains("Title"));
+        assert!(rows[2].contains("Body"));
+    }
+
+    #[test]
+    fn media_previews_show_their_type_and_url() {
+        let gif = rows(&embed(serde_json::json!({
+            "type": "gifv",
+            "url": "https://tenor.com/view/1",
+            "video": {"url": "https://media.tenor.com/1.mp4"},
+        })));
+
+        assert_eq!(gif.len(), 3);
+        assert!(gif[1].contains("[gifv] https://tenor.com/view/1"));
+
+        let image = rows(&embed(serde_json::json!({
+            "type": "image",
+            "thumbnail": {"url": "https://cdn.example/a.png"},
+        })));
+        assert!(image[1].contains("[image] https://cdn.example/a.png"));
+    }
+
+    #[test]
+    fn embeds_with_nothing_to_show_are_skipped() {
+        assert!(rows(&embed(serde_json::json!({"type": "image"}))).is_empty());
+    }
+}
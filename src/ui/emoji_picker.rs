@@ -0,0 +1,216 @@
+use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, AppState, api::message::ReactionEmoji, ui::selection::SELECTION_STATUS,
+};
+
+/// Extra shortcodes for glyphs `emojis.json` only knows under one name.
+const ALIASES: [(&str, &str); 12] = [
+    ("+1", "thumbsup"),
+    ("thumbs_up", "thumbsup"),
+    ("-1", "thumbsdown"),
+    ("thumbs_down", "thumbsdown"),
+    ("lol", "joy"),
+    ("haha", "laughing"),
+    ("party", "tada"),
+    ("ok", "ok_hand"),
+    ("love", "heart"),
+    ("sad", "cry"),
+    ("think", "thinking"),
+    ("hi", "wave"),
+];
+
+/// How much a picking history outweighs how well the name matches.
+const USAGE_WEIGHT: f64 = 4.0;
+
+/// An entry of the emoji picker.
+#[derive(Debug, Clone)]
+pub struct EmojiCandidate {
+    /// Every shortcode of the emoji, the first being the main one
+    pub names: Vec<String>,
+    /// The unicode glyph, `None` for custom emojis
+    pub glyph: Option<String>,
+    /// Text inserted in the input when picked
+    pub insert: String,
+    /// Key of the emoji in the usage history
+    pub usage_key: String,
+}
+
+impl EmojiCandidate {
+    pub fn reaction_emoji(&self) -> ReactionEmoji {
+        match &self.glyph {
+            Some(glyph) => ReactionEmoji {
+                id: None,
+                name: Some(glyph.clone()),
+            },
+            None => ReactionEmoji {
+                id: Some(self.usage_key.clone()),
+                name: Some(self.names[0].clone()),
+            },
+        }
+    }
+}
+
+/// Groups `App.emoji_map` by glyph so each emoji lists all of its shortcodes.
+fn unicode_candidates(state: &App) -> Vec<EmojiCandidate> {
+    let mut candidates: Vec<EmojiCandidate> = Vec::new();
+
+    for (name, glyph) in &state.emoji_map {
+        match candidates
+            .iter_mut()
+            .find(|c| c.glyph.as_ref() == Some(glyph))
+        {
+            Some(candidate) => candidate.names.push(name.clone()),
+            None => candidates.push(EmojiCandidate {
+                names: vec![name.clone()],
+                glyph: Some(glyph.clone()),
+                insert: glyph.clone(),
+                usage_key: glyph.clone(),
+            }),
+        }
+    }
+
+    for (alias, name) in ALIASES {
+        if let Some(candidate) = candidates
+            .iter_mut()
+            .find(|c| c.names.iter().any(|n| n == name) && !c.names.iter().any(|n| n == alias))
+        {
+            candidate.names.push(alias.to_string());
+        }
+    }
+
+    candidates
+}
+
+/// Emojis matching `App.emoji_filter`, best first: fuzzy matches on any shortcode are
+/// boosted by how often and how recently each emoji was picked.
+pub fn candidates(state: &App) -> Vec<EmojiCandidate> {
+    let matcher = SkimMatcherV2::default();
+    let filter = state.emoji_filter.as_str();
+
+    let custom = state.custom_emojis.iter().map(|e| EmojiCandidate {
+        names: vec![e.name.clone()],
+        glyph: None,
+        insert: format!(
+            "<{}:{}:{}>",
+            if e.animated.unwrap_or(false) { "a" } else { "" },
+            e.name,
+            e.id
+        ),
+        usage_key: e.id.clone(),
+    });
+
+    let mut scored: Vec<(f64, EmojiCandidate)> = unicode_candidates(state)
+        .into_iter()
+        .chain(custom)
+        .filter_map(|candidate| {
+            let usage = state.emoji_usage.score(&candidate.usage_key);
+            if filter.is_empty() {
+                return Some((usage, candidate));
+            }
+
+            let best = candidate
+                .names
+                .iter()
+                .filter_map(|name| {
+                    let score = matcher.fuzzy_match(name, filter)?;
+                    // Exact and prefix matches beat scattered ones of the same quality
+                    let bonus = if name == filter {
+                        100
+                    } else if name.starts_with(filter) {
+                        50
+                    } else {
+                        0
+                    };
+                    Some(score + bonus)
+                })
+                .max()?;
+
+            Some((best as f64 + usage * USAGE_WEIGHT, candidate))
+        })
+        .collect();
+
+    // Stable, so unused emojis keep the order of emojis.json when nothing is typed
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Opens the picker to react to a message. The filter is typed into the popup and the
+/// input is left untouched.
+pub fn start_reaction(state: &mut MutexGuard<'_, App>, channel_id: String, message_id: String) {
+    state.reacting_to = Some(message_id);
+    state.emoji_filter.clear();
+    state.emoji_filter_start = None;
+    state.selection_index = 0;
+    state.state = AppState::EmojiSelection(channel_id);
+    state.status_message =
+        "Type to filter emoji. Enter to add or remove the reaction, Esc to cancel.".to_string();
+}
+
+/// Writes the picks made since the last time, every few seconds and on exit.
+pub fn save(state: &mut MutexGuard<'_, App>) {
+    if let Err(e) = state.emoji_usage.flush() {
+        state.status_message = format!("Failed to save emoji usage: {e}");
+    }
+}
+
+/// Closes the reaction picker, going back to the message selection.
+pub fn stop_reaction(state: &mut MutexGuard<'_, App>) {
+    if let AppState::EmojiSelection(channel_id) = state.state.clone() {
+        state.state = AppState::Chatting(channel_id);
+    }
+    state.reacting_to = None;
+    state.emoji_filter.clear();
+    state.selection_index = 0;
+    state.status_message = SELECTION_STATUS.to_string();
+}
+
+/// Reacts with `emoji`, or takes the reaction back if the current user already added it.
+///
+/// The displayed counts are updated by the gateway events that follow.
+pub fn react(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    emoji: &EmojiCandidate,
+) {
+    let (Some(message_id), AppState::EmojiSelection(channel_id)) =
+        (state.reacting_to.clone(), state.state.clone())
+    else {
+        return;
+    };
+
+    let reaction = emoji.reaction_emoji();
+    let remove = state
+        .messages
+        .iter()
+        .find(|m| m.id == message_id)
+        .is_some_and(|m| {
+            m.reactions
+                .iter()
+                .any(|r| r.me && r.emoji.same_as(&reaction))
+        });
+    stop_reaction(state);
+    state.emoji_usage.record(&emoji.usage_key);
+
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        let result = if remove {
+            api_client_clone
+                .remove_reaction(&channel_id, &message_id, &reaction)
+                .await
+        } else {
+            api_client_clone
+                .add_reaction(&channel_id, &message_id, &reaction)
+                .await
+        };
+
+        if let Err(e) = result {
+            tx_clone
+                .send(AppAction::ApiFailure("Failed to react".to_string(), e))
+                .await
+                .ok();
+        }
+    });
+}
@@ -1,4 +1,4 @@
-use std::io;
+use std::{io, path::PathBuf, sync::Mutex};
 
 use crossterm::event::{self, KeyCode, KeyEventKind};
 use tokio::{
@@ -7,14 +7,28 @@ use tokio::{
 };
 
 use crate::{
-    App, AppAction, AppState, InputMode, KeywordAction, Window,
-    api::{Channel, DM, Emoji, Guild},
-    ui::vim,
+    App, AppAction, AppState, ChatScroll, InputMode, KeywordAction, Window,
+    api::{
+        ApiClient, ApiError, Channel, DM, Message, UploadFile,
+        channel::{GUILD_ANNOUNCEMENT, GUILD_CATEGORY, GUILD_FORUM, GUILD_TEXT},
+        error::{MISSING_ACCESS, MISSING_PERMISSIONS},
+    },
+    ui::{
+        channels, command, completion, draw, editor,
+        emoji_picker::{self, EmojiCandidate},
+        layout::{self, SidebarEntry},
+        login, members,
+        mentions::find_channel,
+        profile, selection, switcher,
+        undo::{self, EditKind},
+        unread, vim,
+    },
 };
 
 /// Helper function to insert a character at the cursor position.
 /// Handles both emoji selection state and normal input state.
 fn insert_char_at_cursor(state: &mut MutexGuard<'_, App>, c: char) {
+    undo::record(state, typing_kind(state, EditKind::Typing));
     let current_state = state.state.clone();
     match current_state {
         AppState::EmojiSelection(channel_id) => {
@@ -59,8 +73,43 @@ fn insert_char_at_cursor(state: &mut MutexGuard<'_, App>, c: char) {
             state.cursor_position += c.len_utf8();
         }
     }
+
+    // Without vim, typing is undone a word at a time
+    if !state.vim_mode && c.is_whitespace() {
+        undo::commit(state);
+    }
+}
+
+/// Edits in a vim insert session belong to it, others are grouped by `kind`.
+fn typing_kind(state: &App, kind: EditKind) -> EditKind {
+    if state.vim_mode && state.mode == InputMode::Insert {
+        EditKind::Insert
+    } else {
+        kind
+    }
+}
+
+/// Held while another program reads the terminal, the input reader leaving keys to it.
+static INPUT_PAUSE: Mutex<()> = Mutex::new(());
+
+/// Stops reading the terminal until the guard is dropped.
+pub fn pause_input() -> std::sync::MutexGuard<'static, ()> {
+    INPUT_PAUSE.lock().unwrap_or_else(|e| e.into_inner())
 }
 
+/// A pending terminal event, unless the input is paused.
+fn read_event() -> io::Result<Option<event::Event>> {
+    let Ok(_reading) = INPUT_PAUSE.try_lock() else {
+        return Ok(None);
+    };
+    if event::poll(Duration::from_millis(0))? {
+        event::read().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[allow(clippy::collapsible_match)]
 pub async fn handle_input_events(
     tx: Sender<AppAction>,
     mut rx_shutdown: tokio::sync::broadcast::Receiver<()>,
@@ -72,12 +121,16 @@ pub async fn handle_input_events(
             }
 
             _ = time::sleep(Duration::from_millis(10)) => {
-                if event::poll(Duration::from_millis(0))? {
-                    match event::read()? {
+                if let Some(event) = read_event()? {
+                    match event {
                         event::Event::Key(key) => {
                             if key.kind == KeyEventKind::Press {
                                 if key.code == KeyCode::Char('c') && key.modifiers.contains(event::KeyModifiers::CONTROL) {
                                     tx.send(AppAction::SigInt).await.ok();
+                                } else if let KeyCode::Char(c) = key.code
+                                    && key.modifiers.contains(event::KeyModifiers::CONTROL)
+                                {
+                                    tx.send(AppAction::InputCtrl(c)).await.ok();
                                 } else {
                                     match key.code {
                                         KeyCode::Esc => {
@@ -86,6 +139,9 @@ pub async fn handle_input_events(
                                         KeyCode::Enter => {
                                             tx.send(AppAction::InputSubmit).await.ok();
                                         }
+                                        KeyCode::Tab => {
+                                            tx.send(AppAction::InputTab).await.ok();
+                                        }
                                         KeyCode::Backspace => {
                                             tx.send(AppAction::InputBackspace).await.ok();
                                         }
@@ -95,6 +151,12 @@ pub async fn handle_input_events(
                                         KeyCode::Down => {
                                             tx.send(AppAction::SelectNext).await.ok();
                                         }
+                                        KeyCode::PageUp => {
+                                            tx.send(AppAction::ScrollChat(ChatScroll::PageUp)).await.ok();
+                                        }
+                                        KeyCode::PageDown => {
+                                            tx.send(AppAction::ScrollChat(ChatScroll::PageDown)).await.ok();
+                                        }
                                         KeyCode::Char(c) => {
                                             tx.send(AppAction::InputChar(c)).await.ok();
                                         }
@@ -114,15 +176,428 @@ pub async fn handle_input_events(
     }
 }
 
+/// Ticks between writes of the read state and emoji usage, 5 seconds.
+const SAVE_TICKS: usize = 50;
+
+/// Number of messages requested per page of channel history.
+const MESSAGE_PAGE_SIZE: usize = 100;
+
+/// Switches to the loading screen, fetches the latest messages of a channel and then
+/// opens it as a chat.
+pub fn open_chat(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    channel_id: String,
+    channel_name: String,
+) -> Option<KeywordAction> {
+    unread::save(state);
+    state.input = layout::take_draft(state, &channel_id);
+    state.cursor_position = state.input.len();
+    state.chat_channel = Some(channel_id.clone());
+    members::load(state, tx_action);
+    state.status_message = format!("Loading messages for {channel_name}...");
+
+    // Fetched without the app lock, which rate limit waits would otherwise hold
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        tx_clone
+            .send(AppAction::TransitionToLoading(Window::Chat(
+                channel_id.clone(),
+            )))
+            .await
+            .ok();
+        let action = match api_client_clone
+            .get_channel_messages(&channel_id, None, None, None, Some(MESSAGE_PAGE_SIZE))
+            .await
+        {
+            Ok(messages) => AppAction::ApiUpdateMessages(messages),
+            Err(e) => AppAction::ApiFailure(format!("Error loading {channel_name}"), e),
+        };
+        tx_clone.send(action).await.ok();
+        tx_clone.send(AppAction::EndLoading).await.ok();
+    });
+
+    None
+}
+
+/// Opens a guild channel: the post list of a forum, or the chat of anything else.
+pub fn open_channel(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    channel: &Channel,
+) -> Option<KeywordAction> {
+    let (channel_id, channel_name) = (channel.id.clone(), channel.name.clone());
+
+    match channel.channel_type {
+        GUILD_FORUM => {
+            open_forum(state, tx_action, channel_id);
+            None
+        }
+        GUILD_TEXT | GUILD_ANNOUNCEMENT => {
+            load_archived_threads(state, tx_action, channel_id.clone());
+            open_chat(state, tx_action, channel_id, channel_name)
+        }
+        _ => open_chat(state, tx_action, channel_id, channel_name),
+    }
+}
+
+/// Switches to the loading screen and loads the channels, threads, emojis and permissions
+/// of a guild before showing its channel list.
+pub fn open_guild(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    guild_id: String,
+    guild_name: String,
+) {
+    let tx_clone = tx_action.clone();
+
+    state.status_message = format!("Loading channels for {guild_name}...");
+
+    let api_client_clone = state.api_client.clone();
+
+    tokio::spawn(async move {
+        tx_clone
+            .send(AppAction::TransitionToLoading(Window::Channel(
+                guild_id.clone(),
+            )))
+            .await
+            .ok();
+        match api_client_clone.get_guild_channels(&guild_id).await {
+            Ok(channels) => {
+                tx_clone
+                    .send(AppAction::ApiUpdateChannel(channels))
+                    .await
+                    .ok();
+            }
+            Err(e) => {
+                tx_clone
+                    .send(AppAction::ApiFailure(
+                        "Failed to load channels".to_string(),
+                        e,
+                    ))
+                    .await
+                    .ok();
+            }
+        }
+        fetch_guild_details(&api_client_clone, &tx_clone, &guild_id).await;
+
+        tx_clone.send(AppAction::EndLoading).await.ok();
+    });
+}
+
+/// Fetches the threads, custom emojis and permissions of a guild.
+pub async fn fetch_guild_details(
+    api_client: &ApiClient,
+    tx_action: &Sender<AppAction>,
+    guild_id: &str,
+) {
+    match api_client.get_active_threads(guild_id).await {
+        Ok(threads) => {
+            tx_action
+                .send(AppAction::ApiUpdateThreads(threads))
+                .await
+                .ok();
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure(
+                    "Failed to load threads".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    }
+    match api_client.get_guild_emojis(guild_id).await {
+        Ok(emojis) => {
+            tx_action
+                .send(AppAction::ApiUpdateEmojis(emojis))
+                .await
+                .ok();
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure(
+                    "Failed to load custom emojis".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    }
+    match api_client.get_permission_context(guild_id).await {
+        Ok(context) => {
+            tx_action
+                .send(AppAction::ApiUpdateContext(Some(context)))
+                .await
+                .ok();
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure(
+                    "Failed to load permission context".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    }
+}
+
+/// Loads the account the token belongs to: the user, servers and DMs, then the channels and
+/// custom emojis of every server.
+pub async fn load_account(api_client: &ApiClient, tx_action: &Sender<AppAction>) {
+    match api_client.get_current_user().await {
+        Ok(user) => {
+            if let Err(e) = tx_action.send(AppAction::ApiUpdateCurrentUser(user)).await {
+                eprintln!("Failed to send current user update action: {e}");
+            }
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure(
+                    "Failed to load current user".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    }
+
+    let mut guild_ids = Vec::new();
+    match api_client.get_current_user_guilds().await {
+        Ok(guilds) => {
+            guild_ids = guilds.iter().map(|g| g.id.clone()).collect();
+            if let Err(e) = tx_action.send(AppAction::ApiUpdateGuilds(guilds)).await {
+                eprintln!("Failed to send guild update action: {e}");
+            }
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure(
+                    "Failed to load servers".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    }
+
+    match api_client.get_dms().await {
+        Ok(dms) => {
+            if let Err(e) = tx_action.send(AppAction::ApiUpdateDMs(dms)).await {
+                eprintln!("Failed to send DM update action: {e}");
+            }
+        }
+        Err(e) => {
+            tx_action
+                .send(AppAction::ApiFailure("Failed to load DMs".to_string(), e))
+                .await
+                .ok();
+        }
+    }
+
+    tx_action.send(AppAction::EndLoading).await.ok();
+
+    // Custom emojis of every server are usable anywhere, so the picker offers them all,
+    // and channels tell which servers got messages since the last session
+    for guild_id in guild_ids {
+        match api_client.get_guild_channels(&guild_id).await {
+            Ok(channels) => {
+                tx_action
+                    .send(AppAction::ApiUpdateGuildActivity(channels))
+                    .await
+                    .ok();
+            }
+            Err(e) => {
+                tx_action
+                    .send(AppAction::ApiFailure(
+                        "Failed to load channels".to_string(),
+                        e,
+                    ))
+                    .await
+                    .ok();
+            }
+        }
+        match api_client.get_guild_emojis(&guild_id).await {
+            Ok(emojis) => {
+                tx_action
+                    .send(AppAction::ApiUpdateEmojis(emojis))
+                    .await
+                    .ok();
+            }
+            Err(e) => {
+                tx_action
+                    .send(AppAction::ApiFailure(
+                        "Failed to load custom emojis".to_string(),
+                        e,
+                    ))
+                    .await
+                    .ok();
+            }
+        }
+    }
+}
+
+/// Fetches the latest archived threads of a channel in the background, they show up in the
+/// channel list and forum view once loaded.
+fn load_archived_threads(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    channel_id: String,
+) {
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        let action = match api_client_clone
+            .get_archived_threads(&channel_id, channels::ARCHIVED_THREAD_LIMIT)
+            .await
+        {
+            Ok(threads) => AppAction::ApiUpdateThreads(threads),
+            Err(e) => AppAction::ApiFailure("Failed to load archived threads".to_string(), e),
+        };
+        tx_clone.send(action).await.ok();
+    });
+}
+
+/// Shows the posts of a forum channel.
+pub fn open_forum(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    forum_id: String,
+) {
+    load_archived_threads(state, tx_action, forum_id.clone());
+
+    state.input.clear();
+    state.cursor_position = 0;
+    state.selection_index = 0;
+    state.state = AppState::Forum(forum_id);
+    state.status_message =
+        "Select a post. Type to filter, Enter to open & Esc to return to channels".to_string();
+}
+
+/// Fetches the page of history preceding the oldest loaded message, unless a request is
+/// already in flight or the start of the channel has been reached.
+fn load_older_messages(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    channel_id: String,
+) {
+    if state.loading_older_messages || state.history_exhausted {
+        return;
+    }
+    let Some(oldest) = state.messages.last() else {
+        return;
+    };
+
+    let before = oldest.id.clone();
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    state.loading_older_messages = true;
+
+    tokio::spawn(async move {
+        let result = api_client_clone
+            .get_channel_messages(
+                &channel_id,
+                None,
+                Some(before),
+                None,
+                Some(MESSAGE_PAGE_SIZE),
+            )
+            .await;
+        tx_clone
+            .send(AppAction::ApiUpdateOlderMessages(channel_id, result))
+            .await
+            .ok();
+    });
+}
+
+pub fn scroll_chat(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    scroll: ChatScroll,
+) {
+    let AppState::Chatting(channel_id) = state.state.clone() else {
+        return;
+    };
+
+    let page = state.terminal_height.saturating_sub(2).max(1);
+    state.chat_scroll = match scroll {
+        ChatScroll::PageUp => state.chat_scroll + page,
+        ChatScroll::HalfPageUp => state.chat_scroll + page / 2,
+        ChatScroll::PageDown => state.chat_scroll.saturating_sub(page),
+        ChatScroll::HalfPageDown => state.chat_scroll.saturating_sub(page / 2),
+        ChatScroll::Top => state.chat_max_scroll,
+        ChatScroll::Bottom => 0,
+    }
+    .min(state.chat_max_scroll);
+
+    if state.chat_scroll >= state.chat_max_scroll
+        && matches!(
+            scroll,
+            ChatScroll::PageUp | ChatScroll::HalfPageUp | ChatScroll::Top
+        )
+    {
+        load_older_messages(state, tx_action, channel_id);
+    }
+}
+
+/// Shows an API failure in the status bar, explaining the errors the user can act on.
+fn report_api_error(state: &mut MutexGuard<'_, App>, context: &str, error: &ApiError) {
+    if let ApiError::Unauthorized = error {
+        login::prompt(state);
+        return;
+    }
+
+    state.status_message = match (error, error.code()) {
+        (_, Some(MISSING_ACCESS)) => format!("{context}: missing access to this channel."),
+        (_, Some(MISSING_PERMISSIONS)) => {
+            format!("{context}: you don't have permission to do that.")
+        }
+        (ApiError::RateLimited { retry_after }, _) => {
+            format!("{context}: rate limited, try again in {retry_after:.1}s.")
+        }
+        (ApiError::Network(_), _) => format!("{context}: network error, check your connection."),
+        _ => format!("{context}. {error}"),
+    };
+}
+
+/// Sends a desktop notification for a message received outside of the active chat.
+fn notify_message(state: &MutexGuard<'_, App>, message: &Message) {
+    let is_self = state
+        .current_user
+        .as_ref()
+        .is_some_and(|u| u.id == message.author.id);
+
+    if is_self {
+        return;
+    }
+
+    let content = if state.discreet_notifs {
+        "Sent you a DM".to_string()
+    } else {
+        message
+            .content
+            .clone()
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| "Sent an attachment".to_string())
+    };
+
+    let _ = notify_rust::Notification::new()
+        .summary(&message.author.username)
+        .body(&content)
+        .appname("vimcord")
+        .show();
+}
+
 async fn input_submit(
     state: &mut MutexGuard<'_, App>,
     tx_action: &Sender<AppAction>,
-    filtered_unicode: Vec<&(String, String)>,
-    filtered_custom: Vec<&Emoji>,
-    total_filtered_emojis: usize,
+    emoji_candidates: &[EmojiCandidate],
 ) -> Option<KeywordAction> {
     match &state.clone().state {
-        AppState::Loading(_) => {}
+        AppState::Loading(_) | AppState::EnteringToken => {}
         AppState::Home => match state.selection_index {
             0 => {
                 tx_action.send(AppAction::TransitionToGuilds).await.ok();
@@ -155,203 +630,73 @@ async fn input_submit(
                 selected_dm.recipients[0].username.clone()
             };
 
-            state.input = String::new();
-            state.cursor_position = 0;
-            state.status_message = format!("Loading messages for {selected_dm_name}...");
-
-            tx_action
-                .send(AppAction::TransitionToChat(dm_id_clone))
-                .await
-                .ok();
+            return open_chat(state, tx_action, dm_id_clone, selected_dm_name);
         }
         AppState::SelectingGuild => {
             let filter_text = state.input.to_lowercase();
-            let guilds: Vec<&Guild> = state
-                .guilds
-                .iter()
-                .filter(|g| g.name.to_lowercase().contains(&filter_text))
-                .collect();
-
-            if guilds.is_empty() {
-                return Some(KeywordAction::Continue);
-            }
-
-            let selected_guild = &guilds[state.selection_index];
-            let guild_id_clone = selected_guild.id.clone();
-            let selected_guild_name = selected_guild.name.clone();
-
-            let tx_clone = tx_action.clone();
-
-            state.status_message = format!("Loading channels for {selected_guild_name}...");
-
-            let api_client_clone = state.api_client.clone();
+            let selected = match layout::sidebar_entries(state, &filter_text)
+                .get(state.selection_index)
+            {
+                Some(SidebarEntry::DirectMessages) => None,
+                Some(SidebarEntry::Guild(guild)) => Some((guild.id.clone(), guild.name.clone())),
+                None => return Some(KeywordAction::Continue),
+            };
 
-            tokio::spawn(async move {
-                tx_clone
-                    .send(AppAction::TransitionToLoading(Window::Channel(
-                        guild_id_clone.clone(),
-                    )))
-                    .await
-                    .ok();
-                match api_client_clone.get_guild_channels(&guild_id_clone).await {
-                    Ok(channels) => {
-                        tx_clone
-                            .send(AppAction::ApiUpdateChannel(channels))
-                            .await
-                            .ok();
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to load channels: {e}");
-                    }
-                }
-                match api_client_clone.get_guild_emojis(&guild_id_clone).await {
-                    Ok(emojis) => {
-                        tx_clone.send(AppAction::ApiUpdateEmojis(emojis)).await.ok();
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to load custom emojis: {e}");
-                    }
+            match selected {
+                Some((guild_id, guild_name)) => open_guild(state, tx_action, guild_id, guild_name),
+                None => {
+                    tx_action.send(AppAction::TransitionToDM).await.ok();
                 }
-                match api_client_clone
-                    .get_permission_context(&guild_id_clone)
-                    .await
-                {
-                    Ok(context) => {
-                        tx_clone
-                            .send(AppAction::ApiUpdateContext(Some(context)))
-                            .await
-                            .ok();
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to load permission context: {e}");
-                    }
-                }
-
-                tx_clone.send(AppAction::EndLoading).await.ok();
-            });
+            }
         }
         AppState::SelectingChannel(_) => {
-            let permission_context = &state.context;
-            let mut text_channels: Vec<&Channel> = Vec::new();
-
-            let filter_text = state.input.to_lowercase();
-            state
-                .channels
-                .iter()
-                .filter(|c| {
-                    let mut readable = false;
-                    if let Some(context) = &permission_context {
-                        readable = c.is_readable(context)
-                    }
-                    readable && c.name.to_lowercase().contains(&filter_text)
-                })
-                .for_each(|c| {
-                    if let Some(children) = &c.children {
-                        text_channels.push(c);
-
-                        children
-                            .iter()
-                            .filter(|c| {
-                                let mut readable = false;
-                                if let Some(context) = &permission_context {
-                                    readable = c.is_readable(context)
-                                }
-                                readable && c.name.to_lowercase().contains(&filter_text)
-                            })
-                            .for_each(|c| {
-                                text_channels.push(c);
-                            });
-                    } else {
-                        text_channels.push(c);
-                    }
-                });
-
-            if text_channels.is_empty()
-                || text_channels.len() <= state.selection_index
-                || text_channels[state.selection_index].channel_type == 4
-            {
+            let Some(row) = channels::visible_channels(state)
+                .get(state.selection_index)
+                .copied()
+            else {
+                return Some(KeywordAction::Continue);
+            };
+            if row.channel.channel_type == GUILD_CATEGORY {
                 return Some(KeywordAction::Continue);
             }
 
-            let channel_info = {
-                let selected_channel = &text_channels[state.selection_index];
-                (selected_channel.id.clone(), selected_channel.name.clone())
+            let channel = row.channel.clone();
+            return open_channel(state, tx_action, &channel);
+        }
+        AppState::Forum(forum_id) => {
+            let Some(post) = channels::forum_posts(state, forum_id)
+                .get(state.selection_index)
+                .map(|p| (p.id.clone(), p.name.clone()))
+            else {
+                return Some(KeywordAction::Continue);
             };
-            let (channel_id_clone, selected_channel_name) = channel_info;
 
-            tx_action
-                .send(AppAction::TransitionToLoading(Window::Chat(
-                    channel_id_clone.clone(),
-                )))
-                .await
-                .ok();
-
-            state.input = String::new();
-            state.cursor_position = 0;
-            state.status_message = format!("Loading messages for {selected_channel_name}...");
-
-            match state
-                .api_client
-                .get_channel_messages(&channel_id_clone, None, None, None, Some(100))
-                .await
-            {
-                Ok(messages) => {
-                    if let Err(e) = tx_action.send(AppAction::ApiUpdateMessages(messages)).await {
-                        eprintln!("Failed to send message update action: {e}");
-                        return None;
-                    }
-                }
-                Err(e) => {
-                    state.status_message = format!("Error loading chat: {e}");
-                }
+            return open_chat(state, tx_action, post.0, post.1);
+        }
+        AppState::MentionSelection(_) => completion::accept(state),
+        AppState::EmojiSelection(_) if state.reacting_to.is_some() => {
+            match emoji_candidates.get(state.selection_index) {
+                Some(emoji) => emoji_picker::react(state, tx_action, emoji),
+                None => emoji_picker::stop_reaction(state),
             }
-
-            tx_action.send(AppAction::EndLoading).await.ok();
         }
         AppState::EmojiSelection(channel_id) => {
             let start_pos = state.emoji_filter_start?;
             let end_pos = start_pos + ':'.len_utf8() + state.emoji_filter.len();
 
-            if state.selection_index < filtered_unicode.len() {
-                let (_, char) = filtered_unicode[state.selection_index];
-
-                if state.input.is_char_boundary(start_pos) && state.input.is_char_boundary(end_pos)
-                {
-                    state.input.drain(start_pos..end_pos);
-
-                    state.input.insert_str(start_pos, char);
-                    let mut pos = start_pos + char.len();
-                    state.input.insert(pos, ' ');
-                    pos += ' '.len_utf8();
-
-                    state.cursor_position = pos;
-                }
-            } else if state.selection_index < total_filtered_emojis {
-                let custom_index = state.selection_index - filtered_unicode.len();
-                let emoji = filtered_custom[custom_index];
-
-                let emoji_string = format!(
-                    "<{}:{}:{}>",
-                    if emoji.animated.unwrap_or(false) {
-                        "a"
-                    } else {
-                        ""
-                    },
-                    emoji.name,
-                    emoji.id
-                );
-
-                if state.input.is_char_boundary(start_pos) && state.input.is_char_boundary(end_pos)
-                {
-                    state.input.drain(start_pos..end_pos);
+            if let Some(emoji) = emoji_candidates.get(state.selection_index)
+                && state.input.is_char_boundary(start_pos)
+                && state.input.is_char_boundary(end_pos)
+            {
+                state.input.drain(start_pos..end_pos);
 
-                    state.input.insert_str(start_pos, &emoji_string);
-                    let mut pos = start_pos + emoji_string.len();
-                    state.input.insert(pos, ' ');
-                    pos += ' '.len_utf8();
+                state.input.insert_str(start_pos, &emoji.insert);
+                let mut pos = start_pos + emoji.insert.len();
+                state.input.insert(pos, ' ');
+                pos += ' '.len_utf8();
 
-                    state.cursor_position = pos;
-                }
+                state.cursor_position = pos;
+                state.emoji_usage.record(&emoji.usage_key);
             }
 
             state.state = AppState::Chatting(channel_id.clone());
@@ -362,6 +707,7 @@ async fn input_submit(
                 "Chatting in channel. Press Enter to send message, Esc to return to channels."
                     .to_string();
         }
+        AppState::Chatting(_) if state.message_selection.is_some() => {}
         AppState::Chatting(_) => {
             let channel_id_clone = if let AppState::Chatting(id) = &state.state {
                 Some(id.clone())
@@ -371,6 +717,9 @@ async fn input_submit(
 
             let content = state.input.drain(..).collect::<String>();
             state.cursor_position = 0;
+            undo::clear(state);
+            let content = completion::expand_mentions(&content, &state.input_mentions);
+            state.input_mentions.clear();
 
             let message_data = if content.is_empty() || channel_id_clone.is_none() {
                 None
@@ -378,18 +727,63 @@ async fn input_submit(
                 channel_id_clone.map(|id| (id, content))
             };
 
-            if let Some((channel_id_clone, content)) = message_data {
-                let api_client_clone = state.api_client.clone();
+            if let Some(message_id) = state.starting_thread.take() {
+                state.status_message =
+                    "Chatting in channel. Press Enter to send message, Esc to return to channels."
+                        .to_string();
+                let (channel_id, name) = message_data?;
 
+                let api_client_clone = state.api_client.clone();
+                let tx_clone = tx_action.clone();
                 tokio::spawn(async move {
-                    match api_client_clone
-                        .create_message(&channel_id_clone, Some(content), false)
+                    let action = match api_client_clone
+                        .start_thread_from_message(&channel_id, &message_id, name)
                         .await
                     {
-                        Ok(_) => {}
-                        Err(e) => {
-                            eprintln!("API Error: {e}");
-                        }
+                        Ok(thread) => AppAction::ApiThreadCreated(thread),
+                        Err(e) => AppAction::ApiFailure("Failed to start thread".to_string(), e),
+                    };
+                    tx_clone.send(action).await.ok();
+                });
+                return None;
+            }
+
+            let reply_to = state.replying_to.take().map(|m| m.id);
+            let editing_message = state.editing_message.take();
+            if reply_to.is_some() || editing_message.is_some() {
+                state.status_message =
+                    "Chatting in channel. Press Enter to send message, Esc to return to channels."
+                        .to_string();
+            }
+
+            if let Some((channel_id_clone, content)) = message_data {
+                let api_client_clone = state.api_client.clone();
+                let tx_clone = tx_action.clone();
+                tokio::spawn(async move {
+                    let result = match editing_message {
+                        Some(message_id) => api_client_clone
+                            .edit_message(&channel_id_clone, &message_id, content)
+                            .await
+                            .map(|_| ()),
+                        None => api_client_clone
+                            .create_message(
+                                &channel_id_clone,
+                                Some(content),
+                                false,
+                                reply_to.as_deref(),
+                            )
+                            .await
+                            .map(|_| ()),
+                    };
+
+                    if let Err(e) = result {
+                        tx_clone
+                            .send(AppAction::ApiFailure(
+                                "Failed to send message".to_string(),
+                                e,
+                            ))
+                            .await
+                            .ok();
                     }
                 });
             }
@@ -398,6 +792,49 @@ async fn input_submit(
     None
 }
 
+/// Resolves a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(relative), Some(home)) => home.join(relative),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Sends the file at `path` to a channel, with `text` as the message.
+pub fn upload_file(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    channel_id: String,
+    path: PathBuf,
+    text: Option<String>,
+    reply_to: Option<String>,
+) {
+    state.status_message = format!("Uploading {}...", path.display());
+
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        let result = match UploadFile::read(&path).await {
+            Ok(file) => api_client_clone
+                .create_message_with_files(&channel_id, text, &[file], reply_to.as_deref())
+                .await
+                .map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            tx_clone
+                .send(AppAction::ApiFailure(
+                    "Failed to send message".to_string(),
+                    e,
+                ))
+                .await
+                .ok();
+        }
+    });
+}
+
+#[allow(clippy::collapsible_match)]
 async fn move_selection(state: &mut MutexGuard<'_, App>, n: i32, total_filtered_emojis: usize) {
     match state.state {
         AppState::Home => {
@@ -426,78 +863,25 @@ async fn move_selection(state: &mut MutexGuard<'_, App>, n: i32, total_filtered_
             }
         }
         AppState::SelectingGuild => {
-            if !state.guilds.is_empty() {
-                if n < 0 {
-                    state.selection_index = if state.selection_index == 0 {
-                        state.guilds.len() - n.unsigned_abs() as usize
-                    } else {
-                        state.selection_index - n.unsigned_abs() as usize
-                    };
-                } else {
-                    state.selection_index =
-                        (state.selection_index + n.unsigned_abs() as usize) % state.guilds.len();
-                }
+            let len = layout::sidebar_entries(state, &state.input.to_lowercase()).len();
+            if len == 0 {
+                return;
             }
-        }
-        AppState::SelectingChannel(_) => {
-            if !state.channels.is_empty() {
-                let filter_text = state.input.to_lowercase();
-                let permission_context = &state.context;
-
-                let should_display_content = |c: &Channel| {
-                    let is_readable = permission_context
-                        .as_ref()
-                        .is_some_and(|context| c.is_readable(context));
-
-                    is_readable
-                        && (filter_text.is_empty() || c.name.to_lowercase().contains(&filter_text))
-                };
 
-                let len: usize = state
-                    .channels
-                    .iter()
-                    .flat_map(|c| {
-                        if c.channel_type == 4 {
-                            let mut list_items_to_render: Vec<&Channel> = Vec::new();
-
-                            let name_matches = filter_text.is_empty()
-                                || c.name.to_lowercase().contains(&filter_text);
-
-                            let child_matches = c.children.as_ref().is_some_and(|children| {
-                                children.iter().any(should_display_content)
-                            });
-
-                            if name_matches || child_matches {
-                                list_items_to_render.push(c);
-
-                                if let Some(children) = &c.children {
-                                    list_items_to_render.extend(
-                                        children
-                                            .iter()
-                                            .filter(|child| should_display_content(child)),
-                                    );
-                                }
-                            }
-                            list_items_to_render
-                        } else if should_display_content(c) {
-                            vec![c]
-                        } else {
-                            vec![]
-                        }
-                    })
-                    .count();
-
-                if n < 0 {
-                    state.selection_index = if state.selection_index == 0 {
-                        len - n.unsigned_abs() as usize
-                    } else {
-                        state.selection_index - n.unsigned_abs() as usize
-                    };
-                } else {
-                    state.selection_index =
-                        (state.selection_index + n.unsigned_abs() as usize) % len;
-                }
+            state.selection_index =
+                (state.selection_index as i64 + i64::from(n)).rem_euclid(len as i64) as usize;
+        }
+        AppState::SelectingChannel(_) | AppState::Forum(_) => {
+            let len = match &state.state {
+                AppState::Forum(forum_id) => channels::forum_posts(state, forum_id).len(),
+                _ => channels::visible_channels(state).len(),
+            };
+            if len == 0 {
+                return;
             }
+
+            state.selection_index =
+                (state.selection_index as i64 + i64::from(n)).rem_euclid(len as i64) as usize;
         }
         AppState::EmojiSelection(_) => {
             if total_filtered_emojis > 0 {
@@ -516,47 +900,169 @@ async fn move_selection(state: &mut MutexGuard<'_, App>, n: i32, total_filtered_
     }
 }
 
+/// Moves the selection of the list on screen, like the arrow keys outside of a chat.
+pub async fn move_list_selection(state: &mut MutexGuard<'_, App>, n: i32) {
+    match state.state {
+        AppState::MentionSelection(_) => completion::move_selection(state, n),
+        AppState::EmojiSelection(_) => {
+            let total_filtered_emojis = emoji_picker::candidates(state).len();
+            move_selection(state, n, total_filtered_emojis).await;
+        }
+        _ => move_selection(state, n, 0).await,
+    }
+}
+
 pub async fn handle_keys_events(
     mut state: MutexGuard<'_, App>,
     action: AppAction,
     tx_action: Sender<AppAction>,
 ) -> Option<KeywordAction> {
-    let state_clone = state.clone();
-    let filtered_unicode: Vec<&(String, String)> = state_clone
-        .emoji_map
-        .iter()
-        .filter(|(name, _)| name.starts_with(&state.emoji_filter))
-        .collect();
-
-    let state_clone = state.clone();
-    let filtered_custom: Vec<&Emoji> = state_clone
-        .custom_emojis
-        .iter()
-        .filter(|e| e.name.starts_with(&state.emoji_filter))
-        .collect();
-
-    let total_filtered_emojis = filtered_unicode.len() + filtered_custom.len();
+    let emoji_candidates = match state.state {
+        AppState::EmojiSelection(_) => emoji_picker::candidates(&state),
+        _ => Vec::new(),
+    };
+    let total_filtered_emojis = emoji_candidates.len();
 
     match action {
         AppAction::SigInt => return Some(KeywordAction::Break),
+        AppAction::InputEscape if matches!(state.state, AppState::EnteringToken) => {
+            return Some(KeywordAction::Break);
+        }
+        AppAction::InputChar(c) if matches!(state.state, AppState::EnteringToken) => {
+            login::insert(&mut state, c.encode_utf8(&mut [0; 4]));
+        }
+        AppAction::Paste(text) if matches!(state.state, AppState::EnteringToken) => {
+            login::insert(&mut state, &text);
+        }
+        AppAction::InputBackspace if matches!(state.state, AppState::EnteringToken) => {
+            login::backspace(&mut state);
+        }
+        AppAction::InputSubmit if matches!(state.state, AppState::EnteringToken) => {
+            login::submit(&mut state, &tx_action);
+        }
+        AppAction::InputCtrl(_)
+        | AppAction::InputTab
+        | AppAction::SelectNext
+        | AppAction::SelectPrevious
+        | AppAction::ScrollChat(_)
+            if matches!(state.state, AppState::EnteringToken) => {}
+        AppAction::InputCtrl('k') if state.switcher.is_some() => state.switcher = None,
+        AppAction::InputCtrl('k') => switcher::open(&mut state, &tx_action),
+        AppAction::InputEscape if state.switcher.is_some() => state.switcher = None,
+        AppAction::InputChar(c) if state.switcher.is_some() => {
+            if let Some(switcher) = &mut state.switcher {
+                switcher.query.push(c);
+                switcher.selection = 0;
+            }
+        }
+        AppAction::Paste(text) if state.switcher.is_some() => {
+            if let Some(switcher) = &mut state.switcher {
+                switcher.query.push_str(text.trim());
+                switcher.selection = 0;
+            }
+        }
+        AppAction::InputBackspace if state.switcher.is_some() => {
+            if let Some(switcher) = &mut state.switcher {
+                switcher.query.pop();
+                switcher.selection = 0;
+            }
+        }
+        AppAction::SelectNext | AppAction::InputTab if state.switcher.is_some() => {
+            switcher::move_selection(&mut state, 1);
+        }
+        AppAction::SelectPrevious if state.switcher.is_some() => {
+            switcher::move_selection(&mut state, -1);
+        }
+        AppAction::InputSubmit if state.switcher.is_some() => {
+            return switcher::accept(&mut state, &tx_action);
+        }
+        AppAction::InputEscape if state.mode == InputMode::Command => command::close(&mut state),
+        AppAction::InputChar(c) if state.mode == InputMode::Command => {
+            command::insert(&mut state, c.encode_utf8(&mut [0; 4]));
+        }
+        AppAction::Paste(text) if state.mode == InputMode::Command => {
+            command::insert(&mut state, text.lines().next().unwrap_or_default());
+        }
+        AppAction::InputBackspace if state.mode == InputMode::Command => {
+            command::backspace(&mut state);
+        }
+        AppAction::InputTab if state.mode == InputMode::Command => command::complete(&mut state),
+        AppAction::SelectPrevious if state.mode == InputMode::Command => {
+            command::browse_history(&mut state, -1);
+        }
+        AppAction::SelectNext if state.mode == InputMode::Command => {
+            command::browse_history(&mut state, 1);
+        }
+        AppAction::InputSubmit if state.mode == InputMode::Command => {
+            return command::run(&mut state, &tx_action);
+        }
+        AppAction::InputEscape | AppAction::InputChar('q') if state.profile.is_some() => {
+            profile::close(&mut state);
+        }
+        AppAction::InputChar('j') | AppAction::SelectNext if state.profile.is_some() => {
+            profile::move_action(&mut state, 1);
+        }
+        AppAction::InputChar('k') | AppAction::SelectPrevious if state.profile.is_some() => {
+            profile::move_action(&mut state, -1);
+        }
+        AppAction::InputSubmit if state.profile.is_some() => {
+            return profile::run_action(&mut state, &tx_action);
+        }
+        AppAction::InputChar(_) | AppAction::InputTab if state.profile.is_some() => {}
+        AppAction::InputEscape | AppAction::InputChar('m') if state.member_selection.is_some() => {
+            members::exit_selection(&mut state);
+        }
+        AppAction::InputChar('j') | AppAction::SelectNext if state.member_selection.is_some() => {
+            members::move_selection(&mut state, 1);
+        }
+        AppAction::InputChar('k') | AppAction::SelectPrevious
+            if state.member_selection.is_some() =>
+        {
+            members::move_selection(&mut state, -1);
+        }
+        AppAction::InputSubmit | AppAction::InputChar('p') if state.member_selection.is_some() => {
+            if let Some(member) = members::selected_member(&state) {
+                profile::open(&mut state, &tx_action, member.user);
+            }
+        }
+        AppAction::InputChar(_) | AppAction::InputTab if state.member_selection.is_some() => {}
+        AppAction::InputEscape if state.reacting_to.is_some() => {
+            emoji_picker::stop_reaction(&mut state);
+        }
         AppAction::InputEscape => {
             // In vim mode, Esc switches from Insert to Normal mode and returns early.
             // In non-vim mode (or vim Normal mode), Esc triggers navigation (handled below).
+            if state.vim_mode && (vim::cancel_pending(&mut state) || vim::exit_visual(&mut state)) {
+                return None;
+            }
             if state.vim_mode && state.mode == InputMode::Insert {
-                state.mode = InputMode::Normal;
-                if let Some(c) = state.input[..state.cursor_position].chars().next_back()
-                    && c != '\n'
+                vim::leave_insert(&mut state);
+                return None;
+            }
+            if state.message_selection.is_some() {
+                selection::exit_selection(&mut state);
+                return None;
+            }
+            if state.replying_to.is_some()
+                || state.editing_message.is_some()
+                || state.starting_thread.is_some()
+            {
+                if state.editing_message.take().is_some() || state.starting_thread.take().is_some()
                 {
-                    state.cursor_position -= c.len_utf8();
-                }
-                if !(state.cursor_position == state.input.len() && state.input.ends_with('\n')) {
-                    vim::clamp_cursor(&mut state);
+                    state.input.clear();
+                    state.cursor_position = 0;
                 }
+                state.replying_to = None;
+                state.status_message =
+                    "Chatting in channel. Press Enter to send message, Esc to return to channels."
+                        .to_string();
                 return None;
             }
             // Navigation logic: go back to previous screen or quit
             match &state.state {
-                AppState::Home | AppState::Loading(_) => return Some(KeywordAction::Break),
+                AppState::Home | AppState::Loading(_) | AppState::EnteringToken => {
+                    return Some(KeywordAction::Break);
+                }
                 AppState::SelectingDM => {
                     tx_action.send(AppAction::TransitionToHome).await.ok();
                 }
@@ -566,18 +1072,50 @@ pub async fn handle_keys_events(
                 AppState::SelectingChannel(_) => {
                     tx_action.send(AppAction::TransitionToGuilds).await.ok();
                 }
+                AppState::Forum(forum_id) => {
+                    match find_channel(&state.channels, forum_id).and_then(|c| c.guild_id.clone()) {
+                        Some(guild_id) => tx_action
+                            .send(AppAction::TransitionToChannels(guild_id))
+                            .await
+                            .ok(),
+                        None => tx_action.send(AppAction::TransitionToGuilds).await.ok(),
+                    };
+                }
                 AppState::Chatting(channel_id) => {
-                    let channel = match state.api_client.get_channel(&channel_id.clone()).await {
-                        Ok(c) => c,
-                        Err(e) => {
-                            tx_action.send(AppAction::TransitionToHome).await.ok();
-                            state.status_message = format!("{e}");
-                            return None;
-                        }
+                    let channel_id = channel_id.clone();
+                    layout::stash_draft(&mut state);
+
+                    if state.dms.iter().any(|d| d.id == channel_id) {
+                        tx_action.send(AppAction::TransitionToDM).await.ok();
+                        return None;
+                    }
+
+                    let known = find_channel(&state.channels, &channel_id)
+                        .or_else(|| state.threads.iter().find(|t| t.id == channel_id))
+                        .cloned();
+                    let channel = match known {
+                        Some(c) => c,
+                        None => match state.api_client.get_channel(&channel_id).await {
+                            Ok(c) => c,
+                            Err(e) => {
+                                tx_action.send(AppAction::TransitionToHome).await.ok();
+                                report_api_error(&mut state, "Failed to load channel", &e);
+                                return None;
+                            }
+                        },
                     };
 
+                    let parent_forum = channel
+                        .parent_id
+                        .as_deref()
+                        .and_then(|id| find_channel(&state.channels, id))
+                        .filter(|p| channel.is_thread() && p.channel_type == GUILD_FORUM)
+                        .map(|p| p.id.clone());
+
                     if channel.channel_type == 1 || channel.channel_type == 3 {
                         tx_action.send(AppAction::TransitionToDM).await.ok();
+                    } else if let Some(forum_id) = parent_forum {
+                        open_forum(&mut state, &tx_action, forum_id);
                     } else {
                         match channel.guild_id {
                             Some(guild_id) => tx_action
@@ -594,6 +1132,7 @@ pub async fn handle_keys_events(
                         .await
                         .ok();
                 }
+                AppState::MentionSelection(_) => completion::stop(&mut state),
             }
         }
         AppAction::Paste(text) => {
@@ -601,15 +1140,45 @@ pub async fn handle_keys_events(
             // but without necessarily switching mode if we want to be strict.
             // However, standard behavior usually implies switching to insert or just inserting.
             // Let's just insert.
+            let kind = typing_kind(&state, EditKind::Other);
+            undo::record(&mut state, kind);
             let pos = state.cursor_position;
             state.input.insert_str(pos, &text);
             state.cursor_position += text.len();
+            if kind == EditKind::Other {
+                undo::commit(&mut state);
+            }
+        }
+        AppAction::InputChar(c) if state.reacting_to.is_some() => {
+            state.emoji_filter.push(c);
+            state.selection_index = 0;
+        }
+        AppAction::InputChar(c) if state.message_selection.is_some() => {
+            selection::handle_selection_keys(&mut state, c, &tx_action).await;
         }
         AppAction::InputChar(c) => {
+            if let AppState::MentionSelection(_) = &state.state
+                && (!state.vim_mode || state.mode == InputMode::Insert)
+            {
+                insert_char_at_cursor(&mut state, c);
+                if c == ' ' {
+                    completion::stop(&mut state);
+                } else {
+                    completion::update_filter(&mut state, &tx_action);
+                }
+                return None;
+            }
             if c == ':' && (!state.vim_mode || state.mode == InputMode::Insert) {
                 tx_action.send(AppAction::SelectEmoji).await.ok();
                 return None;
             }
+            if (c == '@' || c == '#')
+                && (!state.vim_mode || state.mode == InputMode::Insert)
+                && matches!(state.state, AppState::Chatting(_))
+            {
+                tx_action.send(AppAction::SelectMention(c)).await.ok();
+                return None;
+            }
 
             if !state.vim_mode {
                 insert_char_at_cursor(&mut state, c);
@@ -621,9 +1190,19 @@ pub async fn handle_keys_events(
                     InputMode::Insert => {
                         insert_char_at_cursor(&mut state, c);
                     }
+                    // Typed into the command line by the arms above
+                    InputMode::Command => {}
                 }
             }
         }
+        AppAction::SelectMention(trigger) => completion::start(&mut state, trigger),
+        AppAction::ApiUpdateMemberSearch(query, members) => {
+            // Drop answers to searches the user has typed past
+            if matches!(state.state, AppState::MentionSelection(_)) && state.mention_filter == query
+            {
+                state.member_search_results = members;
+            }
+        }
         AppAction::SelectEmoji => {
             if let AppState::Chatting(channel_id) = &mut state.clone().state {
                 let cursor_pos = std::cmp::min(state.cursor_position, state.input.len());
@@ -648,6 +1227,10 @@ pub async fn handle_keys_events(
                 }
             }
         }
+        AppAction::InputBackspace if state.reacting_to.is_some() => {
+            state.emoji_filter.pop();
+            state.selection_index = 0;
+        }
         AppAction::InputBackspace => {
             if state.vim_mode && state.mode == InputMode::Normal {
                 if let Some(c) = state.input[..state.cursor_position].chars().next_back() {
@@ -655,8 +1238,19 @@ pub async fn handle_keys_events(
                 }
                 return None;
             }
+            let kind = typing_kind(&state, EditKind::Deleting);
+            undo::record(&mut state, kind);
             let current_state = state.state.clone();
             match current_state {
+                AppState::MentionSelection(_) => {
+                    let pos = state.cursor_position;
+                    if let Some(c) = state.input[..pos].chars().next_back() {
+                        let char_len = c.len_utf8();
+                        state.input.remove(pos - char_len);
+                        state.cursor_position -= char_len;
+                        completion::update_filter(&mut state, &tx_action);
+                    }
+                }
                 AppState::Chatting(_) => {
                     let pos = state.cursor_position;
                     if let Some(c) = state.input[..pos].chars().next_back() {
@@ -713,32 +1307,111 @@ pub async fn handle_keys_events(
             }
         }
         AppAction::InputSubmit => {
-            return input_submit(
-                &mut state,
-                &tx_action,
-                filtered_unicode,
-                filtered_custom,
-                total_filtered_emojis,
-            )
-            .await;
+            return input_submit(&mut state, &tx_action, &emoji_candidates).await;
+        }
+        AppAction::InputCtrl('e') if !(state.vim_mode && state.mode == InputMode::Normal) => {
+            editor::edit_input(&mut state);
+        }
+        AppAction::InputCtrl('n') => {
+            return unread::jump_to_next_unread(&mut state, &tx_action);
+        }
+        AppAction::InputCtrl('l') => members::toggle(&mut state, &tx_action),
+        AppAction::InputCtrl('o')
+            if matches!(state.state, AppState::Chatting(_))
+                && state.message_selection.is_none()
+                && state.mode != InputMode::Command =>
+        {
+            command::start_upload(&mut state);
+        }
+        AppAction::InputCtrl('w') if state.reacting_to.is_none() => layout::focus_next(&mut state),
+        AppAction::InputCtrl(c) => {
+            if state.vim_mode && state.mode == InputMode::Normal {
+                match c {
+                    'u' => scroll_chat(&mut state, &tx_action, ChatScroll::HalfPageUp),
+                    'd' => scroll_chat(&mut state, &tx_action, ChatScroll::HalfPageDown),
+                    'r' if matches!(state.state, AppState::Chatting(_)) => {
+                        if undo::redo(&mut state, 1) == 0 {
+                            state.status_message = "Already at newest change.".to_string();
+                        }
+                        vim::clamp_cursor(&mut state);
+                    }
+                    _ => {}
+                }
+            } else if !state.vim_mode && matches!(state.state, AppState::Chatting(_)) {
+                match c {
+                    'z' => {
+                        undo::undo(&mut state, 1);
+                    }
+                    'y' => {
+                        undo::redo(&mut state, 1);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppAction::ScrollChat(scroll) => scroll_chat(&mut state, &tx_action, scroll),
+        AppAction::InputTab => {
+            if let AppState::Chatting(_) = &state.state {
+                if state.message_selection.is_some() {
+                    selection::exit_selection(&mut state);
+                } else {
+                    selection::enter_selection(&mut state);
+                }
+            }
+        }
+        AppAction::SelectNext if state.reacting_to.is_some() => {
+            move_selection(&mut state, 1, total_filtered_emojis).await;
+        }
+        AppAction::SelectPrevious if state.reacting_to.is_some() => {
+            move_selection(&mut state, -1, total_filtered_emojis).await;
+        }
+        AppAction::SelectNext if state.message_selection.is_some() => {
+            selection::move_selection(&mut state, &tx_action, -1);
+        }
+        AppAction::SelectPrevious if state.message_selection.is_some() => {
+            selection::move_selection(&mut state, &tx_action, 1);
+        }
+        AppAction::SelectNext if matches!(state.state, AppState::MentionSelection(_)) => {
+            completion::move_selection(&mut state, 1);
+        }
+        AppAction::SelectPrevious if matches!(state.state, AppState::MentionSelection(_)) => {
+            completion::move_selection(&mut state, -1);
         }
         AppAction::SelectNext => move_selection(&mut state, 1, total_filtered_emojis).await,
         AppAction::SelectPrevious => move_selection(&mut state, -1, total_filtered_emojis).await,
         AppAction::ApiUpdateMessages(new_messages) => {
-            let active_channel_id = if let AppState::Chatting(id) = &state.state {
-                Some(id.clone())
-            } else {
-                None
-            };
-
-            if let Some(channel_id) = active_channel_id
-                && let Some(newest_msg) = new_messages.iter().max_by_key(|m| &m.id)
+            if let Some(channel_id) = state.chat_channel.clone()
+                && let Some(newest_msg) =
+                    new_messages.iter().max_by_key(|m| unread::snowflake(&m.id))
             {
                 state
                     .last_message_ids
-                    .insert(channel_id, newest_msg.id.clone());
+                    .insert(channel_id.clone(), newest_msg.id.clone());
+                unread::mark_read(&mut state, &tx_action, &channel_id);
             }
             state.messages = new_messages;
+            state.message_selection = None;
+            state.replying_to = None;
+            state.editing_message = None;
+            state.chat_scroll = 0;
+            state.loading_older_messages = false;
+            state.history_exhausted = false;
+        }
+        AppAction::ApiUpdateOlderMessages(channel_id, result) => {
+            if state.chat_channel.as_ref() == Some(&channel_id) {
+                state.loading_older_messages = false;
+                match result {
+                    Ok(older_messages) => {
+                        state.history_exhausted = older_messages.len() < MESSAGE_PAGE_SIZE;
+                        for message in older_messages {
+                            if !state.messages.iter().any(|m| m.id == message.id) {
+                                state.messages.push(message);
+                            }
+                        }
+                    }
+                    Err(e) => report_api_error(&mut state, "Failed to load older messages", &e),
+                }
+            }
         }
         AppAction::ApiUpdateGuilds(new_guilds) => {
             state.guilds = new_guilds.clone();
@@ -747,8 +1420,11 @@ pub async fn handle_keys_events(
                     .to_string();
         }
         AppAction::ApiUpdateChannel(new_channels) => {
+            unread::track_channels(&mut state, &new_channels);
+            switcher::cache_channels(&mut state, &new_channels);
             state.channels =
                 Channel::filter_channels_by_categories(new_channels).unwrap_or_default();
+            state.threads.clear();
             let text_channels_count = state.channels.len();
             if text_channels_count > 0 {
                 state.status_message =
@@ -759,20 +1435,30 @@ pub async fn handle_keys_events(
             }
             state.selection_index = 0;
         }
-        AppAction::ApiUpdateEmojis(new_emojis) => {
-            state.custom_emojis = new_emojis;
+        AppAction::ApiUpdateThreads(threads) => {
+            unread::track_channels(&mut state, &threads);
+            channels::merge_threads(&mut state, threads);
         }
-        AppAction::ApiUpdateDMs(new_dms) => {
-            state.dms = new_dms.clone();
-
-            // Initialize last_message_ids for all DMs on load
-            for dm in new_dms {
-                if let Some(msg_id) = dm.last_message_id {
-                    // Only insert if it doesn't already exist so we don't accidentally
-                    // overwrite during a mid-session refresh
-                    state.last_message_ids.entry(dm.id).or_insert(msg_id);
+        AppAction::ApiUpdateGuildActivity(channels) => {
+            unread::track_channels(&mut state, &channels);
+            switcher::cache_channels(&mut state, &channels);
+        }
+        AppAction::ApiThreadCreated(thread) => {
+            let (thread_id, thread_name) = (thread.id.clone(), thread.name.clone());
+            channels::merge_threads(&mut state, vec![thread]);
+            return open_chat(&mut state, &tx_action, thread_id, thread_name);
+        }
+        AppAction::ApiUpdateEmojis(new_emojis) => {
+            for emoji in new_emojis {
+                match state.custom_emojis.iter_mut().find(|e| e.id == emoji.id) {
+                    Some(existing) => *existing = emoji,
+                    None => state.custom_emojis.push(emoji),
                 }
             }
+        }
+        AppAction::ApiUpdateDMs(new_dms) => {
+            state.dms = new_dms;
+            unread::track_dms(&mut state);
 
             let dms_count = state.dms.len();
             if dms_count > 0 {
@@ -783,68 +1469,179 @@ pub async fn handle_keys_events(
             }
             state.selection_index = 0;
         }
+        AppAction::ApiUpdateMembers(guild_id, members) => {
+            state.guild_members.insert(guild_id, members);
+        }
+        AppAction::ApiUpdateProfileMember(member) => profile::update_member(&mut state, member),
+        AppAction::ApiDMOpened(dm) => return profile::open_dm(&mut state, &tx_action, dm),
         AppAction::ApiUpdateContext(new_context) => {
             state.context = new_context;
         }
         AppAction::ApiUpdateCurrentUser(user) => {
             state.current_user = Some(user);
         }
-        AppAction::ApiUpdateUnreadMessages(channel_id, new_messages) => {
-            let is_active_channel = match &state.state {
-                AppState::Chatting(active_id) => active_id == &channel_id,
-                _ => false,
-            };
-
-            if !new_messages.is_empty() {
-                // Only notify if we are not actively viewing this channel
-                if !is_active_channel {
-                    for msg in &new_messages {
-                        let should_notify = match state.last_message_ids.get(&channel_id) {
-                            // If we tracked it, check if it's strictly newer
-                            Some(last_id) => {
-                                msg.id.parse::<u64>().unwrap_or_default()
-                                    > last_id.parse::<u64>().unwrap_or_default()
-                            }
-                            // If we haven't tracked it, it's a completely new DM created mid-session!
-                            None => true,
-                        };
+        AppAction::ApiFailure(context, error) => {
+            report_api_error(&mut state, &context, &error);
+        }
+        AppAction::GatewayMessageCreate(message) => {
+            let is_active_channel =
+                layout::visible_chat(&state) == Some(message.channel_id.as_str());
 
-                        if should_notify {
-                            let is_self = state
-                                .current_user
-                                .as_ref()
-                                .is_some_and(|u| u.id == msg.author.id);
+            if state.chat_channel.as_ref() == Some(&message.channel_id)
+                && !state.messages.iter().any(|m| m.id == message.id)
+            {
+                // Keep the visible part of the history in place while scrolled up
+                if state.chat_scroll > 0 {
+                    let width = state.terminal_width.saturating_sub(6);
+                    state.chat_scroll += draw::estimate_message_height(&state, &message, width);
+                }
+                state.messages.insert(0, message.clone());
+                if let Some(selection) = &mut state.message_selection {
+                    selection.index += 1;
+                }
+            }
 
-                            if !is_self {
-                                let sender = msg.author.username.clone();
-                                let content = if state.discreet_notifs {
-                                    "Sent you a DM".to_string()
-                                } else {
-                                    msg.content
-                                        .clone()
-                                        .unwrap_or_else(|| "Sent an attachment".to_string())
-                                };
-                                let _ = notify_rust::Notification::new()
-                                    .summary(&sender)
-                                    .body(&content)
-                                    .appname("vimcord")
-                                    .show();
-                            }
+            if !is_active_channel && message.guild_id.is_none() {
+                notify_message(&state, &message);
+
+                if !state.dms.iter().any(|d| d.id == message.channel_id) {
+                    // First message of a DM opened mid-session, refresh the list to pick it up
+                    let api_client_clone = state.api_client.clone();
+                    let channel_id = message.channel_id.clone();
+                    let tx_clone = tx_action.clone();
+                    tokio::spawn(async move {
+                        if let Ok(dms) = api_client_clone.get_dms().await
+                            && let Some(dm) = dms.into_iter().find(|d| d.id == channel_id)
+                        {
+                            tx_clone.send(AppAction::GatewayDMUpdate(dm)).await.ok();
                         }
-                    }
+                    });
                 }
+            }
 
-                // Track the ID of the newest message in this batch (first in array or max ID)
-                if let Some(newest_msg) = new_messages
-                    .iter()
-                    .max_by_key(|m| m.id.parse::<u64>().unwrap_or_default())
+            if let Some(dm) = state.dms.iter_mut().find(|d| d.id == message.channel_id) {
+                dm.last_message_id = Some(message.id.clone());
+            }
+            unread::record_message(&mut state, &tx_action, &message, is_active_channel);
+        }
+        AppAction::GatewayMessageUpdate(message) => {
+            if let Some(existing) = state
+                .messages
+                .iter_mut()
+                .find(|m| m.channel_id == message.channel_id && m.id == message.id)
+            {
+                existing.apply_update(message);
+            }
+        }
+        AppAction::GatewayReactionAdd(update) => {
+            let me = state
+                .current_user
+                .as_ref()
+                .is_some_and(|u| u.id == update.user_id);
+            if let Some(message) = state
+                .messages
+                .iter_mut()
+                .find(|m| m.channel_id == update.channel_id && m.id == update.message_id)
+            {
+                message.add_reaction(update.emoji, me);
+            }
+        }
+        AppAction::GatewayReactionRemove(update) => {
+            let me = state
+                .current_user
+                .as_ref()
+                .is_some_and(|u| u.id == update.user_id);
+            if let Some(message) = state
+                .messages
+                .iter_mut()
+                .find(|m| m.channel_id == update.channel_id && m.id == update.message_id)
+            {
+                message.remove_reaction(&update.emoji, me);
+            }
+        }
+        AppAction::GatewayMessageDelete(channel_id, message_id) => {
+            if let Some(index) = state
+                .messages
+                .iter()
+                .position(|m| m.channel_id == channel_id && m.id == message_id)
+            {
+                state.messages.remove(index);
+
+                let len = state.messages.len();
+                if let Some(selection) = &mut state.message_selection
+                    && (index < selection.index || selection.index >= len)
                 {
-                    state
-                        .last_message_ids
-                        .insert(channel_id, newest_msg.id.clone());
+                    selection.index = selection.index.saturating_sub(1);
+                }
+                if state.messages.is_empty() {
+                    state.message_selection = None;
+                }
+            }
+        }
+        AppAction::GatewayChannelUpdate(channel) => {
+            let belongs_to_loaded_guild = channel.guild_id.is_some()
+                && Channel::flatten_categories(state.channels.clone())
+                    .iter()
+                    .any(|c| c.guild_id == channel.guild_id);
+
+            if belongs_to_loaded_guild {
+                let mut channels = Channel::flatten_categories(std::mem::take(&mut state.channels));
+                match channels.iter_mut().find(|c| c.id == channel.id) {
+                    Some(existing) => *existing = channel,
+                    None => channels.push(channel),
                 }
+                state.channels =
+                    Channel::filter_channels_by_categories(channels).unwrap_or_default();
             }
         }
+        AppAction::GatewayThreadUpdate(thread) => {
+            let belongs_to_loaded_guild = thread.guild_id.is_some()
+                && Channel::flatten_categories(state.channels.clone())
+                    .iter()
+                    .any(|c| c.guild_id == thread.guild_id);
+
+            if belongs_to_loaded_guild {
+                channels::merge_threads(&mut state, vec![thread]);
+            }
+        }
+        AppAction::GatewayChannelDelete(channel_id) => {
+            state.dms.retain(|d| d.id != channel_id);
+            state.threads.retain(|t| t.id != channel_id);
+            for channels in state.guild_channels.values_mut() {
+                channels.retain(|c| c.id != channel_id);
+            }
+
+            let mut channels = Channel::flatten_categories(std::mem::take(&mut state.channels));
+            channels.retain(|c| c.id != channel_id && c.parent_id.as_ref() != Some(&channel_id));
+            state.channels = Channel::filter_channels_by_categories(channels).unwrap_or_default();
+
+            if state.chat_channel.as_ref() == Some(&channel_id) {
+                state.messages.clear();
+                state.status_message = "This channel was deleted.".to_string();
+            }
+        }
+        AppAction::GatewayDMUpdate(dm) => {
+            match state.dms.iter_mut().find(|d| d.id == dm.id) {
+                Some(existing) => *existing = dm,
+                None => state.dms.insert(0, dm),
+            }
+            unread::track_dms(&mut state);
+        }
+        AppAction::GatewayPresenceUpdate(presences) => {
+            for presence in presences {
+                state.presences.insert(presence.user.id, presence.status);
+            }
+        }
+        AppAction::GatewayDisconnected(reason) => {
+            state.status_message = reason;
+        }
+        AppAction::GatewayUnauthorized => login::prompt(&mut state),
+        AppAction::ApiMessageDeleted => {
+            state.status_message = "Message deleted.".to_string();
+        }
+        AppAction::AttachmentDownloaded(path) => {
+            state.status_message = format!("Saved attachment to {}", path.display());
+        }
         AppAction::TransitionToChannels(guild_id) => {
             state.input = String::new();
             state.cursor_position = 0;
@@ -852,7 +1649,7 @@ pub async fn handle_keys_events(
             state.status_message =
                 "Select a server. Use arrows to navigate, Enter to select & Esc to quit"
                     .to_string();
-            state.selection_index = 0;
+            state.selection_index = layout::current_channel_index(&state).unwrap_or_default();
         }
         AppAction::TransitionToChat(channel_id) => {
             // Check if we're coming from emoji selection before changing state
@@ -871,7 +1668,7 @@ pub async fn handle_keys_events(
             }
             state.state = AppState::Chatting(channel_id.clone());
             state.status_message =
-                "Chatting in channel. Press Enter to send message, Esc to return to channels."
+                "Chatting in channel. Press Enter to send message, Ctrl-O to upload a file, Esc to return to channels."
                     .to_string();
         }
         AppAction::TransitionToGuilds => {
@@ -881,7 +1678,7 @@ pub async fn handle_keys_events(
             state.status_message =
                 "Select a server. Use arrows to navigate, Enter to select & Esc to quit"
                     .to_string();
-            state.selection_index = 0;
+            state.selection_index = layout::current_sidebar_index(&state).unwrap_or_default();
         }
         AppAction::TransitionToDM => {
             state.input = String::new();
@@ -889,7 +1686,7 @@ pub async fn handle_keys_events(
             state.state = AppState::SelectingDM;
             state.status_message =
                 "Select a DM. Use arrows to navigate, Enter to select & Esc to quit".to_string();
-            state.selection_index = 0;
+            state.selection_index = layout::current_dm_index(&state).unwrap_or_default();
         }
         AppAction::TransitionToHome => {
             state.input = String::new();
@@ -921,6 +1718,10 @@ pub async fn handle_keys_events(
         }
         AppAction::Tick => {
             state.tick_count = state.tick_count.wrapping_add(1);
+            if state.tick_count.is_multiple_of(SAVE_TICKS) {
+                unread::save(&mut state);
+                emoji_picker::save(&mut state);
+            }
             return Some(KeywordAction::Continue);
         }
     }
@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{LazyLock, Mutex},
+};
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Theme, ThemeSet},
+    parsing::SyntaxSet,
+};
+
+/// Background shared by every code block, highlighted or not.
+pub const CODE_BACKGROUND: Color = Color::Rgb(30, 33, 40);
+
+/// Highlighted blocks are kept so redrawing doesn't run the grammars on every frame.
+const MAX_CACHED_BLOCKS: usize = 256;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("base16-ocean.dark")
+        .unwrap_or_default()
+});
+
+type HighlightedBlock = Vec<Vec<Span<'static>>>;
+
+/// Language and code of a block.
+type BlockKey = (String, String);
+
+/// Highlighted blocks, dropping the least recently drawn one once full.
+#[derive(Default)]
+struct Cache {
+    blocks: HashMap<BlockKey, HighlightedBlock>,
+    /// Least recently used first
+    order: VecDeque<BlockKey>,
+}
+
+impl Cache {
+    fn get(&mut self, key: &BlockKey) -> Option<HighlightedBlock> {
+        let block = self.blocks.get(key)?.clone();
+        if let Some(index) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(index)?;
+            self.order.push_back(key);
+        }
+        Some(block)
+    }
+
+    fn insert(&mut self, key: BlockKey, block: HighlightedBlock) {
+        if self.blocks.len() >= MAX_CACHED_BLOCKS
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.blocks.remove(&oldest);
+        }
+        if self.blocks.insert(key.clone(), block).is_none() {
+            self.order.push_back(key);
+        }
+    }
+}
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(|| Mutex::new(Cache::default()));
+
+fn to_style(style: syntect::highlighting::Style) -> Style {
+    let mut modifier = Modifier::empty();
+    if style.font_style.contains(FontStyle::BOLD) {
+        modifier |= Modifier::BOLD;
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        modifier |= Modifier::ITALIC;
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        modifier |= Modifier::UNDERLINED;
+    }
+
+    Style::default()
+        .fg(Color::Rgb(
+            style.foreground.r,
+            style.foreground.g,
+            style.foreground.b,
+        ))
+        .bg(CODE_BACKGROUND)
+        .add_modifier(modifier)
+}
+
+/// Highlights the lines of a code block written in `language` (a name or file extension
+/// such as `rust`, `rs`, `sh` or `yaml`), one list of spans per line.
+///
+/// Returns `None` for languages the bundled grammars don't know.
+pub fn highlight(language: &str, code: &[String]) -> Option<HighlightedBlock> {
+    if language.is_empty() {
+        return None;
+    }
+
+    let joined = code.join("\n");
+    let key = (language.to_lowercase(), joined);
+    if let Some(block) = CACHE.lock().ok()?.get(&key) {
+        return Some(block);
+    }
+
+    let syntax = SYNTAXES.find_syntax_by_token(&key.0)?;
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+
+    let mut block = Vec::with_capacity(code.len());
+    for line in code {
+        let line = format!("{line}\n");
+        let ranges = highlighter.highlight_line(&line, &SYNTAXES).ok()?;
+        block.push(
+            ranges
+                .into_iter()
+                .map(|(style, text)| {
+                    Span::styled(text.trim_end_matches('\n').to_string(), to_style(style))
+                })
+                .filter(|span| !span.content.is_empty())
+                .collect(),
+        );
+    }
+
+    CACHE.lock().ok()?.insert(key, block.clone());
+
+    Some(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(code: &[&str]) -> Vec<String> {
+        code.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn known_languages_keep_the_text_on_the_code_background() {
+        let code = lines(&["fn main() {", "    let a = 1;", "}"]);
+        let block = highlight("rs", &code).unwrap();
+
+        let text: Vec<String> = block
+            .iter()
+            .map(|spans| spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        assert_eq!(text, code);
+        assert!(
+            block
+                .iter()
+                .flatten()
+                .all(|s| s.style.bg == Some(CODE_BACKGROUND))
+        );
+        // Keywords and literals don't share a color
+        let colors: std::collections::HashSet<Color> =
+            block[1].iter().filter_map(|s| s.style.fg).collect();
+        assert!(colors.len() > 1, "{colors:?}");
+    }
+
+    #[test]
+    fn language_names_and_extensions_both_work() {
+        let code = lines(&["echo hi"]);
+        assert!(highlight("sh", &code).is_some());
+        assert!(highlight("Bash", &code).is_some());
+    }
+
+    #[test]
+    fn unknown_or_missing_languages_are_left_plain() {
+        let code = lines(&["text"]);
+        assert!(highlight("", &code).is_none());
+        assert!(highlight("not-a-language", &code).is_none());
+    }
+
+    #[test]
+    fn the_cache_drops_the_least_recently_used_block() {
+        let mut cache = Cache::default();
+        let key = |i: usize| ("rs".to_string(), i.to_string());
+        for i in 0..MAX_CACHED_BLOCKS {
+            cache.insert(key(i), Vec::new());
+        }
+
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(MAX_CACHED_BLOCKS), Vec::new());
+
+        assert_eq!(cache.blocks.len(), MAX_CACHED_BLOCKS);
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.get(&key(MAX_CACHED_BLOCKS)).is_some());
+    }
+}
@@ -0,0 +1,197 @@
+use tokio::sync::MutexGuard;
+
+use crate::{
+    App, AppState,
+    api::{Channel, Guild},
+    ui::{channels, undo},
+};
+
+/// A pane of the multi-pane layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    /// Guilds, with an entry for DMs on top
+    Sidebar,
+    /// DMs, the channels of a guild or the posts of a forum
+    Middle,
+    Chat,
+}
+
+/// A row of the guild list. The DM entry only exists in the multi-pane layout, where
+/// there is no home screen to pick DMs from.
+#[derive(Debug, Clone, Copy)]
+pub enum SidebarEntry<'a> {
+    DirectMessages,
+    Guild(&'a Guild),
+}
+
+/// Whether the panes are enabled and the terminal is wide enough for them.
+pub fn panes_active(app: &App) -> bool {
+    app.panes.enabled && app.screen_width >= app.panes.min_width
+}
+
+/// The pane holding the focus, `None` for the screens drawn on their own.
+pub fn focused_pane(app: &App) -> Option<Pane> {
+    match &app.state {
+        AppState::Home => None,
+        AppState::SelectingGuild => Some(Pane::Sidebar),
+        AppState::SelectingDM | AppState::SelectingChannel(_) | AppState::Forum(_) => {
+            Some(Pane::Middle)
+        }
+        AppState::Chatting(_) | AppState::EmojiSelection(_) | AppState::MentionSelection(_) => {
+            Some(Pane::Chat)
+        }
+        AppState::Loading(_) | AppState::EnteringToken => None,
+    }
+}
+
+/// The chat on screen: the focused one, or with panes the one left open in the chat pane.
+pub fn visible_chat(app: &App) -> Option<&str> {
+    match &app.state {
+        AppState::Chatting(id) | AppState::EmojiSelection(id) | AppState::MentionSelection(id) => {
+            Some(id)
+        }
+        _ if panes_active(app) => app.chat_channel.as_deref(),
+        _ => None,
+    }
+}
+
+/// The guild whose channels are loaded.
+pub fn loaded_guild(app: &App) -> Option<String> {
+    Channel::flatten_categories(app.channels.clone())
+        .into_iter()
+        .find_map(|c| c.guild_id)
+}
+
+/// Whether the middle pane lists DMs rather than the channels of the loaded guild.
+pub fn middle_shows_dms(app: &App) -> bool {
+    match &app.state {
+        AppState::SelectingDM => true,
+        AppState::SelectingChannel(_) | AppState::Forum(_) => false,
+        _ => match &app.chat_channel {
+            Some(id) => app.dms.iter().any(|d| &d.id == id),
+            None => app.channels.is_empty(),
+        },
+    }
+}
+
+/// Rows of the guild list matching the lowercase filter.
+pub fn sidebar_entries<'a>(app: &'a App, filter: &str) -> Vec<SidebarEntry<'a>> {
+    let dms = (panes_active(app) && "direct messages".contains(filter))
+        .then_some(SidebarEntry::DirectMessages);
+
+    dms.into_iter()
+        .chain(
+            app.guilds
+                .iter()
+                .filter(|g| g.name.to_lowercase().contains(filter))
+                .map(SidebarEntry::Guild),
+        )
+        .collect()
+}
+
+/// Index of the sidebar entry of whatever the middle pane shows.
+pub fn current_sidebar_index(app: &App) -> Option<usize> {
+    let guild_id = loaded_guild(app);
+    let shows_dms = middle_shows_dms(app);
+
+    sidebar_entries(app, "")
+        .iter()
+        .position(|entry| match entry {
+            SidebarEntry::DirectMessages => shows_dms,
+            SidebarEntry::Guild(g) => !shows_dms && Some(&g.id) == guild_id.as_ref(),
+        })
+}
+
+/// Index of the open chat in the unfiltered DM list.
+pub fn current_dm_index(app: &App) -> Option<usize> {
+    let chat = app.chat_channel.as_deref()?;
+    app.dms.iter().position(|d| d.id == chat)
+}
+
+/// Index of the open chat in the unfiltered channel list.
+pub fn current_channel_index(app: &App) -> Option<usize> {
+    let chat = app.chat_channel.as_deref()?;
+    channels::channel_rows(app, "")
+        .iter()
+        .position(|row| row.channel.id == chat)
+}
+
+/// Stashes the unsent input of the chat being left.
+pub fn stash_draft(state: &mut MutexGuard<'_, App>) {
+    let input = std::mem::take(&mut state.input);
+    state.cursor_position = 0;
+    state.chat_draft = match (&state.chat_channel, input.is_empty()) {
+        (Some(channel_id), false) => Some((channel_id.clone(), input)),
+        _ => None,
+    };
+}
+
+/// Takes the draft stashed for `channel_id`, if any.
+pub fn take_draft(state: &mut MutexGuard<'_, App>, channel_id: &str) -> String {
+    undo::clear(state);
+    if let Some(vim_state) = &mut state.vim_state {
+        vim_state.visual = None;
+    }
+    match state.chat_draft.take() {
+        Some((id, draft)) if id == channel_id => draft,
+        _ => String::new(),
+    }
+}
+
+/// Moves the focus to the next pane: guilds, then DMs or channels, then the chat.
+pub fn focus_next(state: &mut MutexGuard<'_, App>) {
+    if !panes_active(state) {
+        return;
+    }
+
+    match focused_pane(state) {
+        Some(Pane::Sidebar) => {
+            state.input.clear();
+            state.cursor_position = 0;
+            if middle_shows_dms(state) {
+                state.selection_index = current_dm_index(state).unwrap_or_default();
+                state.state = AppState::SelectingDM;
+                state.status_message =
+                    "Select a DM. Use arrows to navigate, Enter to select & Ctrl-w to switch panes"
+                        .to_string();
+            } else if let Some(guild_id) = loaded_guild(state) {
+                state.selection_index = current_channel_index(state).unwrap_or_default();
+                state.state = AppState::SelectingChannel(guild_id);
+                state.status_message =
+                    "Select a channel. Enter to open & Ctrl-w to switch panes".to_string();
+            }
+        }
+        Some(Pane::Middle) => match state.chat_channel.clone() {
+            Some(channel_id) => {
+                state.input = take_draft(state, &channel_id);
+                state.cursor_position = state.input.len();
+                state.state = AppState::Chatting(channel_id);
+                state.status_message =
+                    "Chatting in channel. Press Enter to send message, Ctrl-w to switch panes."
+                        .to_string();
+            }
+            None => focus_sidebar(state),
+        },
+        Some(Pane::Chat) if matches!(state.state, AppState::Chatting(_)) => {
+            state.message_selection = None;
+            state.member_selection = None;
+            state.replying_to = None;
+            if state.editing_message.take().is_some() || state.starting_thread.take().is_some() {
+                state.input.clear();
+            }
+            stash_draft(state);
+            focus_sidebar(state);
+        }
+        Some(Pane::Chat) | None => {}
+    }
+}
+
+fn focus_sidebar(state: &mut MutexGuard<'_, App>) {
+    state.input.clear();
+    state.cursor_position = 0;
+    state.selection_index = current_sidebar_index(state).unwrap_or_default();
+    state.state = AppState::SelectingGuild;
+    state.status_message =
+        "Select a server. Use arrows to navigate, Enter to select & Ctrl-w to switch panes"
+            .to_string();
+}
@@ -0,0 +1,59 @@
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{App, AppAction, AppState, InputMode, Window, ui::events};
+
+/// Drops the token Discord rejected and asks for a new one in the input.
+pub fn prompt(state: &mut MutexGuard<'_, App>) {
+    if !matches!(state.state, AppState::EnteringToken) {
+        state.api_client.auth_token.clear();
+        state.state = AppState::EnteringToken;
+        state.input.clear();
+        state.cursor_position = 0;
+        state.switcher = None;
+        state.profile = None;
+        state.member_selection = None;
+        state.message_selection = None;
+        if state.mode == InputMode::Command {
+            state.mode = InputMode::Normal;
+        }
+    }
+    state.status_message =
+        "Discord rejected the token (401). Paste a valid one and press Enter, Esc to quit"
+            .to_string();
+}
+
+/// Types into the token, which is shown masked.
+pub fn insert(state: &mut MutexGuard<'_, App>, text: &str) {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let pos = state.cursor_position;
+    state.input.insert_str(pos, &text);
+    state.cursor_position += text.len();
+}
+
+pub fn backspace(state: &mut MutexGuard<'_, App>) {
+    if let Some(c) = state.input[..state.cursor_position].chars().next_back() {
+        state.cursor_position -= c.len_utf8();
+        let pos = state.cursor_position;
+        state.input.remove(pos);
+    }
+}
+
+/// Uses the entered token for the API and the gateway, then loads the account again.
+pub fn submit(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    let token = std::mem::take(&mut state.input);
+    state.cursor_position = 0;
+    if token.is_empty() {
+        return;
+    }
+
+    state.api_client.auth_token = token.clone();
+    state.gateway_token.send_replace(token);
+    state.state = AppState::Loading(Window::Home);
+    state.status_message = "Loading...".to_string();
+
+    let api_client = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        events::load_account(&api_client, &tx_clone).await;
+    });
+}
@@ -0,0 +1,652 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::ui::{highlight, mentions::Mentions};
+
+/// A rendered line of markdown before wrapping.
+#[derive(Debug, Clone)]
+pub struct MarkdownLine {
+    pub line: Line<'static>,
+    /// Spans repeated at the start of every row the line wraps onto (quote bars, list indents)
+    pub indent: Vec<Span<'static>>,
+    /// Part of a fenced code block, which never shares a row with the message header
+    pub code: bool,
+}
+
+impl MarkdownLine {
+    fn new(line: Line<'static>, indent: Vec<Span<'static>>) -> Self {
+        Self {
+            line,
+            indent,
+            code: false,
+        }
+    }
+}
+
+/// What the markdown is rendered with besides the text itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions<'a> {
+    pub reveal_spoilers: bool,
+    pub mentions: Mentions<'a>,
+}
+
+/// Inline delimiters, longest first so `**` wins over `*`.
+const DELIMITERS: [(&str, Modifier); 6] = [
+    ("**", Modifier::BOLD),
+    ("__", Modifier::UNDERLINED),
+    ("~~", Modifier::CROSSED_OUT),
+    ("||", Modifier::empty()),
+    ("*", Modifier::ITALIC),
+    ("_", Modifier::ITALIC),
+];
+
+fn quote_bar() -> Span<'static> {
+    Span::styled("▎ ", Style::default().fg(Color::DarkGray))
+}
+
+fn code_style() -> Style {
+    Style::default().fg(Color::LightRed).bg(Color::Black)
+}
+
+/// Parses Discord flavoured markdown into styled lines.
+///
+/// Spoilers are hidden behind blocks unless `options.reveal_spoilers` is set.
+pub fn render(text: &str, base: Style, options: &RenderOptions) -> Vec<MarkdownLine> {
+    let mut lines = Vec::new();
+    let mut code_block: Option<(String, Vec<String>)> = None;
+    let mut quote_rest = false;
+
+    for raw in text.split('\n') {
+        if let Some((language, code)) = &mut code_block {
+            if let Some(last) = raw.strip_suffix("```") {
+                if !last.is_empty() {
+                    code.push(last.to_string());
+                }
+                lines.extend(code_lines(language, code));
+                code_block = None;
+            } else {
+                code.push(raw.to_string());
+            }
+            continue;
+        }
+
+        if let Some(rest) = raw.strip_prefix("```") {
+            // Single line block: ```code```
+            if let Some(code) = rest.strip_suffix("```").filter(|c| !c.is_empty()) {
+                lines.extend(code_lines("", &[code.to_string()]));
+                continue;
+            }
+
+            let (language, first) = match rest.trim() {
+                word if !word.contains(' ') => (word.to_string(), None),
+                _ => (String::new(), Some(rest.to_string())),
+            };
+            code_block = Some((language, first.into_iter().collect()));
+            continue;
+        }
+
+        let mut indent = Vec::new();
+        let mut line = raw;
+        if quote_rest {
+            indent.push(quote_bar());
+        } else if let Some(rest) = line.strip_prefix(">>> ") {
+            quote_rest = true;
+            indent.push(quote_bar());
+            line = rest;
+        } else if let Some(rest) = line.strip_prefix("> ") {
+            indent.push(quote_bar());
+            line = rest;
+        }
+
+        lines.push(render_line(line, base, indent, options));
+    }
+
+    // An unterminated fence still reads better as code than as backticks
+    if let Some((language, code)) = code_block {
+        lines.extend(code_lines(&language, &code));
+    }
+
+    lines
+}
+
+/// Renders one line outside code blocks, handling headers, subtext and list items.
+fn render_line(
+    line: &str,
+    base: Style,
+    mut indent: Vec<Span<'static>>,
+    options: &RenderOptions,
+) -> MarkdownLine {
+    let mut spans = indent.clone();
+
+    let headers = [
+        ("### ", base.add_modifier(Modifier::BOLD)),
+        ("## ", base.add_modifier(Modifier::BOLD)),
+        (
+            "# ",
+            base.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        ),
+        ("-# ", Style::default().fg(Color::DarkGray)),
+    ];
+    for (marker, style) in headers {
+        if let Some(rest) = line.strip_prefix(marker) {
+            parse_inline(rest, style, options, &mut spans);
+            return MarkdownLine::new(Line::from(spans), indent);
+        }
+    }
+
+    let trimmed = line.trim_start_matches(' ');
+    let depth = (line.len() - trimmed.len()) / 2;
+    let bullet = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+        .map(|rest| ("• ".to_string(), rest))
+        .or_else(|| {
+            let (number, rest) = trimmed.split_once(". ")?;
+            (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+                .then(|| (format!("{number}. "), rest))
+        });
+
+    match bullet {
+        Some((marker, rest)) => {
+            let padding = "  ".repeat(depth);
+            let marker_width = UnicodeWidthStr::width(marker.as_str());
+            spans.push(Span::styled(
+                format!("{padding}{marker}"),
+                Style::default().fg(Color::DarkGray),
+            ));
+            indent.push(Span::raw(format!("{padding}{}", " ".repeat(marker_width))));
+            parse_inline(rest, base, options, &mut spans);
+        }
+        None => parse_inline(line, base, options, &mut spans),
+    }
+
+    MarkdownLine::new(Line::from(spans), indent)
+}
+
+/// Lines of a fenced code block, highlighted when the language is known.
+fn code_lines(language: &str, code: &[String]) -> Vec<MarkdownLine> {
+    let gutter = Span::styled("│ ", Style::default().fg(Color::DarkGray));
+    // Tabs have no width of their own, expand them so indentation survives
+    let code: Vec<String> = code.iter().map(|l| l.replace('\t', "    ")).collect();
+
+    let highlighted = highlight::highlight(language, &code).unwrap_or_else(|| {
+        code.iter()
+            .map(|line| vec![Span::styled(line.clone(), plain_code_style())])
+            .collect()
+    });
+
+    highlighted
+        .into_iter()
+        .map(|spans| {
+            let mut line = vec![gutter.clone()];
+            line.extend(spans);
+            MarkdownLine {
+                line: Line::from(line),
+                indent: vec![gutter.clone()],
+                code: true,
+            }
+        })
+        .collect()
+}
+
+fn plain_code_style() -> Style {
+    Style::default()
+        .fg(Color::Gray)
+        .bg(highlight::CODE_BACKGROUND)
+}
+
+/// Finds where the span opened by `delimiter` closes in `rest`, skipping doubled
+/// delimiters when looking for a single character one.
+fn find_closing(rest: &str, delimiter: &str) -> Option<usize> {
+    let mut start = 0;
+    while let Some(found) = rest[start..].find(delimiter) {
+        let index = start + found;
+        let after = &rest[index + delimiter.len()..];
+
+        if delimiter.len() == 1 && after.starts_with(delimiter) {
+            start = index + 2 * delimiter.len();
+            continue;
+        }
+
+        // `***a***` closes the bold on the last two stars, leaving the italic inside
+        let mut end = index;
+        let mut after = after;
+        while let Some(stripped) = after.strip_prefix(&delimiter[..1]) {
+            end += 1;
+            after = stripped;
+        }
+        return (end > 0).then_some(end);
+    }
+
+    None
+}
+
+/// Parses masked links, `[label](url)`, returning the label, url and the consumed length.
+fn parse_link(rest: &str) -> Option<(&str, &str, usize)> {
+    let label_end = rest.find("](")?;
+    let label = &rest[1..label_end];
+    let url_start = label_end + 2;
+    let url_len = rest[url_start..].find(')')?;
+    let url = &rest[url_start..url_start + url_len];
+
+    (!label.is_empty() && url.starts_with("http") && !url.contains(' ')).then_some((
+        label,
+        url,
+        url_start + url_len + 1,
+    ))
+}
+
+fn flush(buffer: &mut String, style: Style, spans: &mut Vec<Span<'static>>) {
+    if !buffer.is_empty() {
+        spans.push(Span::styled(std::mem::take(buffer), style));
+    }
+}
+
+/// Parses inline markdown in `text`, pushing styled spans.
+fn parse_inline(text: &str, style: Style, options: &RenderOptions, spans: &mut Vec<Span<'static>>) {
+    let mut buffer = String::new();
+    let mut previous: Option<char> = None;
+    let mut i = 0;
+
+    'outer: while i < text.len() {
+        let rest = &text[i..];
+
+        if let Some(c) = rest.strip_prefix('\\').and_then(|r| r.chars().next())
+            && c.is_ascii_punctuation()
+        {
+            buffer.push(c);
+            previous = Some(c);
+            i += 1 + c.len_utf8();
+            continue;
+        }
+
+        if rest.starts_with('`') {
+            let ticks = if rest.starts_with("``") { 2 } else { 1 };
+            if let Some(end) = rest[ticks..].find(&rest[..ticks])
+                && end > 0
+            {
+                flush(&mut buffer, style, spans);
+                spans.push(Span::styled(
+                    rest[ticks..ticks + end].to_string(),
+                    code_style(),
+                ));
+                i += 2 * ticks + end;
+                previous = Some('`');
+                continue;
+            }
+        }
+
+        if rest.starts_with('<')
+            && let Some(end) = rest.find('>')
+            && let Some((text, mention_style)) = options.mentions.resolve(&rest[1..end])
+        {
+            flush(&mut buffer, style, spans);
+            spans.push(Span::styled(text, mention_style));
+            i += end + 1;
+            previous = Some('>');
+            continue;
+        }
+
+        if rest.starts_with('[')
+            && let Some((label, url, len)) = parse_link(rest)
+        {
+            flush(&mut buffer, style, spans);
+            parse_inline(
+                label,
+                style
+                    .fg(Color::LightBlue)
+                    .add_modifier(Modifier::UNDERLINED),
+                options,
+                spans,
+            );
+            spans.push(Span::styled(
+                format!(" ({url})"),
+                Style::default().fg(Color::DarkGray),
+            ));
+            i += len;
+            previous = Some(')');
+            continue;
+        }
+
+        for (delimiter, modifier) in DELIMITERS {
+            if !rest.starts_with(delimiter) {
+                continue;
+            }
+            // snake_case identifiers aren't italic
+            if delimiter == "_" && previous.is_some_and(|c| c.is_alphanumeric()) {
+                continue;
+            }
+            let Some(end) = find_closing(&rest[delimiter.len()..], delimiter) else {
+                continue;
+            };
+
+            let inner = &rest[delimiter.len()..delimiter.len() + end];
+            let consumed = 2 * delimiter.len() + end;
+            if delimiter == "_"
+                && rest[consumed..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_alphanumeric())
+            {
+                continue;
+            }
+
+            flush(&mut buffer, style, spans);
+            if delimiter == "||" && !options.reveal_spoilers {
+                spans.push(Span::styled(
+                    "█".repeat(UnicodeWidthStr::width(inner)),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            } else if delimiter == "||" {
+                parse_inline(inner, style.bg(Color::Black), options, spans);
+            } else {
+                parse_inline(inner, style.add_modifier(modifier), options, spans);
+            }
+
+            i += consumed;
+            previous = delimiter.chars().next();
+            continue 'outer;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        buffer.push(c);
+        previous = Some(c);
+        i += c.len_utf8();
+    }
+
+    flush(&mut buffer, style, spans);
+}
+
+/// Appends text to the last row, merging it into the previous span when the style matches.
+fn push_piece(row: &mut Vec<Span<'static>>, piece: &str, style: Style) {
+    match row.last_mut() {
+        Some(last) if last.style == style => last.content.to_mut().push_str(piece),
+        _ => row.push(Span::styled(piece.to_string(), style)),
+    }
+}
+
+fn trim_row_end(row: &mut [Span<'static>]) {
+    if let Some(last) = row.last_mut() {
+        let trimmed = last.content.trim_end_matches(' ').len();
+        last.content.to_mut().truncate(trimmed);
+    }
+}
+
+/// Word-wraps a styled line to `width` columns, starting continuation rows with `indent`.
+pub fn wrap_line(
+    line: Line<'static>,
+    indent: &[Span<'static>],
+    width: usize,
+) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let indent_width: usize = indent.iter().map(|s| s.width()).sum();
+    let indent = if indent_width < width { indent } else { &[] };
+    let indent_width = if indent.is_empty() { 0 } else { indent_width };
+
+    let line_style = line.style;
+    let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new()];
+    let mut row_width = 0;
+
+    let break_row = |rows: &mut Vec<Vec<Span<'static>>>, row_width: &mut usize| {
+        if let Some(row) = rows.last_mut() {
+            trim_row_end(row);
+        }
+        rows.push(indent.to_vec());
+        *row_width = indent_width;
+    };
+
+    for span in line.spans {
+        for piece in span.content.split_inclusive(' ') {
+            let piece_width = UnicodeWidthStr::width(piece);
+            let word_width = UnicodeWidthStr::width(piece.trim_end_matches(' '));
+            let row_has_text = row_width > indent_width || rows.len() == 1 && row_width > 0;
+
+            if row_width + word_width > width && row_has_text {
+                break_row(&mut rows, &mut row_width);
+            }
+
+            if row_width + word_width <= width {
+                if let Some(row) = rows.last_mut() {
+                    push_piece(row, piece, span.style);
+                }
+                row_width += piece_width;
+                continue;
+            }
+
+            // Words wider than a whole row are split wherever they overflow
+            for c in piece.chars() {
+                let char_width = c.width().unwrap_or(0);
+                if row_width + char_width > width && c != ' ' {
+                    break_row(&mut rows, &mut row_width);
+                }
+                if let Some(row) = rows.last_mut() {
+                    push_piece(row, c.encode_utf8(&mut [0; 4]), span.style);
+                }
+                row_width += char_width;
+            }
+        }
+    }
+
+    rows.into_iter()
+        .map(|row| Line::from(row).style(line_style))
+        .collect()
+}
+
+/// Breaks a code line at exactly `width` columns, keeping every space so indentation
+/// stays intact, and pads rows so the block background forms a rectangle.
+fn wrap_code_line(
+    line: Line<'static>,
+    indent: &[Span<'static>],
+    width: usize,
+) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let indent_width: usize = indent.iter().map(|s| s.width()).sum();
+    let indent = if indent_width < width { indent } else { &[] };
+    let indent_width = if indent.is_empty() { 0 } else { indent_width };
+
+    let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new()];
+    let mut row_width = 0;
+
+    for span in line.spans {
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if row_width + char_width > width {
+                rows.push(indent.to_vec());
+                row_width = indent_width;
+            }
+            if let Some(row) = rows.last_mut() {
+                push_piece(row, c.encode_utf8(&mut [0; 4]), span.style);
+            }
+            row_width += char_width;
+        }
+    }
+
+    rows.into_iter()
+        .map(|mut row| {
+            let used: usize = row.iter().map(|s| s.width()).sum();
+            push_piece(
+                &mut row,
+                &" ".repeat(width.saturating_sub(used)),
+                plain_code_style(),
+            );
+            Line::from(row).style(line.style)
+        })
+        .collect()
+}
+
+/// Wraps rendered markdown into rows of at most `width` columns.
+pub fn wrap(lines: Vec<MarkdownLine>, width: usize) -> Vec<Line<'static>> {
+    lines
+        .into_iter()
+        .flat_map(|l| {
+            if l.code {
+                wrap_code_line(l.line, &l.indent, width)
+            } else {
+                wrap_line(l.line, &l.indent, width)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &str, options: &RenderOptions) -> Vec<(String, Style)> {
+        let mut spans = Vec::new();
+        parse_inline(text, Style::default(), options, &mut spans);
+        spans
+            .into_iter()
+            .map(|s| (s.content.into_owned(), s.style))
+            .collect()
+    }
+
+    fn plain(text: &str) -> Vec<(String, Style)> {
+        spans(text, &RenderOptions::default())
+    }
+
+    fn styled(text: &str, modifier: Modifier) -> (String, Style) {
+        (text.to_string(), Style::default().add_modifier(modifier))
+    }
+
+    fn rows(lines: Vec<Line<'static>>) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn inline_delimiters() {
+        assert_eq!(
+            plain("a **b** *c* __d__ ~~e~~"),
+            vec![
+                ("a ".to_string(), Style::default()),
+                styled("b", Modifier::BOLD),
+                (" ".to_string(), Style::default()),
+                styled("c", Modifier::ITALIC),
+                (" ".to_string(), Style::default()),
+                styled("d", Modifier::UNDERLINED),
+                (" ".to_string(), Style::default()),
+                styled("e", Modifier::CROSSED_OUT),
+            ]
+        );
+    }
+
+    #[test]
+    fn triple_stars_are_bold_and_italic() {
+        assert_eq!(
+            plain("***a***"),
+            vec![styled("a", Modifier::BOLD | Modifier::ITALIC)]
+        );
+    }
+
+    #[test]
+    fn text_that_is_not_markdown_stays_plain() {
+        for text in ["snake_case_name", "**unclosed", "2 * 3 = 6"] {
+            assert_eq!(plain(text), vec![(text.to_string(), Style::default())]);
+        }
+    }
+
+    #[test]
+    fn escaped_delimiters_are_literal() {
+        assert_eq!(
+            plain(r"\*not italic\*"),
+            vec![("*not italic*".to_string(), Style::default())]
+        );
+    }
+
+    #[test]
+    fn inline_code_is_not_parsed() {
+        assert_eq!(
+            plain("a `b *c*` d"),
+            vec![
+                ("a ".to_string(), Style::default()),
+                ("b *c*".to_string(), code_style()),
+                (" d".to_string(), Style::default()),
+            ]
+        );
+        assert_eq!(plain("``a`b``"), vec![("a`b".to_string(), code_style())]);
+    }
+
+    #[test]
+    fn spoilers_are_hidden_until_revealed() {
+        assert_eq!(
+            plain("||secret||"),
+            vec![("██████".to_string(), Style::default().fg(Color::DarkGray))]
+        );
+
+        let options = RenderOptions {
+            reveal_spoilers: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            spans("||secret||", &options),
+            vec![("secret".to_string(), Style::default().bg(Color::Black))]
+        );
+    }
+
+    #[test]
+    fn masked_links_show_the_url() {
+        let parsed = plain("[docs](https://example.com)");
+        assert_eq!(parsed[0].0, "docs");
+        assert_eq!(parsed[1].0, " (https://example.com)");
+
+        // Only web links are masked
+        assert_eq!(
+            plain("[a](b)"),
+            vec![("[a](b)".to_string(), Style::default())]
+        );
+    }
+
+    #[test]
+    fn block_elements() {
+        let text = "# Title\n> quoted\n- item\n  1. nested\n```rs\nfn main() {}\n```";
+        let lines = render(text, Style::default(), &RenderOptions::default());
+        let rendered: Vec<String> = lines.iter().map(|l| l.line.to_string()).collect();
+
+        assert_eq!(
+            rendered,
+            vec![
+                "Title",
+                "▎ quoted",
+                "• item",
+                "  1. nested",
+                "│ fn main() {}"
+            ]
+        );
+        assert_eq!(lines[1].indent.len(), 1);
+        assert_eq!(lines[2].indent[0].content, "  ");
+        assert!(!lines[3].code && lines[4].code);
+    }
+
+    #[test]
+    fn unterminated_code_block_is_still_code() {
+        let lines = render(
+            "```\nlet a = 1;",
+            Style::default(),
+            &RenderOptions::default(),
+        );
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].code);
+    }
+
+    #[test]
+    fn wraps_words_and_keeps_indents() {
+        let lines = render(
+            "- one two three",
+            Style::default(),
+            &RenderOptions::default(),
+        );
+        assert_eq!(rows(wrap(lines, 9)), vec!["• one two", "  three"]);
+
+        let lines = render("abcdefghij", Style::default(), &RenderOptions::default());
+        assert_eq!(rows(wrap(lines, 4)), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn code_rows_are_padded_to_the_width() {
+        let lines = render("```\nab\n```", Style::default(), &RenderOptions::default());
+        assert_eq!(rows(wrap(lines, 6)), vec!["│ ab  "]);
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Reverse;
+
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction,
+    api::{Channel, User, channel::Role},
+    ui::{layout, mentions::find_channel, selection},
+};
+
+/// How many members are fetched per guild, the most a single request returns.
+const MEMBER_LIMIT: usize = 1000;
+
+pub const MEMBER_SELECTION_STATUS: &str =
+    "Members: j/k move, Enter or p show profile, Esc or m back to messages";
+
+/// A member as shown in the member list.
+#[derive(Debug, Clone)]
+pub struct ListedMember {
+    pub user: User,
+    pub name: String,
+    /// Color of the highest colored role, 0 for none
+    pub color: u32,
+    /// `None` when presences are unknown
+    pub status: Option<String>,
+}
+
+/// Members listed under a hoisted role, or under Online, Offline or Members.
+#[derive(Debug, Clone)]
+pub struct MemberGroup {
+    pub name: String,
+    pub members: Vec<ListedMember>,
+}
+
+/// Status of a user. With presences on, users the gateway never reported are offline.
+pub fn status<'a>(app: &'a App, user_id: &str) -> Option<&'a str> {
+    if !app.track_presences {
+        return None;
+    }
+    Some(app.presences.get(user_id).map_or("offline", String::as_str))
+}
+
+fn display_name(user: &User, nick: Option<&String>) -> String {
+    nick.or(user.global_name.as_ref())
+        .unwrap_or(&user.username)
+        .clone()
+}
+
+/// The guild channel of the open chat, threads resolved to their parent whose permissions
+/// they share.
+fn chat_guild_channel(app: &App) -> Option<&Channel> {
+    let id = app.chat_channel.as_deref()?;
+    let channel =
+        find_channel(&app.channels, id).or_else(|| app.threads.iter().find(|t| t.id == id))?;
+
+    match (channel.is_thread(), &channel.parent_id) {
+        (true, Some(parent_id)) => find_channel(&app.channels, parent_id),
+        _ => Some(channel),
+    }
+}
+
+/// Guild of the open chat, `None` in DMs.
+pub fn chat_guild_id(app: &App) -> Option<String> {
+    chat_guild_channel(app).and_then(|c| c.guild_id.clone())
+}
+
+/// Fetches the members of the open chat's guild unless they are cached or on their way.
+pub fn load(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    if !state.show_members {
+        return;
+    }
+    let Some(guild_id) = chat_guild_id(state) else {
+        return;
+    };
+    if state.guild_members.contains_key(&guild_id) {
+        return;
+    }
+
+    state.guild_members.insert(guild_id.clone(), Vec::new());
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        let action = match api_client_clone
+            .get_guild_members(&guild_id, MEMBER_LIMIT)
+            .await
+        {
+            Ok(members) => AppAction::ApiUpdateMembers(guild_id, members),
+            Err(e) => AppAction::ApiFailure("Failed to load members".to_string(), e),
+        };
+        tx_clone.send(action).await.ok();
+    });
+}
+
+pub fn toggle(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    state.show_members = !state.show_members;
+    state.member_selection = None;
+    load(state, tx_action);
+}
+
+/// Moves the cursor into the member list, showing it first if needed.
+pub fn enter_selection(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    if !state.show_members {
+        toggle(state, tx_action);
+    }
+    if member_groups(state).iter().all(|g| g.members.is_empty()) {
+        state.status_message = "No members to pick from yet.".to_string();
+        return;
+    }
+
+    state.member_selection = Some(0);
+    state.status_message = MEMBER_SELECTION_STATUS.to_string();
+}
+
+/// Leaves the member list, back to the selected message if there was one.
+pub fn exit_selection(state: &mut MutexGuard<'_, App>) {
+    state.member_selection = None;
+    if state.message_selection.is_some() {
+        state.status_message = selection::SELECTION_STATUS.to_string();
+    } else {
+        selection::exit_selection(state);
+    }
+}
+
+pub fn move_selection(state: &mut MutexGuard<'_, App>, n: i32) {
+    let len: usize = member_groups(state).iter().map(|g| g.members.len()).sum();
+    if let Some(index) = &mut state.member_selection
+        && len > 0
+    {
+        *index = (*index as i64 + i64::from(n)).rem_euclid(len as i64) as usize;
+    }
+}
+
+/// The member under the cursor, counting down through the groups as they are drawn.
+pub fn selected_member(app: &App) -> Option<ListedMember> {
+    let index = app.member_selection?;
+    member_groups(app)
+        .into_iter()
+        .flat_map(|g| g.members)
+        .nth(index)
+}
+
+/// Who can see the visible chat: the recipients of a DM, or the guild members the channel's
+/// permissions let in, grouped by their highest hoisted role like Discord does.
+pub fn member_groups(app: &App) -> Vec<MemberGroup> {
+    let Some(chat_id) = layout::visible_chat(app) else {
+        return Vec::new();
+    };
+
+    if let Some(dm) = app.dms.iter().find(|d| d.id == chat_id) {
+        let members = dm
+            .recipients
+            .iter()
+            .chain(app.current_user.as_ref())
+            .map(|user| ListedMember {
+                user: user.clone(),
+                name: display_name(user, None),
+                color: 0,
+                status: status(app, &user.id).map(str::to_string),
+            });
+        return group(members.map(|m| (None, m)).collect());
+    }
+
+    let (Some(channel), Some(context)) = (chat_guild_channel(app), &app.context) else {
+        return Vec::new();
+    };
+    let Some(members) = channel
+        .guild_id
+        .as_ref()
+        .and_then(|id| app.guild_members.get(id))
+    else {
+        return Vec::new();
+    };
+
+    let mut roles: Vec<&Role> = context.all_guild_roles.iter().collect();
+    roles.sort_by_key(|r| Reverse(r.position));
+
+    let listed = members
+        .iter()
+        .filter(|member| channel.is_readable_by(context, member))
+        .map(|member| {
+            let member_roles: Vec<&Role> = roles
+                .iter()
+                .copied()
+                .filter(|r| member.roles.contains(&r.id))
+                .collect();
+            let hoisted = member_roles.iter().copied().find(|r| r.hoist);
+
+            (
+                hoisted,
+                ListedMember {
+                    user: member.user.clone(),
+                    name: display_name(&member.user, member.nick.as_ref()),
+                    color: member_roles
+                        .iter()
+                        .find(|r| r.color != 0)
+                        .map_or(0, |r| r.color),
+                    status: status(app, &member.user.id).map(str::to_string),
+                },
+            )
+        })
+        .collect();
+
+    group(listed)
+}
+
+/// Offline members go last whatever their roles, the rest under their hoisted role or
+/// Online, or Members when presences are unknown.
+fn group(members: Vec<(Option<&Role>, ListedMember)>) -> Vec<MemberGroup> {
+    let mut groups: Vec<(i64, MemberGroup)> = Vec::new();
+
+    for (hoisted, member) in members {
+        let (rank, name) = match (hoisted, member.status.as_deref()) {
+            (_, Some("offline")) => (i64::MIN, "Offline".to_string()),
+            (Some(role), _) => (i64::from(role.position), role.name.clone()),
+            (None, Some(_)) => (i64::MIN + 1, "Online".to_string()),
+            (None, None) => (i64::MIN + 1, "Members".to_string()),
+        };
+
+        match groups.iter_mut().find(|(_, g)| g.name == name) {
+            Some((_, existing)) => existing.members.push(member),
+            None => groups.push((
+                rank,
+                MemberGroup {
+                    name,
+                    members: vec![member],
+                },
+            )),
+        }
+    }
+
+    groups.sort_by_key(|(rank, _)| Reverse(*rank));
+    groups
+        .into_iter()
+        .map(|(_, mut group)| {
+            group.members.sort_by_key(|m| m.name.to_lowercase());
+            group
+        })
+        .collect()
+}
@@ -0,0 +1,204 @@
+use chrono::{DateTime, Local, Utc};
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::{
+    App,
+    api::{Channel, Message, User, channel::Role},
+};
+
+/// Looks up what `<@id>`, `<@&id>`, `<#id>` and `<t:unix:style>` tokens in a message refer to.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mentions<'a> {
+    /// Users mentioned by the message, Discord sends them alongside the content
+    pub users: &'a [User],
+    pub roles: &'a [Role],
+    pub channels: &'a [Channel],
+    pub current_user_id: Option<&'a str>,
+}
+
+/// Whether `id` can be a Discord id, so that text like `<@>` is left as typed.
+fn is_snowflake(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+}
+
+fn mention_style() -> Style {
+    Style::default()
+        .fg(Color::LightBlue)
+        .add_modifier(Modifier::BOLD)
+}
+
+/// Finds a channel in `App.channels`, where channels are nested under their category.
+pub fn find_channel<'a>(channels: &'a [Channel], id: &str) -> Option<&'a Channel> {
+    channels.iter().find_map(|c| {
+        if c.id == id {
+            Some(c)
+        } else {
+            find_channel(c.children.as_deref().unwrap_or_default(), id)
+        }
+    })
+}
+
+impl<'a> Mentions<'a> {
+    pub fn new(app: &'a App, message: &'a Message) -> Self {
+        Self {
+            users: &message.mentions,
+            roles: app
+                .context
+                .as_ref()
+                .map(|c| c.all_guild_roles.as_slice())
+                .unwrap_or_default(),
+            channels: &app.channels,
+            current_user_id: app.current_user.as_ref().map(|u| u.id.as_str()),
+        }
+    }
+
+    /// Resolves the inside of a `<...>` token to the text and style shown in its place.
+    pub fn resolve(&self, token: &str) -> Option<(String, Style)> {
+        if let Some(id) = token.strip_prefix("@&") {
+            if !is_snowflake(id) {
+                return None;
+            }
+
+            let role = self.roles.iter().find(|r| r.id == id);
+            let style = match role.map(|r| r.color) {
+                Some(color) if color != 0 => Style::default()
+                    .fg(Color::Rgb(
+                        ((color >> 16) & 0xff) as u8,
+                        ((color >> 8) & 0xff) as u8,
+                        (color & 0xff) as u8,
+                    ))
+                    .add_modifier(Modifier::BOLD),
+                _ => mention_style(),
+            };
+            let name = role.map_or("unknown-role", |r| r.name.as_str());
+            return Some((format!("@{name}"), style));
+        }
+
+        if let Some(id) = token.strip_prefix('@') {
+            let id = id.strip_prefix('!').unwrap_or(id);
+            if !is_snowflake(id) {
+                return None;
+            }
+
+            let name = self
+                .users
+                .iter()
+                .find(|u| u.id == id)
+                .map_or("unknown-user", |u| {
+                    u.global_name.as_deref().unwrap_or(&u.username)
+                });
+            let style = if self.current_user_id == Some(id) {
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                mention_style()
+            };
+            return Some((format!("@{name}"), style));
+        }
+
+        if let Some(id) = token.strip_prefix('#') {
+            if !is_snowflake(id) {
+                return None;
+            }
+
+            let name = find_channel(self.channels, id).map_or("unknown-channel", |c| &c.name);
+            return Some((format!("#{name}"), mention_style()));
+        }
+
+        let timestamp = token.strip_prefix("t:")?;
+        let (seconds, format) = timestamp.split_once(':').unwrap_or((timestamp, "f"));
+        let time = DateTime::<Utc>::from_timestamp(seconds.parse().ok()?, 0)?;
+
+        Some((
+            format_timestamp(time, format)?,
+            Style::default().fg(Color::LightCyan),
+        ))
+    }
+}
+
+/// Formats a timestamp token the way the official client displays each style.
+fn format_timestamp(time: DateTime<Utc>, format: &str) -> Option<String> {
+    let local = time.with_timezone(&Local);
+    let text = match format {
+        "t" => local.format("%H:%M").to_string(),
+        "T" => local.format("%H:%M:%S").to_string(),
+        "d" => local.format("%d/%m/%Y").to_string(),
+        "D" => local.format("%-d %B %Y").to_string(),
+        "f" => local.format("%-d %B %Y %H:%M").to_string(),
+        "F" => local.format("%A, %-d %B %Y %H:%M").to_string(),
+        "R" => relative_time(time),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+fn relative_time(time: DateTime<Utc>) -> String {
+    let seconds = (time - Utc::now()).num_seconds();
+    let distance = seconds.unsigned_abs();
+
+    let (amount, unit) = match distance {
+        0..60 => (distance, "second"),
+        60..3_600 => (distance / 60, "minute"),
+        3_600..86_400 => (distance / 3_600, "hour"),
+        86_400..2_592_000 => (distance / 86_400, "day"),
+        2_592_000..31_536_000 => (distance / 2_592_000, "month"),
+        _ => (distance / 31_536_000, "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+
+    if seconds >= 0 {
+        format!("in {amount} {unit}{plural}")
+    } else {
+        format!("{amount} {unit}{plural} ago")
+    }
+}
+
+/// Whether the message pings the current user, directly, through one of their roles or
+/// with `@everyone`.
+pub fn mentions_current_user(app: &App, message: &Message) -> bool {
+    let Some(user) = &app.current_user else {
+        return false;
+    };
+
+    message.mention_everyone
+        || message.mentions.iter().any(|u| u.id == user.id)
+        || app.context.as_ref().is_some_and(|c| {
+            message
+                .mention_roles
+                .iter()
+                .any(|r| c.user_role_ids.contains(r))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_tokens_are_left_alone() {
+        let mentions = Mentions::default();
+
+        for token in ["@", "@!", "@!!1", "@&", "@&x", "#", "#general", "@everyone"] {
+            assert_eq!(mentions.resolve(token), None, "<{token}>");
+        }
+    }
+
+    #[test]
+    fn unknown_ids_still_render_as_mentions() {
+        let mentions = Mentions::default();
+
+        let names = ["@1", "@!1", "@&2", "#3"].map(|t| mentions.resolve(t).unwrap().0);
+        assert_eq!(
+            names,
+            [
+                "@unknown-user",
+                "@unknown-user",
+                "@unknown-role",
+                "#unknown-channel"
+            ]
+        );
+    }
+}
@@ -1,5 +1,23 @@
+pub mod channels;
+pub mod command;
+pub mod completion;
 pub mod draw;
+pub mod editor;
+pub mod embed;
+pub mod emoji_picker;
 pub mod events;
+pub mod highlight;
+pub mod layout;
+pub mod login;
+pub mod markdown;
+pub mod members;
+pub mod mentions;
+pub mod profile;
+pub mod registers;
+pub mod selection;
+pub mod switcher;
+pub mod undo;
+pub mod unread;
 pub mod vim;
 
 pub use draw::draw_ui;
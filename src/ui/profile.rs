@@ -0,0 +1,138 @@
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, KeywordAction,
+    api::{User, dm::DM, guild::GuildMember},
+    ui::{events, layout, members, selection},
+};
+
+/// Actions listed under a profile, in order.
+pub const PROFILE_ACTIONS: [&str; 2] = ["Open DM", "Copy user ID"];
+
+const PROFILE_STATUS: &str = "Profile: j/k pick an action, Enter to run it, Esc to close";
+
+/// The popup describing a message author or a member of the list.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub user: User,
+    /// The user's membership of the open chat's guild, `None` in DMs or while loading
+    pub member: Option<GuildMember>,
+    /// Index into `PROFILE_ACTIONS`
+    pub action: usize,
+}
+
+/// Opens the profile of `user`, fetching their membership when the member list has not.
+pub fn open(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>, user: User) {
+    let guild_id = members::chat_guild_id(state);
+    let member = guild_id
+        .as_ref()
+        .and_then(|id| state.guild_members.get(id))
+        .and_then(|list| list.iter().find(|m| m.user.id == user.id))
+        .cloned();
+
+    if let (Some(guild_id), None) = (guild_id, &member) {
+        let api_client_clone = state.api_client.clone();
+        let tx_clone = tx_action.clone();
+        let user_id = user.id.clone();
+        tokio::spawn(async move {
+            let action = match api_client_clone.get_member(&guild_id, &user_id).await {
+                Ok(member) => AppAction::ApiUpdateProfileMember(member),
+                Err(e) => AppAction::ApiFailure("Failed to load member".to_string(), e),
+            };
+            tx_clone.send(action).await.ok();
+        });
+    }
+
+    state.profile = Some(Profile {
+        user,
+        member,
+        action: 0,
+    });
+    state.status_message = PROFILE_STATUS.to_string();
+}
+
+/// Closes the popup, back to whatever it was opened from.
+pub fn close(state: &mut MutexGuard<'_, App>) {
+    state.profile = None;
+    if state.member_selection.is_some() {
+        state.status_message = members::MEMBER_SELECTION_STATUS.to_string();
+    } else {
+        members::exit_selection(state);
+    }
+}
+
+pub fn move_action(state: &mut MutexGuard<'_, App>, n: i32) {
+    if let Some(profile) = &mut state.profile {
+        profile.action = (profile.action as i64 + i64::from(n))
+            .rem_euclid(PROFILE_ACTIONS.len() as i64) as usize;
+    }
+}
+
+/// Fills in the membership fetched by `open`, unless another profile was opened since.
+pub fn update_member(state: &mut MutexGuard<'_, App>, member: GuildMember) {
+    if let Some(profile) = &mut state.profile
+        && profile.user.id == member.user.id
+    {
+        profile.member = Some(member);
+    }
+}
+
+/// Runs the highlighted action.
+pub fn run_action(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+) -> Option<KeywordAction> {
+    let profile = state.profile.clone()?;
+
+    match PROFILE_ACTIONS[profile.action] {
+        "Open DM" => match existing_dm(state, &profile.user.id) {
+            Some(dm) => return open_dm(state, tx_action, dm),
+            None => create_dm(state, tx_action, &profile.user),
+        },
+        _ => selection::copy_to_clipboard(state, &profile.user.id, "user ID"),
+    }
+    None
+}
+
+/// The one-on-one DM with a user, if there is one.
+pub fn existing_dm(app: &App, user_id: &str) -> Option<DM> {
+    app.dms
+        .iter()
+        .find(|d| d.recipients.len() == 1 && d.recipients[0].id == user_id)
+        .cloned()
+}
+
+/// Asks Discord for the DM with a user, it is opened once it arrives.
+pub fn create_dm(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>, user: &User) {
+    state.status_message = format!("Opening DM with {}...", user.username);
+
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    let user_id = user.id.clone();
+    tokio::spawn(async move {
+        let action = match api_client_clone.create_dm(&user_id).await {
+            Ok(dm) => AppAction::ApiDMOpened(dm),
+            Err(e) => AppAction::ApiFailure("Failed to open DM".to_string(), e),
+        };
+        tx_clone.send(action).await.ok();
+    });
+}
+
+/// Leaves the open chat for the DM, adding it to the list if it is new.
+pub fn open_dm(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    dm: DM,
+) -> Option<KeywordAction> {
+    match state.dms.iter_mut().find(|d| d.id == dm.id) {
+        Some(existing) => *existing = dm.clone(),
+        None => state.dms.insert(0, dm.clone()),
+    }
+
+    state.profile = None;
+    state.member_selection = None;
+    state.message_selection = None;
+    state.replying_to = None;
+    layout::stash_draft(state);
+    events::open_chat(state, tx_action, dm.id.clone(), dm.get_name())
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::clipboard;
+
+/// Text kept by a yank or delete.
+#[derive(Debug, Clone, Default)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, put on their own lines rather than inside the current one
+    pub linewise: bool,
+}
+
+/// The vim registers of the compose box: `"` for the last yank or delete, `0` for the last
+/// yank, `1`-`9` for the last deletes, `a`-`z` named (`A`-`Z` appending), `_` discarding and
+/// `+` or `*` the system clipboard.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+}
+
+impl Registers {
+    /// Contents of a register, `None` meaning the unnamed one. The clipboard is read when
+    /// it can be, else it holds what was last copied to it.
+    pub fn get(&self, name: Option<char>) -> Option<Register> {
+        match name {
+            Some('+' | '*') => clipboard::paste()
+                .map(|text| Register {
+                    linewise: text.ends_with('\n'),
+                    text: text.strip_suffix('\n').unwrap_or(&text).to_string(),
+                })
+                .or_else(|| self.registers.get(&'+').cloned()),
+            _ => self.registers.get(&name.unwrap_or('"')).cloned(),
+        }
+    }
+
+    /// Stores yanked or deleted text in the named register, or in `0` or `1` when none is
+    /// named, and always in the unnamed register. Fails when the clipboard could not be set.
+    pub fn store(
+        &mut self,
+        name: Option<char>,
+        register: Register,
+        yank: bool,
+    ) -> std::io::Result<()> {
+        match name {
+            Some('_') => return Ok(()),
+            Some('+' | '*') => {
+                let text = if register.linewise {
+                    format!("{}\n", register.text)
+                } else {
+                    register.text.clone()
+                };
+                self.registers.insert('+', register.clone());
+                self.registers.insert('"', register);
+                return clipboard::copy(&text);
+            }
+            Some(upper @ 'A'..='Z') => {
+                let lower = upper.to_ascii_lowercase();
+                let appended = match self.registers.remove(&lower) {
+                    Some(existing) if existing.linewise || register.linewise => Register {
+                        text: format!("{}\n{}", existing.text, register.text),
+                        linewise: true,
+                    },
+                    Some(existing) => Register {
+                        text: existing.text + &register.text,
+                        linewise: false,
+                    },
+                    None => register,
+                };
+                self.registers.insert(lower, appended.clone());
+                self.registers.insert('"', appended);
+                return Ok(());
+            }
+            Some(name) if name != '"' => {
+                self.registers.insert(name, register.clone());
+            }
+            _ if yank => {
+                self.registers.insert('0', register.clone());
+            }
+            _ => {
+                for n in (1..9).rev() {
+                    let from = char::from_digit(n, 10).unwrap_or('1');
+                    let to = char::from_digit(n + 1, 10).unwrap_or('2');
+                    if let Some(shifted) = self.registers.remove(&from) {
+                        self.registers.insert(to, shifted);
+                    }
+                }
+                self.registers.insert('1', register.clone());
+            }
+        }
+        self.registers.insert('"', register);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Register {
+        Register {
+            text: text.to_string(),
+            linewise: false,
+        }
+    }
+
+    fn lines(text: &str) -> Register {
+        Register {
+            text: text.to_string(),
+            linewise: true,
+        }
+    }
+
+    fn text(registers: &Registers, name: Option<char>) -> Option<String> {
+        registers.get(name).map(|r| r.text)
+    }
+
+    #[test]
+    fn yanks_go_to_register_zero() {
+        let mut registers = Registers::default();
+        registers.store(None, chars("yanked"), true).unwrap();
+        registers.store(None, chars("deleted"), false).unwrap();
+
+        assert_eq!(text(&registers, Some('0')), Some("yanked".to_string()));
+        assert_eq!(text(&registers, None), Some("deleted".to_string()));
+    }
+
+    #[test]
+    fn deletes_shift_through_the_numbered_registers() {
+        let mut registers = Registers::default();
+        for n in 1..=10 {
+            registers.store(None, chars(&n.to_string()), false).unwrap();
+        }
+
+        assert_eq!(text(&registers, Some('1')), Some("10".to_string()));
+        assert_eq!(text(&registers, Some('9')), Some("2".to_string()));
+        assert_eq!(text(&registers, Some('0')), None);
+    }
+
+    #[test]
+    fn named_registers_and_appending() {
+        let mut registers = Registers::default();
+        registers.store(Some('a'), chars("foo"), true).unwrap();
+        registers.store(Some('A'), chars("bar"), true).unwrap();
+        assert_eq!(text(&registers, Some('a')), Some("foobar".to_string()));
+        assert_eq!(text(&registers, None), Some("foobar".to_string()));
+        // Named yanks leave register 0 alone
+        assert_eq!(text(&registers, Some('0')), None);
+
+        // Appending lines keeps them on their own lines
+        registers.store(Some('A'), lines("baz"), true).unwrap();
+        let appended = registers.get(Some('a')).unwrap();
+        assert_eq!(appended.text, "foobar\nbaz");
+        assert!(appended.linewise);
+
+        registers.store(Some('B'), chars("new"), true).unwrap();
+        assert_eq!(text(&registers, Some('b')), Some("new".to_string()));
+    }
+
+    #[test]
+    fn black_hole_register_keeps_nothing() {
+        let mut registers = Registers::default();
+        registers.store(None, chars("kept"), true).unwrap();
+        registers.store(Some('_'), chars("dropped"), false).unwrap();
+
+        assert_eq!(text(&registers, None), Some("kept".to_string()));
+        assert_eq!(text(&registers, Some('1')), None);
+        assert_eq!(text(&registers, Some('_')), None);
+    }
+}
@@ -0,0 +1,355 @@
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, AppState, ChatScroll, InputMode,
+    api::Message,
+    clipboard,
+    ui::{
+        command, emoji_picker, events, members, mentions::find_channel, profile,
+        registers::Register,
+    },
+};
+
+/// Cursor over the loaded messages of the active chat.
+#[derive(Debug, Clone, Default)]
+pub struct MessageSelection {
+    /// Index into `App.messages`, 0 being the newest message
+    pub index: usize,
+    /// Set after a first `d`, a second one deletes the message
+    pub pending_delete: bool,
+    /// Index into the selected message's attachments, picked with `a` and saved with `s`.
+    /// `None` until `a` is pressed, `s` then saves the first one.
+    pub attachment: Option<usize>,
+}
+
+pub const SELECTION_STATUS: &str = "Selecting: j/k move, r reply, e edit, dd delete, y copy, Y copy link, a/s pick/save attachment, + react, t thread, p profile, m members, Esc exit";
+
+pub fn enter_selection(state: &mut MutexGuard<'_, App>) {
+    if state.messages.is_empty() {
+        return;
+    }
+
+    state.message_selection = Some(MessageSelection::default());
+    state.status_message = SELECTION_STATUS.to_string();
+}
+
+pub const CHATTING_STATUS: &str =
+    "Chatting in channel. Press Enter to send message, Esc to return to channels.";
+
+pub fn exit_selection(state: &mut MutexGuard<'_, App>) {
+    state.message_selection = None;
+    state.status_message = CHATTING_STATUS.to_string();
+}
+
+pub fn selected_message(state: &App) -> Option<&Message> {
+    state
+        .message_selection
+        .as_ref()
+        .and_then(|s| state.messages.get(s.index))
+}
+
+/// Moves the selection `n` messages towards older history (negative moves towards newer),
+/// loading more history when the cursor runs past the oldest loaded message.
+pub fn move_selection(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>, n: i32) {
+    let len = state.messages.len();
+    let Some(selection) = &mut state.message_selection else {
+        return;
+    };
+
+    selection.pending_delete = false;
+    selection.attachment = None;
+    let target = selection.index as i64 + i64::from(n);
+    selection.index = target.clamp(0, len.saturating_sub(1) as i64) as usize;
+
+    if target >= len as i64 {
+        events::scroll_chat(state, tx_action, ChatScroll::Top);
+    }
+}
+
+pub fn is_own_message(state: &App, message: &Message) -> bool {
+    state
+        .current_user
+        .as_ref()
+        .is_some_and(|u| u.id == message.author.id)
+}
+
+/// Builds the `https://discord.com/channels/...` link of a message.
+fn message_link(state: &App, message: &Message) -> String {
+    let guild_id = find_channel(&state.channels, &message.channel_id)
+        .or_else(|| state.threads.iter().find(|t| t.id == message.channel_id))
+        .and_then(|c| c.guild_id.as_deref())
+        .unwrap_or("@me");
+
+    format!(
+        "https://discord.com/channels/{guild_id}/{}/{}",
+        message.channel_id, message.id
+    )
+}
+
+pub fn copy_to_clipboard(state: &mut MutexGuard<'_, App>, text: &str, what: &str) {
+    state.status_message = match clipboard::copy(text) {
+        Ok(_) => format!("Copied {what} to clipboard."),
+        Err(e) => format!("Failed to copy {what}: {e}"),
+    };
+}
+
+pub fn start_reply(state: &mut MutexGuard<'_, App>, message: Message) {
+    state.editing_message = None;
+    state.starting_thread = None;
+    state.status_message = format!(
+        "Replying to {}. Enter to send, Esc to cancel.",
+        message.author.username
+    );
+    state.replying_to = Some(message);
+    state.message_selection = None;
+    state.mode = InputMode::Insert;
+}
+
+pub fn start_edit(state: &mut MutexGuard<'_, App>, message: &Message) {
+    if !is_own_message(state, message) {
+        state.status_message = "You can only edit your own messages.".to_string();
+        return;
+    }
+
+    state.replying_to = None;
+    state.starting_thread = None;
+    state.input = message.content.clone().unwrap_or_default();
+    state.cursor_position = state.input.len();
+    state.editing_message = Some(message.id.clone());
+    state.message_selection = None;
+    state.mode = InputMode::Insert;
+    state.status_message = "Editing message. Enter to save, Esc to cancel.".to_string();
+}
+
+/// Handles a key press while a message is selected.
+pub async fn handle_selection_keys(
+    state: &mut MutexGuard<'_, App>,
+    c: char,
+    tx_action: &Sender<AppAction>,
+) {
+    let Some(message) = selected_message(state).cloned() else {
+        exit_selection(state);
+        return;
+    };
+    let AppState::Chatting(channel_id) = state.state.clone() else {
+        return;
+    };
+
+    let pending_delete = state
+        .message_selection
+        .as_ref()
+        .is_some_and(|s| s.pending_delete);
+    if let Some(selection) = &mut state.message_selection {
+        selection.pending_delete = false;
+    }
+
+    match c {
+        'j' => move_selection(state, tx_action, -1),
+        'k' => move_selection(state, tx_action, 1),
+        'r' => start_reply(state, message),
+        't' => {
+            state.replying_to = None;
+            state.editing_message = None;
+            state.input.clear();
+            state.cursor_position = 0;
+            state.starting_thread = Some(message.id.clone());
+            state.message_selection = None;
+            state.mode = InputMode::Insert;
+            state.status_message =
+                "Name the new thread. Enter to create it, Esc to cancel.".to_string();
+        }
+        'e' => start_edit(state, &message),
+        'd' => {
+            if !is_own_message(state, &message) {
+                state.status_message = "You can only delete your own messages.".to_string();
+                return;
+            }
+
+            if !pending_delete {
+                if let Some(selection) = &mut state.message_selection {
+                    selection.pending_delete = true;
+                }
+                state.status_message = "Press d again to delete this message.".to_string();
+                return;
+            }
+
+            state.status_message = "Deleting message...".to_string();
+            let api_client_clone = state.api_client.clone();
+            let tx_clone = tx_action.clone();
+            tokio::spawn(async move {
+                let action = match api_client_clone
+                    .delete_message(&channel_id, &message.id)
+                    .await
+                {
+                    Ok(()) => AppAction::ApiMessageDeleted,
+                    Err(e) => AppAction::ApiFailure("Failed to delete message".to_string(), e),
+                };
+                tx_clone.send(action).await.ok();
+            });
+        }
+        'y' => {
+            let content = message.content.clone().unwrap_or_default();
+            // Also yanked like in the input, for `p` to put it there
+            if let Some(vim_state) = &mut state.vim_state {
+                let register = Register {
+                    text: content.clone(),
+                    linewise: false,
+                };
+                vim_state.registers.store(None, register, true).ok();
+            }
+            copy_to_clipboard(state, &content, "message");
+        }
+        'Y' => {
+            let link = message_link(state, &message);
+            copy_to_clipboard(state, &link, "message link");
+        }
+        'a' => {
+            if message.attachments.is_empty() {
+                state.status_message = "This message has no attachments.".to_string();
+                return;
+            }
+
+            if let Some(selection) = &mut state.message_selection {
+                let index = selection
+                    .attachment
+                    .map_or(0, |i| (i + 1) % message.attachments.len());
+                selection.attachment = Some(index);
+                state.status_message = format!(
+                    "Attachment {}/{}: {}. Press s to save it.",
+                    index + 1,
+                    message.attachments.len(),
+                    message.attachments[index].filename
+                );
+            }
+        }
+        '+' => emoji_picker::start_reaction(state, channel_id, message.id),
+        'p' => profile::open(state, tx_action, message.author),
+        'm' => members::enter_selection(state, tx_action),
+        ':' if state.vim_mode => command::open(state),
+        's' => {
+            let index = state
+                .message_selection
+                .as_ref()
+                .and_then(|s| s.attachment)
+                .unwrap_or_default();
+            let Some(attachment) = message.attachments.get(index).cloned() else {
+                state.status_message = "This message has no attachments.".to_string();
+                return;
+            };
+
+            state.status_message = format!("Downloading {}...", attachment.filename);
+            let api_client_clone = state.api_client.clone();
+            let download_dir = state.download_dir.clone();
+            let tx_clone = tx_action.clone();
+            tokio::spawn(async move {
+                let action = match api_client_clone
+                    .download_attachment(&attachment, &download_dir)
+                    .await
+                {
+                    Ok(path) => AppAction::AttachmentDownloaded(path),
+                    Err(e) => AppAction::ApiFailure(
+                        format!("Failed to download {}", attachment.filename),
+                        e,
+                    ),
+                };
+                tx_clone.send(action).await.ok();
+            });
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::sync::{Mutex, mpsc};
+
+    use super::*;
+
+    fn message(id: &str, channel_id: &str, attachments: &[&str]) -> Message {
+        let attachments: Vec<_> = attachments
+            .iter()
+            .map(|name| json!({"id": name, "filename": name, "size": 1, "url": ""}))
+            .collect();
+        serde_json::from_value(json!({
+            "id": id,
+            "channel_id": channel_id,
+            "author": {"id": "9", "username": "alice"},
+            "content": "hi",
+            "timestamp": "2024-01-01T00:00:00+00:00",
+            "edited_timestamp": null,
+            "attachments": attachments,
+        }))
+        .unwrap()
+    }
+
+    fn chatting(messages: Vec<Message>) -> App {
+        let mut app = App::for_tests();
+        app.state = AppState::Chatting("1".to_string());
+        app.messages = messages;
+        app.message_selection = Some(MessageSelection::default());
+        app
+    }
+
+    #[tokio::test]
+    async fn attachments_are_picked_from_the_first() {
+        let app = Mutex::new(chatting(vec![message("5", "1", &["a.png", "b.png"])]));
+        let (tx, _rx) = mpsc::channel(32);
+        let mut state = app.lock().await;
+
+        handle_selection_keys(&mut state, 'a', &tx).await;
+        assert_eq!(
+            state.status_message,
+            "Attachment 1/2: a.png. Press s to save it."
+        );
+        handle_selection_keys(&mut state, 'a', &tx).await;
+        assert_eq!(
+            state.status_message,
+            "Attachment 2/2: b.png. Press s to save it."
+        );
+        handle_selection_keys(&mut state, 'a', &tx).await;
+        assert_eq!(
+            state.status_message,
+            "Attachment 1/2: a.png. Press s to save it."
+        );
+    }
+
+    #[tokio::test]
+    async fn moving_past_the_oldest_message_does_not_wait_for_the_channel() {
+        let app = Mutex::new(chatting(vec![message("5", "1", &[])]));
+        let (tx, _rx) = mpsc::channel(1);
+        tx.send(AppAction::Tick).await.unwrap();
+        let mut state = app.lock().await;
+        state.chat_max_scroll = 10;
+
+        move_selection(&mut state, &tx, 1);
+
+        assert_eq!(state.chat_scroll, 10);
+    }
+
+    #[test]
+    fn thread_links_use_the_guild() {
+        let mut app = chatting(Vec::new());
+        app.threads = vec![
+            serde_json::from_value(json!({
+                "id": "7",
+                "name": "thread",
+                "type": 11,
+                "guild_id": "3",
+                "parent_id": "1",
+                "children": null,
+                "last_message_id": null,
+            }))
+            .unwrap(),
+        ];
+
+        assert_eq!(
+            message_link(&app, &message("5", "7", &[])),
+            "https://discord.com/channels/3/7/5"
+        );
+        assert_eq!(
+            message_link(&app, &message("5", "8", &[])),
+            "https://discord.com/channels/@me/8/5"
+        );
+    }
+}
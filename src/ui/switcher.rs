@@ -0,0 +1,250 @@
+use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, KeywordAction,
+    api::{
+        Channel, DM, Guild,
+        channel::{GUILD_ANNOUNCEMENT, GUILD_FORUM, GUILD_TEXT},
+        snowflake_time,
+    },
+    ui::{events, layout},
+};
+
+/// How much recent activity outweighs how well the name matches.
+const ACTIVITY_WEIGHT: f64 = 2.0;
+
+/// The Ctrl-K overlay, typing filters every DM, guild and channel.
+#[derive(Debug, Clone, Default)]
+pub struct Switcher {
+    pub query: String,
+    pub selection: usize,
+}
+
+/// Somewhere the switcher can jump to.
+#[derive(Debug, Clone)]
+pub enum Target {
+    DM(DM),
+    Guild(Guild),
+    /// A channel and the name of its guild
+    Channel(Channel, String),
+}
+
+impl Target {
+    pub fn name(&self) -> String {
+        match self {
+            Target::DM(dm) => dm.get_name(),
+            Target::Guild(guild) => guild.name.clone(),
+            Target::Channel(channel, _) => channel.name.clone(),
+        }
+    }
+
+    /// The channel whose unread state the entry shows, `None` for guilds.
+    pub fn channel_id(&self) -> Option<&str> {
+        match self {
+            Target::DM(dm) => Some(&dm.id),
+            Target::Guild(_) => None,
+            Target::Channel(channel, _) => Some(&channel.id),
+        }
+    }
+
+    /// Text matched against the query besides the name, so `rust gen` finds #general of
+    /// the Rust server.
+    fn qualified_name(&self) -> Option<String> {
+        match self {
+            Target::Channel(channel, guild_name) => Some(format!("{guild_name} {}", channel.name)),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the flat channel list of a guild for the switcher.
+pub fn cache_channels(app: &mut App, channels: &[Channel]) {
+    if let Some(guild_id) = channels.iter().find_map(|c| c.guild_id.clone()) {
+        app.guild_channels.insert(guild_id, channels.to_vec());
+    }
+}
+
+/// Opens the switcher and fetches the channels of guilds not cached yet.
+pub fn open(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>) {
+    state.switcher = Some(Switcher::default());
+
+    let missing: Vec<String> = state
+        .guilds
+        .iter()
+        .filter(|g| !state.guild_channels.contains_key(&g.id))
+        .map(|g| g.id.clone())
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+
+    let api_client_clone = state.api_client.clone();
+    let tx_clone = tx_action.clone();
+    tokio::spawn(async move {
+        for guild_id in missing {
+            let action = match api_client_clone.get_guild_channels(&guild_id).await {
+                Ok(channels) => AppAction::ApiUpdateGuildActivity(channels),
+                Err(e) => AppAction::ApiFailure("Failed to load channels".to_string(), e),
+            };
+            tx_clone.send(action).await.ok();
+        }
+    });
+}
+
+/// Newest message of a channel, or of any channel of a guild, scored like emoji usage: a
+/// message from today weighs about as much as ten from last month.
+fn activity(app: &App, target: &Target) -> f64 {
+    let newest = match target {
+        Target::Guild(guild) => app
+            .channel_guilds
+            .iter()
+            .filter(|(_, guild_id)| **guild_id == guild.id)
+            .filter_map(|(channel_id, _)| app.last_message_ids.get(channel_id))
+            .max_by_key(|id| id.parse::<u64>().unwrap_or_default()),
+        _ => target
+            .channel_id()
+            .and_then(|id| app.last_message_ids.get(id)),
+    };
+
+    newest
+        .and_then(|id| snowflake_time(id))
+        .map_or(0.0, |time| {
+            let age_days = (chrono::Utc::now() - time).num_seconds().max(0) as f64 / 86_400.0;
+            10.0 / (1.0 + age_days / 3.0)
+        })
+}
+
+/// Channels of the guild that can be opened from the switcher. Those of the open guild are
+/// filtered by permissions, other guilds have none loaded.
+fn guild_targets(app: &App, guild: &Guild) -> Vec<Target> {
+    let is_loaded = layout::loaded_guild(app).as_ref() == Some(&guild.id);
+    let threads = app.threads.iter().filter(|_| is_loaded);
+
+    app.guild_channels
+        .get(&guild.id)
+        .into_iter()
+        .flatten()
+        .chain(threads)
+        .filter(|c| {
+            matches!(
+                c.channel_type,
+                GUILD_TEXT | GUILD_ANNOUNCEMENT | GUILD_FORUM
+            ) || c.is_thread()
+        })
+        .filter(|c| match &app.context {
+            Some(context) if is_loaded => c.is_readable(context),
+            _ => true,
+        })
+        .map(|c| Target::Channel(c.clone(), guild.name.clone()))
+        .collect()
+}
+
+/// Everything matching the switcher's query.
+pub fn targets(app: &App) -> Vec<Target> {
+    match &app.switcher {
+        Some(switcher) => ranked(app, &switcher.query),
+        None => Vec::new(),
+    }
+}
+
+/// Everything matching `query`, best first: fuzzy matches on the name are boosted by how
+/// recently something was said there.
+pub fn ranked(app: &App, query: &str) -> Vec<Target> {
+    let matcher = SkimMatcherV2::default();
+
+    let all = app
+        .dms
+        .iter()
+        .map(|dm| Target::DM(dm.clone()))
+        .chain(app.guilds.iter().map(|g| Target::Guild(g.clone())))
+        .chain(app.guilds.iter().flat_map(|g| guild_targets(app, g)));
+
+    let mut scored: Vec<(f64, Target)> = all
+        .filter_map(|target| {
+            let activity = activity(app, &target);
+            if query.is_empty() {
+                return Some((activity, target));
+            }
+
+            let best = [Some(target.name()), target.qualified_name()]
+                .into_iter()
+                .flatten()
+                .filter_map(|text| {
+                    let score = matcher.fuzzy_match(&text, query)?;
+                    let bonus = if text.to_lowercase().starts_with(&query.to_lowercase()) {
+                        50
+                    } else {
+                        0
+                    };
+                    Some(score + bonus)
+                })
+                .max()?;
+
+            Some((best as f64 + activity * ACTIVITY_WEIGHT, target))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, target)| target).collect()
+}
+
+pub fn move_selection(state: &mut MutexGuard<'_, App>, n: i32) {
+    let len = targets(state).len();
+    if let Some(switcher) = &mut state.switcher
+        && len > 0
+    {
+        switcher.selection =
+            (switcher.selection as i64 + i64::from(n)).rem_euclid(len as i64) as usize;
+    }
+}
+
+/// Closes the switcher and opens the selected entry.
+pub fn accept(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+) -> Option<KeywordAction> {
+    let selection = state.switcher.as_ref()?.selection;
+    let target = targets(state).into_iter().nth(selection);
+    state.switcher = None;
+
+    jump(state, tx_action, target?)
+}
+
+/// Opens a target. Picking a channel of another guild loads that guild first, from the
+/// cached channels.
+pub fn jump(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    target: Target,
+) -> Option<KeywordAction> {
+    match target {
+        Target::DM(dm) => events::open_chat(state, tx_action, dm.id.clone(), dm.get_name()),
+        Target::Guild(guild) => {
+            events::open_guild(state, tx_action, guild.id, guild.name);
+            None
+        }
+        Target::Channel(channel, _) => {
+            let guild_id = channel.guild_id.clone()?;
+            if layout::loaded_guild(state).as_ref() != Some(&guild_id) {
+                let channels = state
+                    .guild_channels
+                    .get(&guild_id)
+                    .cloned()
+                    .unwrap_or_default();
+                state.channels =
+                    Channel::filter_channels_by_categories(channels).unwrap_or_default();
+                state.threads.clear();
+                state.context = None;
+
+                let api_client_clone = state.api_client.clone();
+                let tx_clone = tx_action.clone();
+                tokio::spawn(async move {
+                    events::fetch_guild_details(&api_client_clone, &tx_clone, &guild_id).await;
+                });
+            }
+
+            events::open_channel(state, tx_action, &channel)
+        }
+    }
+}
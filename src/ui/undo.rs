@@ -0,0 +1,250 @@
+use tokio::sync::MutexGuard;
+
+use crate::{App, AppState};
+
+/// How many changes of the compose box can be undone.
+const UNDO_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    input: String,
+    cursor_position: usize,
+}
+
+/// What kind of edit is in progress. Runs of typing or deleting and whole vim insert
+/// sessions are undone at once, anything else on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditKind {
+    Typing,
+    Deleting,
+    /// A vim insert session, from the command entering it to Esc
+    Insert,
+    Other,
+}
+
+/// Linear undo and redo of the compose box.
+#[derive(Debug, Clone, Default)]
+pub struct UndoHistory {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The input before the change in progress
+    open: Option<(Snapshot, EditKind)>,
+}
+
+fn snapshot(app: &App) -> Snapshot {
+    Snapshot {
+        input: app.input.clone(),
+        cursor_position: app.cursor_position,
+    }
+}
+
+/// Whether the input is a message being written, rather than a list filter.
+fn is_composing(app: &App) -> bool {
+    matches!(
+        app.state,
+        AppState::Chatting(_) | AppState::EmojiSelection(_) | AppState::MentionSelection(_)
+    )
+}
+
+/// Called before an edit: starts a new change unless it continues the one in progress.
+pub fn record(state: &mut MutexGuard<'_, App>, kind: EditKind) {
+    if !is_composing(state) {
+        return;
+    }
+    if let Some((_, open_kind)) = &state.undo_history.open
+        && *open_kind == kind
+        && kind != EditKind::Other
+    {
+        return;
+    }
+
+    commit(state);
+    let before = snapshot(state);
+    state.undo_history.open = Some((before, kind));
+}
+
+/// Turns the change in progress into an insert session, so the typing after a vim command
+/// entering insert mode is undone with it.
+pub fn continue_as_insert(state: &mut MutexGuard<'_, App>) {
+    if let Some((_, kind)) = &mut state.undo_history.open {
+        *kind = EditKind::Insert;
+    }
+}
+
+/// Ends the change in progress, keeping it if it changed anything.
+pub fn commit(state: &mut MutexGuard<'_, App>) {
+    let Some((before, _)) = state.undo_history.open.take() else {
+        return;
+    };
+    if before.input == state.input {
+        return;
+    }
+
+    let history = &mut state.undo_history;
+    history.undo.push(before);
+    if history.undo.len() > UNDO_LIMIT {
+        history.undo.remove(0);
+    }
+    history.redo.clear();
+}
+
+/// Forgets every change, when the input starts over with another message.
+pub fn clear(state: &mut MutexGuard<'_, App>) {
+    state.undo_history = UndoHistory::default();
+}
+
+fn restore(state: &mut MutexGuard<'_, App>, snapshot: Snapshot) {
+    state.input = snapshot.input;
+    state.cursor_position = snapshot.cursor_position.min(state.input.len());
+    while !state.input.is_char_boundary(state.cursor_position) {
+        state.cursor_position -= 1;
+    }
+}
+
+/// Reverts the last `count` changes, returns how many there were.
+pub fn undo(state: &mut MutexGuard<'_, App>, count: usize) -> usize {
+    commit(state);
+    for undone in 0..count {
+        let Some(before) = state.undo_history.undo.pop() else {
+            return undone;
+        };
+        let current = snapshot(state);
+        state.undo_history.redo.push(current);
+        restore(state, before);
+    }
+    count
+}
+
+/// Applies again the last `count` undone changes, returns how many there were.
+pub fn redo(state: &mut MutexGuard<'_, App>, count: usize) -> usize {
+    commit(state);
+    for redone in 0..count {
+        let Some(after) = state.undo_history.redo.pop() else {
+            return redone;
+        };
+        let current = snapshot(state);
+        state.undo_history.undo.push(current);
+        restore(state, after);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    fn chat() -> Mutex<App> {
+        let mut app = App::for_tests();
+        app.state = AppState::Chatting("1".to_string());
+        Mutex::new(app)
+    }
+
+    fn type_text(state: &mut MutexGuard<'_, App>, kind: EditKind, text: &str) {
+        for c in text.chars() {
+            record(state, kind);
+            state.input.push(c);
+            state.cursor_position = state.input.len();
+        }
+    }
+
+    fn delete(state: &mut MutexGuard<'_, App>, count: usize) {
+        for _ in 0..count {
+            record(state, EditKind::Deleting);
+            state.input.pop();
+            state.cursor_position = state.input.len();
+        }
+    }
+
+    #[test]
+    fn runs_of_typing_and_deleting_are_undone_at_once() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        type_text(&mut state, EditKind::Typing, "hello");
+        delete(&mut state, 2);
+
+        assert_eq!(undo(&mut state, 1), 1);
+        assert_eq!(state.input, "hello");
+        assert_eq!(undo(&mut state, 1), 1);
+        assert_eq!(state.input, "");
+        assert_eq!(state.cursor_position, 0);
+        assert_eq!(undo(&mut state, 1), 0);
+
+        assert_eq!(redo(&mut state, 2), 2);
+        assert_eq!(state.input, "hel");
+        assert_eq!(state.cursor_position, 3);
+    }
+
+    #[test]
+    fn other_edits_are_undone_one_by_one() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        type_text(&mut state, EditKind::Other, "ab");
+
+        assert_eq!(undo(&mut state, 1), 1);
+        assert_eq!(state.input, "a");
+        // Counts stop at the oldest change
+        assert_eq!(undo(&mut state, 5), 1);
+        assert_eq!(state.input, "");
+    }
+
+    #[test]
+    fn insert_sessions_take_the_command_that_started_them() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        state.input = "one".to_string();
+        state.cursor_position = 3;
+
+        // `o` opens a line, then the typing goes on in the same change
+        record(&mut state, EditKind::Other);
+        state.input.push('\n');
+        continue_as_insert(&mut state);
+        type_text(&mut state, EditKind::Insert, "two");
+        commit(&mut state);
+
+        undo(&mut state, 1);
+        assert_eq!(state.input, "one");
+    }
+
+    #[test]
+    fn edits_that_change_nothing_are_not_kept() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        record(&mut state, EditKind::Other);
+        commit(&mut state);
+        assert_eq!(undo(&mut state, 1), 0);
+    }
+
+    #[test]
+    fn a_new_change_drops_the_redo_history() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        type_text(&mut state, EditKind::Other, "ab");
+        undo(&mut state, 1);
+        type_text(&mut state, EditKind::Other, "c");
+
+        assert_eq!(redo(&mut state, 1), 0);
+        assert_eq!(state.input, "ac");
+    }
+
+    #[test]
+    fn history_is_limited() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        type_text(&mut state, EditKind::Other, &"a".repeat(UNDO_LIMIT + 5));
+
+        assert_eq!(undo(&mut state, usize::MAX), UNDO_LIMIT);
+        assert_eq!(state.input, "aaaaa");
+    }
+
+    #[test]
+    fn list_filters_are_not_recorded() {
+        let app = chat();
+        let mut state = app.try_lock().unwrap();
+        state.state = AppState::SelectingDM;
+        type_text(&mut state, EditKind::Typing, "al");
+        assert_eq!(undo(&mut state, 1), 0);
+        assert_eq!(state.input, "al");
+    }
+}
@@ -0,0 +1,251 @@
+use tokio::sync::{MutexGuard, mpsc::Sender};
+
+use crate::{
+    App, AppAction, KeywordAction,
+    api::{Channel, Message, channel::GUILD_CATEGORY},
+    config::ChannelReadState,
+    ui::{channels, events, layout, mentions},
+};
+
+/// Orders ids numerically, as strings of different lengths don't compare right.
+pub fn snowflake(id: &str) -> u64 {
+    id.parse().unwrap_or_default()
+}
+
+/// Whether the channel got messages since it was last read.
+pub fn is_unread(app: &App, channel_id: &str) -> bool {
+    match (
+        app.last_message_ids.get(channel_id),
+        app.read_state.channels.get(channel_id),
+    ) {
+        (Some(latest), Some(read)) => snowflake(latest) > snowflake(&read.last_read),
+        _ => false,
+    }
+}
+
+pub fn mention_count(app: &App, channel_id: &str) -> u32 {
+    app.read_state
+        .channels
+        .get(channel_id)
+        .map_or(0, |r| r.mentions)
+}
+
+/// Whether any channel of the guild is unread, and how many mentions they hold.
+pub fn guild_status(app: &App, guild_id: &str) -> (bool, u32) {
+    app.channel_guilds
+        .iter()
+        .filter(|(_, guild)| guild.as_str() == guild_id)
+        .fold((false, 0), |(unread, mentions), (channel_id, _)| {
+            (
+                unread || is_unread(app, channel_id),
+                mentions + mention_count(app, channel_id),
+            )
+        })
+}
+
+/// Whether any DM is unread, and how many mentions they hold.
+pub fn dm_status(app: &App) -> (bool, u32) {
+    app.dms.iter().fold((false, 0), |(unread, mentions), dm| {
+        (
+            unread || is_unread(app, &dm.id),
+            mentions + mention_count(app, &dm.id),
+        )
+    })
+}
+
+/// Records the newest message of a channel. Channels never seen before start out read so
+/// the first launch doesn't flag every channel. Returns whether the read state changed.
+fn track(app: &mut App, channel_id: &str, guild_id: Option<&str>, message_id: &str) -> bool {
+    if let Some(guild_id) = guild_id {
+        app.channel_guilds
+            .insert(channel_id.to_string(), guild_id.to_string());
+    }
+
+    let is_newer = app
+        .last_message_ids
+        .get(channel_id)
+        .is_none_or(|latest| snowflake(message_id) > snowflake(latest));
+    if is_newer {
+        app.last_message_ids
+            .insert(channel_id.to_string(), message_id.to_string());
+    }
+
+    if app.read_state.channels.contains_key(channel_id) {
+        return false;
+    }
+    app.read_state.channels.insert(
+        channel_id.to_string(),
+        ChannelReadState {
+            last_read: message_id.to_string(),
+            mentions: 0,
+        },
+    );
+    true
+}
+
+fn track_all(app: &mut App, channels: &[Channel]) -> bool {
+    let mut changed = false;
+    for channel in channels {
+        if let Some(message_id) = &channel.last_message_id {
+            changed |= track(app, &channel.id, channel.guild_id.as_deref(), message_id);
+        }
+        if let Some(children) = &channel.children {
+            changed |= track_all(app, children);
+        }
+    }
+    changed
+}
+
+/// Records the newest message of every channel in the list, including those nested in
+/// categories.
+pub fn track_channels(app: &mut App, channels: &[Channel]) {
+    if track_all(app, channels) {
+        app.read_state.dirty = true;
+    }
+}
+
+/// Records the newest message of every DM.
+pub fn track_dms(app: &mut App) {
+    let dms: Vec<(String, String)> = app
+        .dms
+        .iter()
+        .filter_map(|d| Some((d.id.clone(), d.last_message_id.clone()?)))
+        .collect();
+
+    let mut changed = false;
+    for (channel_id, message_id) in dms {
+        changed |= track(app, &channel_id, None, &message_id);
+    }
+    if changed {
+        app.read_state.dirty = true;
+    }
+}
+
+/// Marks everything in the channel as read, acking it on Discord when configured to.
+pub fn mark_read(state: &mut MutexGuard<'_, App>, tx_action: &Sender<AppAction>, channel_id: &str) {
+    let Some(latest) = state.last_message_ids.get(channel_id).cloned() else {
+        return;
+    };
+    let already_read = state
+        .read_state
+        .channels
+        .get(channel_id)
+        .is_some_and(|r| r.last_read == latest && r.mentions == 0);
+    if already_read {
+        return;
+    }
+
+    state.read_state.channels.insert(
+        channel_id.to_string(),
+        ChannelReadState {
+            last_read: latest.clone(),
+            mentions: 0,
+        },
+    );
+    state.read_state.dirty = true;
+
+    if state.ack_messages {
+        let api_client_clone = state.api_client.clone();
+        let tx_clone = tx_action.clone();
+        let channel_id = channel_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = api_client_clone.ack_message(&channel_id, &latest).await {
+                tx_clone
+                    .send(AppAction::ApiFailure(
+                        "Failed to mark channel read".to_string(),
+                        e,
+                    ))
+                    .await
+                    .ok();
+            }
+        });
+    }
+}
+
+/// Records a message received over the gateway. Messages of the open chat are read right
+/// away, elsewhere mentions of the current user are counted.
+pub fn record_message(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+    message: &Message,
+    is_active_channel: bool,
+) {
+    let first_seen = track(
+        state,
+        &message.channel_id,
+        message.guild_id.as_deref(),
+        &message.id,
+    );
+
+    if is_active_channel {
+        mark_read(state, tx_action, &message.channel_id);
+        return;
+    }
+
+    let is_own = state
+        .current_user
+        .as_ref()
+        .is_some_and(|u| u.id == message.author.id);
+    let mentioned = !is_own && mentions::mentions_current_user(state, message);
+    if let Some(read) = state.read_state.channels.get_mut(&message.channel_id) {
+        // Unlike channels found in a listing, this one just got a message nobody has read
+        if first_seen && !is_own {
+            read.last_read = "0".to_string();
+        }
+        if mentioned {
+            read.mentions += 1;
+        }
+    }
+    if first_seen || mentioned {
+        state.read_state.dirty = true;
+    }
+}
+
+/// Writes the read state if it changed, which happens every few seconds, when a chat is
+/// opened and on exit.
+pub fn save(state: &mut MutexGuard<'_, App>) {
+    if let Err(e) = state.read_state.flush() {
+        state.status_message = format!("Failed to save which messages were read: {e}");
+    }
+}
+
+/// Opens the first unread DM, then the first unread channel of the open guild, and falls
+/// back to the channel list of the first guild with unreads.
+pub fn jump_to_next_unread(
+    state: &mut MutexGuard<'_, App>,
+    tx_action: &Sender<AppAction>,
+) -> Option<KeywordAction> {
+    let active = layout::visible_chat(state).map(str::to_string);
+    let is_candidate = |app: &App, id: &str| active.as_deref() != Some(id) && is_unread(app, id);
+
+    let dm = state
+        .dms
+        .iter()
+        .find(|d| is_candidate(state, &d.id))
+        .map(|d| (d.id.clone(), d.get_name()));
+    let channel = || {
+        channels::channel_rows(state, "")
+            .into_iter()
+            .map(|row| row.channel)
+            .chain(&state.threads)
+            .find(|c| c.channel_type != GUILD_CATEGORY && is_candidate(state, &c.id))
+            .map(|c| (c.id.clone(), c.name.clone()))
+    };
+
+    if let Some((id, name)) = dm.or_else(channel) {
+        return events::open_chat(state, tx_action, id, name);
+    }
+
+    let loaded_guild = layout::loaded_guild(state);
+    let guild = state
+        .guilds
+        .iter()
+        .find(|g| Some(&g.id) != loaded_guild.as_ref() && guild_status(state, &g.id).0)
+        .map(|g| (g.id.clone(), g.name.clone()));
+
+    match guild {
+        Some((id, name)) => events::open_guild(state, tx_action, id, name),
+        None => state.status_message = "No unread messages.".to_string(),
+    }
+    None
+}
@@ -2,39 +2,224 @@ use std::time::Instant;
 use tokio::sync::{MutexGuard, mpsc::Sender};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::{App, AppAction, AppState, InputMode};
+use crate::{
+    App, AppAction, AppState, ChatScroll, InputMode,
+    ui::{
+        command, events,
+        registers::{Register, Registers},
+        selection,
+        undo::{self, EditKind},
+    },
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VimOperator {
     Delete,
-    _Change,
-    _Yank,
+    Change,
+    Yank,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VimMotion {
-    WordForward,
-    WordBackward,
-    _Line,
-    _CharRight,
-    _CharLeft,
-    _StartOfLine,
-    _EndOfLine,
+    CharLeft,
+    CharRight,
+    LineDown,
+    LineUp,
+    /// `w`, or `W` for whitespace separated words
+    WordForward {
+        big: bool,
+    },
+    WordBackward {
+        big: bool,
+    },
+    WordEnd {
+        big: bool,
+    },
+    StartOfLine,
+    FirstNonBlank,
+    EndOfLine,
+    Find(FindKind, char),
+    /// `;`
+    RepeatFind,
+    /// `,`
+    RepeatFindReverse,
+    FirstLine,
+    LastLine,
+}
+
+/// `f`, `F`, `t` and `T`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FindKind {
+    Forward,
+    Backward,
+    TillForward,
+    TillBackward,
+}
+
+impl FindKind {
+    fn reversed(self) -> Self {
+        match self {
+            FindKind::Forward => FindKind::Backward,
+            FindKind::Backward => FindKind::Forward,
+            FindKind::TillForward => FindKind::TillBackward,
+            FindKind::TillBackward => FindKind::TillForward,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextObjectKind {
+    Word,
+    Quote(char),
+    Bracket(char, char),
+}
+
+/// `iw`, `a"` and the like: `around` takes the delimiters or trailing blanks too.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextObject {
+    pub kind: TextObjectKind,
+    pub around: bool,
+}
+
+/// What an operator applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Motion(VimMotion),
+    Object(TextObject),
+    /// Doubled operator like `dd`, whole lines
+    Lines,
+    /// The visual mode selection
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Insert,
+    InsertLineStart,
+    Append,
+    AppendLineEnd,
+    OpenBelow,
+    OpenAbove,
+    Put {
+        before: bool,
+    },
+    Replace(char),
+    ToggleCase,
+    Join,
+    CommandLine,
+    Undo,
+    /// `.`
+    Repeat,
+    /// `v` or `V`, starting, switching or leaving visual mode
+    Visual {
+        linewise: bool,
+    },
+    /// `o` in visual mode, moving the cursor to the other end of the selection
+    SwapEnds,
+    /// A text object in visual mode, selected whole
+    SelectObject(TextObject),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Move(VimMotion),
+    Operate(VimOperator, Target),
+    Act(Action),
+}
+
+/// A whole normal mode command: `"a3dw` is register `a`, count 3, delete a word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NormalCommand {
+    register: Option<char>,
+    count: Option<usize>,
+    kind: Kind,
+}
+
+impl NormalCommand {
+    /// Whether the command edits the input, the ones `.` repeats.
+    fn changes_text(&self) -> bool {
+        match self.kind {
+            Kind::Move(_) => false,
+            Kind::Operate(operator, _) => operator != VimOperator::Yank,
+            Kind::Act(action) => !matches!(
+                action,
+                Action::CommandLine
+                    | Action::Undo
+                    | Action::Repeat
+                    | Action::Visual { .. }
+                    | Action::SwapEnds
+                    | Action::SelectObject(_)
+            ),
+        }
+    }
+}
+
+/// The keys of the last change and the text typed in the insert session it started.
+#[derive(Debug, Clone)]
+struct LastChange {
+    keys: String,
+    inserted: Option<String>,
+}
+
+enum Parsed<T> {
+    /// More keys are needed
+    Incomplete,
+    /// No command starts with these keys
+    Invalid,
+    Done(T),
+}
+
+/// How the text between the cursor and the end of a motion is taken by an operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+/// Largest count a command takes, higher ones are lowered to it.
+const MAX_COUNT: usize = 9999;
+
+/// Most characters a put inserts, its count is lowered to stay under it.
+const MAX_PUT_CHARS: usize = 100_000;
+
+const VISUAL_STATUS: &str = "-- VISUAL -- d/y/c on the selection, \"+y to copy it, Esc to cancel";
+const VISUAL_LINE_STATUS: &str =
+    "-- VISUAL LINE -- d/y/c on the lines, \"+y to copy them, Esc to cancel";
+
+/// A visual mode selection, from the anchor to the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Visual {
+    /// Character index where the selection started
+    pub anchor: usize,
+    /// `V`, whole lines
+    pub linewise: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct VimState {
-    pub operator: Option<VimOperator>,
+    /// Keys of the command being typed, run once they form a whole command
     pub pending_keys: String,
     pub last_action_time: Instant,
+    /// Last `f`, `F`, `t` or `T`, repeated by `;` and `,`
+    last_find: Option<(FindKind, char)>,
+    pub registers: Registers,
+    last_change: Option<LastChange>,
+    /// Where the insert session started by the last change began
+    insert_start: Option<usize>,
+    pub visual: Option<Visual>,
 }
 
 impl Default for VimState {
     fn default() -> Self {
         Self {
-            operator: None,
             pending_keys: String::new(),
             last_action_time: Instant::now(),
+            last_find: None,
+            registers: Registers::default(),
+            last_change: None,
+            insert_start: None,
+            visual: None,
         }
     }
 }
@@ -54,202 +239,897 @@ pub fn clamp_cursor(state: &mut MutexGuard<'_, App>) {
     }
 }
 
-fn get_motion_range(state: &MutexGuard<'_, App>, motion: VimMotion) -> (usize, usize) {
-    let start = state.cursor_position;
-    let len = state.input.len();
-    let input = &state.input;
-
-    let end = match motion {
-        VimMotion::WordForward => {
-            let mut pos = start;
-            if let Some(c) = input[pos..].chars().next() {
-                if c.is_whitespace() {
-                    // Cursor is on whitespace: skip whitespace only, stop at start of next word
-                    while pos < len {
-                        if let Some(c) = input[pos..].chars().next()
-                            && c.is_whitespace()
-                        {
-                            pos += c.len_utf8();
-                        } else {
-                            break;
-                        }
-                    }
-                } else {
-                    // Cursor is on a word/non-whitespace: skip rest of this word...
-                    while pos < len {
-                        if let Some(c) = input[pos..].chars().next()
-                            && !c.is_whitespace()
-                        {
-                            pos += c.len_utf8();
-                        } else {
-                            break;
-                        }
-                    }
-                    // ...then skip following whitespace to land at start of next word
-                    while pos < len {
-                        if let Some(c) = input[pos..].chars().next()
-                            && c.is_whitespace()
-                        {
-                            pos += c.len_utf8();
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            }
-            pos.min(len)
+/// Drops a half typed command, returns whether there was one.
+pub fn cancel_pending(state: &mut MutexGuard<'_, App>) -> bool {
+    match &mut state.vim_state {
+        Some(vim_state) if !vim_state.pending_keys.is_empty() => {
+            vim_state.pending_keys.clear();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Leaves visual mode, returns whether it was on.
+pub fn exit_visual(state: &mut MutexGuard<'_, App>) -> bool {
+    let visual = state.vim_state.as_mut().and_then(|v| v.visual.take());
+    if visual.is_some() {
+        state.status_message = selection::CHATTING_STATUS.to_string();
+    }
+    visual.is_some()
+}
+
+/// Splits a leading `"x` register off the keys, `None` while only `"` is typed.
+fn parse_register(keys: &[char]) -> Option<(Option<char>, &[char])> {
+    match keys {
+        ['"'] => None,
+        ['"', name, rest @ ..] => Some((Some(*name), rest)),
+        _ => Some((None, keys)),
+    }
+}
+
+/// Splits a leading count off the keys, capped at `MAX_COUNT`. A lone `0` is a motion, not
+/// a count.
+fn parse_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    if keys.first().is_none_or(|c| !('1'..='9').contains(c)) {
+        return (None, keys);
+    }
+    let digits = keys.iter().take_while(|c| c.is_ascii_digit()).count();
+    let count = keys[..digits]
+        .iter()
+        .filter_map(|c| c.to_digit(10))
+        .fold(0usize, |count, digit| {
+            count.saturating_mul(10).saturating_add(digit as usize)
+        });
+
+    (Some(count.min(MAX_COUNT)), &keys[digits..])
+}
+
+fn parse_motion(keys: &[char]) -> Parsed<VimMotion> {
+    let motion = match keys {
+        [] => return Parsed::Incomplete,
+        ['h'] => VimMotion::CharLeft,
+        ['l'] => VimMotion::CharRight,
+        ['j'] => VimMotion::LineDown,
+        ['k'] => VimMotion::LineUp,
+        ['w'] => VimMotion::WordForward { big: false },
+        ['W'] => VimMotion::WordForward { big: true },
+        ['b'] => VimMotion::WordBackward { big: false },
+        ['B'] => VimMotion::WordBackward { big: true },
+        ['e'] => VimMotion::WordEnd { big: false },
+        ['E'] => VimMotion::WordEnd { big: true },
+        ['0'] => VimMotion::StartOfLine,
+        ['^'] => VimMotion::FirstNonBlank,
+        ['$'] => VimMotion::EndOfLine,
+        [';'] => VimMotion::RepeatFind,
+        [','] => VimMotion::RepeatFindReverse,
+        ['G'] => VimMotion::LastLine,
+        ['g'] | ['f' | 'F' | 't' | 'T'] => return Parsed::Incomplete,
+        ['g', 'g'] => VimMotion::FirstLine,
+        [find, c] => {
+            let kind = match find {
+                'f' => FindKind::Forward,
+                'F' => FindKind::Backward,
+                't' => FindKind::TillForward,
+                'T' => FindKind::TillBackward,
+                _ => return Parsed::Invalid,
+            };
+            VimMotion::Find(kind, *c)
+        }
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(motion)
+}
+
+fn parse_text_object(keys: &[char]) -> Parsed<TextObject> {
+    let (around, object) = match keys {
+        ['i'] | ['a'] => return Parsed::Incomplete,
+        [scope @ ('i' | 'a'), object] => (*scope == 'a', *object),
+        _ => return Parsed::Invalid,
+    };
+    let kind = match object {
+        'w' => TextObjectKind::Word,
+        '"' | '\'' | '`' => TextObjectKind::Quote(object),
+        '(' | ')' | 'b' => TextObjectKind::Bracket('(', ')'),
+        '[' | ']' => TextObjectKind::Bracket('[', ']'),
+        '{' | '}' | 'B' => TextObjectKind::Bracket('{', '}'),
+        '<' | '>' => TextObjectKind::Bracket('<', '>'),
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(TextObject { kind, around })
+}
+
+fn parse_action(keys: &[char]) -> Parsed<Kind> {
+    let action = match keys {
+        ['i'] => Action::Insert,
+        ['I'] => Action::InsertLineStart,
+        ['a'] => Action::Append,
+        ['A'] => Action::AppendLineEnd,
+        ['o'] => Action::OpenBelow,
+        ['O'] => Action::OpenAbove,
+        ['p'] => Action::Put { before: false },
+        ['P'] => Action::Put { before: true },
+        ['r'] => return Parsed::Incomplete,
+        ['r', c] => Action::Replace(*c),
+        ['~'] => Action::ToggleCase,
+        ['J'] => Action::Join,
+        [':'] => Action::CommandLine,
+        ['u'] => Action::Undo,
+        ['.'] => Action::Repeat,
+        ['v'] => Action::Visual { linewise: false },
+        ['V'] => Action::Visual { linewise: true },
+        ['x'] => {
+            let target = Target::Motion(VimMotion::CharRight);
+            return Parsed::Done(Kind::Operate(VimOperator::Delete, target));
+        }
+        ['X'] => {
+            let target = Target::Motion(VimMotion::CharLeft);
+            return Parsed::Done(Kind::Operate(VimOperator::Delete, target));
         }
-        VimMotion::WordBackward => {
-            let mut pos = start;
-            if pos == 0 {
-                return (start, 0);
+        ['D'] => {
+            let target = Target::Motion(VimMotion::EndOfLine);
+            return Parsed::Done(Kind::Operate(VimOperator::Delete, target));
+        }
+        ['C'] => {
+            let target = Target::Motion(VimMotion::EndOfLine);
+            return Parsed::Done(Kind::Operate(VimOperator::Change, target));
+        }
+        ['Y'] => return Parsed::Done(Kind::Operate(VimOperator::Yank, Target::Lines)),
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(Kind::Act(action))
+}
+
+/// Parses `["x][count]` followed by an operator and its target, a motion or an action.
+fn parse(keys: &[char]) -> Parsed<NormalCommand> {
+    let Some((register, keys)) = parse_register(keys) else {
+        return Parsed::Incomplete;
+    };
+    let (count, keys) = parse_count(keys);
+    let Some((&key, rest)) = keys.split_first() else {
+        return Parsed::Incomplete;
+    };
+
+    let operator = match key {
+        'd' => Some(VimOperator::Delete),
+        'c' => Some(VimOperator::Change),
+        'y' => Some(VimOperator::Yank),
+        _ => None,
+    };
+
+    let (count, kind) = match operator {
+        Some(operator) => {
+            let (inner_count, rest) = parse_count(rest);
+            let count = match (count, inner_count) {
+                (Some(a), Some(b)) => Some(a.saturating_mul(b).min(MAX_COUNT)),
+                (a, b) => a.or(b),
+            };
+
+            let target = match rest {
+                [] => return Parsed::Incomplete,
+                [c] if *c == key => Target::Lines,
+                ['i' | 'a', ..] => match parse_text_object(rest) {
+                    Parsed::Done(object) => Target::Object(object),
+                    Parsed::Incomplete => return Parsed::Incomplete,
+                    Parsed::Invalid => return Parsed::Invalid,
+                },
+                _ => match parse_motion(rest) {
+                    Parsed::Done(motion) => Target::Motion(motion),
+                    Parsed::Incomplete => return Parsed::Incomplete,
+                    Parsed::Invalid => return Parsed::Invalid,
+                },
+            };
+            (count, Kind::Operate(operator, target))
+        }
+        None => match parse_motion(keys) {
+            Parsed::Done(motion) => (count, Kind::Move(motion)),
+            Parsed::Incomplete => return Parsed::Incomplete,
+            Parsed::Invalid => match parse_action(keys) {
+                Parsed::Done(kind) => (count, kind),
+                Parsed::Incomplete => return Parsed::Incomplete,
+                Parsed::Invalid => return Parsed::Invalid,
+            },
+        },
+    };
+
+    Parsed::Done(NormalCommand {
+        register,
+        count,
+        kind,
+    })
+}
+
+/// Parses a visual mode command: `["x][count]` followed by a motion moving the cursor, a
+/// text object to select or an operator on the selection.
+fn parse_visual(keys: &[char]) -> Parsed<NormalCommand> {
+    let Some((register, keys)) = parse_register(keys) else {
+        return Parsed::Incomplete;
+    };
+    let (count, keys) = parse_count(keys);
+
+    let kind = match keys {
+        [] => return Parsed::Incomplete,
+        ['d' | 'x' | 'X' | 'D'] => Kind::Operate(VimOperator::Delete, Target::Selection),
+        ['c' | 's' | 'C' | 'S'] => Kind::Operate(VimOperator::Change, Target::Selection),
+        ['y' | 'Y'] => Kind::Operate(VimOperator::Yank, Target::Selection),
+        ['p'] => Kind::Act(Action::Put { before: false }),
+        ['P'] => Kind::Act(Action::Put { before: true }),
+        ['o'] => Kind::Act(Action::SwapEnds),
+        ['v'] => Kind::Act(Action::Visual { linewise: false }),
+        ['V'] => Kind::Act(Action::Visual { linewise: true }),
+        ['i' | 'a', ..] => match parse_text_object(keys) {
+            Parsed::Done(object) => Kind::Act(Action::SelectObject(object)),
+            Parsed::Incomplete => return Parsed::Incomplete,
+            Parsed::Invalid => return Parsed::Invalid,
+        },
+        _ => match parse_motion(keys) {
+            Parsed::Done(motion) => Kind::Move(motion),
+            Parsed::Incomplete => return Parsed::Incomplete,
+            Parsed::Invalid => return Parsed::Invalid,
+        },
+    };
+
+    Parsed::Done(NormalCommand {
+        register,
+        count,
+        kind,
+    })
+}
+
+/// Byte offset in `text` of the character at `index`.
+fn byte_index(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+fn line_start(chars: &[char], pos: usize) -> usize {
+    chars[..pos.min(chars.len())]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1)
+}
+
+/// Position of the newline ending the line, or the end of the text.
+fn line_end(chars: &[char], pos: usize) -> usize {
+    chars[pos.min(chars.len())..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |i| pos + i)
+}
+
+fn first_non_blank(chars: &[char], pos: usize) -> usize {
+    let start = line_start(chars, pos);
+    let end = line_end(chars, pos);
+    (start..end)
+        .find(|i| !matches!(chars[*i], ' ' | '\t'))
+        .unwrap_or(start)
+}
+
+/// Blanks, keyword characters or punctuation. `big` words only tell blanks apart.
+fn char_class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn word_forward(chars: &[char], pos: usize, big: bool) -> usize {
+    let len = chars.len();
+    let mut pos = pos;
+    if let Some(c) = chars.get(pos) {
+        let class = char_class(*c, big);
+        if class != 0 {
+            while pos < len && char_class(chars[pos], big) == class {
+                pos += 1;
             }
+        }
+    }
+    while pos < len && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
 
-            // First, check what character is immediately before the cursor
-            let prev_char = input[..pos].chars().next_back();
-
-            if let Some(c) = prev_char {
-                if c.is_whitespace() {
-                    // We're after whitespace - skip all whitespace backwards
-                    while pos > 0 {
-                        if let Some(c) = input[..pos].chars().next_back()
-                            && c.is_whitespace()
-                        {
-                            pos -= c.len_utf8();
-                        } else {
-                            break;
-                        }
-                    }
-                    // Now skip the word backwards to find its beginning
-                    while pos > 0 {
-                        if let Some(c) = input[..pos].chars().next_back()
-                            && !c.is_whitespace()
-                        {
-                            pos -= c.len_utf8();
-                        } else {
-                            break;
-                        }
+fn word_backward(chars: &[char], pos: usize, big: bool) -> usize {
+    let mut pos = pos;
+    while pos > 0 && chars[pos - 1].is_whitespace() {
+        pos -= 1;
+    }
+    if pos == 0 {
+        return 0;
+    }
+
+    let class = char_class(chars[pos - 1], big);
+    while pos > 0 && char_class(chars[pos - 1], big) == class {
+        pos -= 1;
+    }
+    pos
+}
+
+fn word_end(chars: &[char], pos: usize, big: bool) -> usize {
+    let len = chars.len();
+    let mut pos = pos + 1;
+    while pos < len && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    if pos >= len {
+        return len.saturating_sub(1);
+    }
+
+    let class = char_class(chars[pos], big);
+    while pos + 1 < len && char_class(chars[pos + 1], big) == class {
+        pos += 1;
+    }
+    pos
+}
+
+/// The `count`th `c` on the line in the direction of `kind`. Repeating a till skips the
+/// match right next to the cursor, otherwise `;` would not move.
+fn find_char(
+    chars: &[char],
+    pos: usize,
+    kind: FindKind,
+    c: char,
+    count: usize,
+    repeat: bool,
+) -> Option<usize> {
+    let start = line_start(chars, pos);
+    let end = line_end(chars, pos);
+    let skip =
+        usize::from(repeat && matches!(kind, FindKind::TillForward | FindKind::TillBackward));
+
+    match kind {
+        FindKind::Forward | FindKind::TillForward => {
+            let found = (pos + 1 + skip..end)
+                .filter(|i| chars[*i] == c)
+                .nth(count - 1)?;
+            Some(if kind == FindKind::Forward {
+                found
+            } else {
+                found - 1
+            })
+        }
+        FindKind::Backward | FindKind::TillBackward => {
+            let found = (start..pos.saturating_sub(skip))
+                .rev()
+                .filter(|i| chars[*i] == c)
+                .nth(count - 1)?;
+            Some(if kind == FindKind::Backward {
+                found
+            } else {
+                found + 1
+            })
+        }
+    }
+}
+
+/// Where a motion lands and how an operator takes the text up to there. Motions used by an
+/// operator may end on the newline, those moving the cursor stop before it.
+fn motion_target(
+    chars: &[char],
+    pos: usize,
+    motion: VimMotion,
+    count: usize,
+    last_find: Option<(FindKind, char)>,
+    for_operator: bool,
+) -> Option<(usize, MotionKind)> {
+    let start = line_start(chars, pos);
+    let end = line_end(chars, pos);
+    let last_on_line = if for_operator {
+        end
+    } else {
+        end.saturating_sub(1).max(start)
+    };
+
+    let target = match motion {
+        VimMotion::CharLeft => (pos.saturating_sub(count).max(start), MotionKind::Exclusive),
+        VimMotion::CharRight => ((pos + count).min(last_on_line), MotionKind::Exclusive),
+        VimMotion::LineDown | VimMotion::LineUp => {
+            let mut line = start;
+            for _ in 0..count {
+                line = match motion {
+                    VimMotion::LineDown if line_end(chars, line) < chars.len() => {
+                        line_end(chars, line) + 1
                     }
-                } else {
-                    // We're after a word character - check if we're at the start of a word
-                    // by looking at the character before that
-                    let two_back = if pos >= c.len_utf8() {
-                        input[..pos - c.len_utf8()].chars().next_back()
+                    VimMotion::LineUp if line > 0 => line_start(chars, line - 1),
+                    _ => return None,
+                };
+            }
+            (line, MotionKind::Linewise)
+        }
+        VimMotion::WordForward { big } => {
+            let mut target = pos;
+            for step in 0..count {
+                let next = word_forward(chars, target, big);
+                // `dw` on the last word of a line stops at its end instead of joining lines
+                let line_end = line_end(chars, target);
+                target =
+                    if for_operator && step + 1 == count && next > line_end && line_end > target {
+                        line_end
                     } else {
-                        None
+                        next
                     };
-
-                    if two_back.is_none() || two_back.is_some_and(|c2| c2.is_whitespace()) {
-                        // At start of word - move to previous word
-                        pos -= c.len_utf8(); // Move past the first char of current word
-                        // Skip whitespace backwards
-                        while pos > 0 {
-                            if let Some(c) = input[..pos].chars().next_back()
-                                && c.is_whitespace()
-                            {
-                                pos -= c.len_utf8();
-                            } else {
-                                break;
-                            }
-                        }
-                        // Skip the previous word backwards
-                        while pos > 0 {
-                            if let Some(c) = input[..pos].chars().next_back()
-                                && !c.is_whitespace()
-                            {
-                                pos -= c.len_utf8();
-                            } else {
-                                break;
-                            }
-                        }
-                    } else {
-                        // In middle of word - go to start of current word
-                        while pos > 0 {
-                            if let Some(c) = input[..pos].chars().next_back()
-                                && !c.is_whitespace()
-                            {
-                                pos -= c.len_utf8();
-                            } else {
-                                break;
-                            }
-                        }
-                    }
+            }
+            (target, MotionKind::Exclusive)
+        }
+        VimMotion::WordBackward { big } => {
+            let target = (0..count).fold(pos, |p, _| word_backward(chars, p, big));
+            (target, MotionKind::Exclusive)
+        }
+        VimMotion::WordEnd { big } => {
+            let target = (0..count).fold(pos, |p, _| word_end(chars, p, big));
+            (target, MotionKind::Inclusive)
+        }
+        VimMotion::StartOfLine => (start, MotionKind::Exclusive),
+        VimMotion::FirstNonBlank => (first_non_blank(chars, pos), MotionKind::Exclusive),
+        VimMotion::EndOfLine => {
+            let mut line = start;
+            for _ in 1..count {
+                if line_end(chars, line) < chars.len() {
+                    line = line_end(chars, line) + 1;
                 }
             }
-            pos
+            let end = line_end(chars, line);
+            let target = if for_operator {
+                end
+            } else {
+                end.saturating_sub(1).max(line)
+            };
+            (target, MotionKind::Exclusive)
         }
-        VimMotion::_Line => len, // Special case, usually handled by operator logic
-        VimMotion::_CharRight => start + input[start..].chars().next().map_or(0, |c| c.len_utf8()),
-        VimMotion::_CharLeft => {
-            start
-                - input[..start]
-                    .chars()
-                    .next_back()
-                    .map_or(0, |c| c.len_utf8())
+        VimMotion::Find(kind, c) => find_target(chars, pos, kind, c, count, false)?,
+        VimMotion::RepeatFind => {
+            let (kind, c) = last_find?;
+            find_target(chars, pos, kind, c, count, true)?
         }
-        VimMotion::_StartOfLine => 0,
-        VimMotion::_EndOfLine => len,
+        VimMotion::RepeatFindReverse => {
+            let (kind, c) = last_find?;
+            find_target(chars, pos, kind.reversed(), c, count, true)?
+        }
+        VimMotion::FirstLine => (0, MotionKind::Linewise),
+        VimMotion::LastLine => (chars.len(), MotionKind::Linewise),
     };
-
-    (start, end)
+    Some(target)
 }
 
-fn execute_operator(state: &mut MutexGuard<'_, App>, operator: VimOperator, range: (usize, usize)) {
-    let (start, end) = range;
-    let (low, high) = if start < end {
-        (start, end)
-    } else {
-        (end, start)
+fn find_target(
+    chars: &[char],
+    pos: usize,
+    kind: FindKind,
+    c: char,
+    count: usize,
+    repeat: bool,
+) -> Option<(usize, MotionKind)> {
+    let target = find_char(chars, pos, kind, c, count, repeat)?;
+    let motion_kind = match kind {
+        FindKind::Forward | FindKind::TillForward => MotionKind::Inclusive,
+        FindKind::Backward | FindKind::TillBackward => MotionKind::Exclusive,
     };
+    Some((target, motion_kind))
+}
 
-    match operator {
-        VimOperator::Delete => {
-            if high > low && state.input.is_char_boundary(low) && state.input.is_char_boundary(high)
-            {
-                state.input.drain(low..high);
-                state.cursor_position = low;
+/// `cw` on a word changes up to its end like `ce`, but only the last character when the
+/// cursor is already on it.
+fn change_word_end(chars: &[char], pos: usize, count: usize, big: bool) -> usize {
+    let mut end = pos;
+    for step in 0..count {
+        let at_word_end = chars
+            .get(end + 1)
+            .is_none_or(|c| char_class(*c, big) != char_class(chars[end], big));
+        if step > 0 || !at_word_end {
+            end = word_end(chars, end, big);
+        }
+    }
+    end
+}
+
+/// Start and end of a text object around the cursor, the end exclusive.
+fn text_object_range(chars: &[char], pos: usize, object: TextObject) -> Option<(usize, usize)> {
+    let (start, end) = match object.kind {
+        TextObjectKind::Word => {
+            let c = *chars.get(pos).filter(|c| **c != '\n')?;
+            let class = char_class(c, false);
+            let same = |i: usize| chars[i] != '\n' && char_class(chars[i], false) == class;
+
+            let mut start = pos;
+            while start > 0 && same(start - 1) {
+                start -= 1;
             }
+            let mut end = pos + 1;
+            while end < chars.len() && same(end) {
+                end += 1;
+            }
+
+            if object.around && class == 0 {
+                // Around blanks takes the word after them
+                if let Some(&next) = chars.get(end).filter(|c| **c != '\n') {
+                    let next_class = char_class(next, false);
+                    while end < chars.len() && char_class(chars[end], false) == next_class {
+                        end += 1;
+                    }
+                }
+            }
+            return Some(if object.around && class != 0 {
+                with_blanks(chars, start, end)
+            } else {
+                (start, end)
+            });
         }
-        VimOperator::_Change => {
-            // Not implemented yet
+        TextObjectKind::Quote(quote) => {
+            let line = line_start(chars, pos)..line_end(chars, pos);
+            let quotes: Vec<usize> = line.filter(|i| chars[*i] == quote).collect();
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, close)| pos <= *close)?;
+            (open, close)
         }
-        VimOperator::_Yank => {
-            // Not implemented yet
+        TextObjectKind::Bracket(open_char, close_char) => {
+            if chars.is_empty() {
+                return None;
+            }
+            // The cursor can rest past the last character, after a trailing newline
+            let pos = pos.min(chars.len() - 1);
+            let mut depth = 0;
+            let mut i = if chars.get(pos) == Some(&close_char) {
+                pos
+            } else {
+                pos + 1
+            };
+            let open = loop {
+                if i == 0 {
+                    return None;
+                }
+                i -= 1;
+                if chars[i] == close_char {
+                    depth += 1;
+                } else if chars[i] == open_char {
+                    if depth == 0 {
+                        break i;
+                    }
+                    depth -= 1;
+                }
+            };
+
+            let mut depth = 0;
+            let close = (open + 1..chars.len()).find(|i| {
+                if chars[*i] == open_char {
+                    depth += 1;
+                } else if chars[*i] == close_char {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                }
+                false
+            })?;
+            (open, close)
         }
+    };
+
+    Some(match (object.around, object.kind) {
+        (false, _) => (start + 1, end),
+        (true, TextObjectKind::Quote(_)) => with_blanks(chars, start, end + 1),
+        (true, _) => (start, end + 1),
+    })
+}
+
+/// Widens a range over the blanks after it, or those before it when there are none after.
+fn with_blanks(chars: &[char], start: usize, end: usize) -> (usize, usize) {
+    let is_blank = |i: usize| matches!(chars[i], ' ' | '\t');
+
+    let mut after = end;
+    while after < chars.len() && is_blank(after) {
+        after += 1;
     }
+    if after > end {
+        return (start, after);
+    }
+
+    let mut before = start;
+    while before > line_start(chars, start) && is_blank(before - 1) {
+        before -= 1;
+    }
+    (before, end)
 }
 
-pub async fn handle_vim_keys(
-    mut state: MutexGuard<'_, App>,
-    c: char,
-    tx_action: Sender<AppAction>,
+fn set_cursor(state: &mut MutexGuard<'_, App>, index: usize) {
+    state.cursor_position = byte_index(&state.input, index);
+}
+
+fn replace_chars(state: &mut MutexGuard<'_, App>, start: usize, end: usize, text: &str) {
+    let range = byte_index(&state.input, start)..byte_index(&state.input, end);
+    state.input.replace_range(range, text);
+}
+
+fn store_register(
+    state: &mut MutexGuard<'_, App>,
+    name: Option<char>,
+    register: Register,
+    yank: bool,
 ) {
-    // Check for timeout
     if let Some(vim_state) = &mut state.vim_state
-        && vim_state.operator.is_some()
-        && Instant::now()
-            .duration_since(vim_state.last_action_time)
-            .as_secs()
-            >= 1
+        && let Err(e) = vim_state.registers.store(name, register, yank)
     {
-        vim_state.operator = None;
-        vim_state.pending_keys.clear();
+        state.status_message = format!("Failed to copy to clipboard: {e}");
     }
+}
 
-    // Ensure vim_state exists (it should, but for safety)
-    if state.vim_state.is_none() {
-        state.vim_state = Some(VimState::default());
+/// Applies an operator to the text between `from` and `to`, or to the lines they are on.
+fn apply_operator(
+    state: &mut MutexGuard<'_, App>,
+    operator: VimOperator,
+    (from, to): (usize, usize),
+    kind: MotionKind,
+    register: Option<char>,
+) {
+    let chars: Vec<char> = state.input.chars().collect();
+    let (low, high) = (from.min(to), from.max(to));
+
+    if kind == MotionKind::Linewise {
+        let start = line_start(&chars, low);
+        let end = line_end(&chars, high);
+        let text: String = chars[start..end].iter().collect();
+        store_register(
+            state,
+            register,
+            Register {
+                text,
+                linewise: true,
+            },
+            operator == VimOperator::Yank,
+        );
+
+        match operator {
+            VimOperator::Yank => {}
+            VimOperator::Change => {
+                replace_chars(state, start, end, "");
+                set_cursor(state, start);
+                state.mode = InputMode::Insert;
+            }
+            VimOperator::Delete => {
+                // Take the newline after the lines, or before them for the last line
+                let (start, end) = if end < chars.len() {
+                    (start, end + 1)
+                } else {
+                    (start.saturating_sub(1), end)
+                };
+                replace_chars(state, start, end, "");
+
+                let chars: Vec<char> = state.input.chars().collect();
+                let line = line_start(&chars, start.min(chars.len()));
+                set_cursor(state, first_non_blank(&chars, line));
+                clamp_cursor(state);
+            }
+        }
+        return;
+    }
+
+    let high = match kind {
+        MotionKind::Inclusive => (high + 1).min(chars.len()),
+        _ => high,
+    };
+    if high > low {
+        let text: String = chars[low..high].iter().collect();
+        store_register(
+            state,
+            register,
+            Register {
+                text,
+                linewise: false,
+            },
+            operator == VimOperator::Yank,
+        );
+    }
+
+    match operator {
+        VimOperator::Yank => set_cursor(state, low),
+        VimOperator::Delete => {
+            replace_chars(state, low, high, "");
+            set_cursor(state, low);
+            clamp_cursor(state);
+        }
+        VimOperator::Change => {
+            replace_chars(state, low, high, "");
+            set_cursor(state, low);
+            state.mode = InputMode::Insert;
+        }
+    }
+}
+
+fn put(state: &mut MutexGuard<'_, App>, register: Option<char>, count: usize, before: bool) {
+    let Some(Register { text, linewise }) = state
+        .vim_state
+        .as_ref()
+        .and_then(|v| v.registers.get(register))
+    else {
+        return;
+    };
+    let chars: Vec<char> = state.input.chars().collect();
+    let pos = state.input[..state.cursor_position].chars().count();
+    let count = count
+        .min(MAX_COUNT)
+        .min(MAX_PUT_CHARS / text.chars().count().max(1))
+        .max(1);
+
+    if linewise {
+        let text = vec![text.as_str(); count].join("\n");
+        let at = if before {
+            let start = line_start(&chars, pos);
+            replace_chars(state, start, start, &format!("{text}\n"));
+            start
+        } else {
+            let end = line_end(&chars, pos);
+            replace_chars(state, end, end, &format!("\n{text}"));
+            end + 1
+        };
+        let chars: Vec<char> = state.input.chars().collect();
+        set_cursor(state, first_non_blank(&chars, at));
+    } else {
+        let text = text.repeat(count);
+        let at = match chars.get(pos) {
+            Some(c) if !before && *c != '\n' => pos + 1,
+            _ => pos,
+        };
+        replace_chars(state, at, at, &text);
+        set_cursor(state, (at + text.chars().count()).saturating_sub(1));
+    }
+    clamp_cursor(state);
+}
+
+/// `r`: replaces `count` characters, or none if the line is too short.
+fn replace(state: &mut MutexGuard<'_, App>, c: char, count: usize) {
+    let chars: Vec<char> = state.input.chars().collect();
+    let pos = state.input[..state.cursor_position].chars().count();
+    if count > line_end(&chars, pos) - pos {
+        return;
+    }
+
+    replace_chars(state, pos, pos + count, &c.to_string().repeat(count));
+    set_cursor(state, pos + count - 1);
+}
+
+fn toggle_case(state: &mut MutexGuard<'_, App>, count: usize) {
+    let chars: Vec<char> = state.input.chars().collect();
+    let pos = state.input[..state.cursor_position].chars().count();
+    let end = (pos + count).min(line_end(&chars, pos));
+    if pos >= end {
+        return;
+    }
+
+    let toggled: String = chars[pos..end]
+        .iter()
+        .flat_map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().collect::<Vec<_>>()
+            } else {
+                c.to_uppercase().collect()
+            }
+        })
+        .collect();
+    replace_chars(state, pos, end, &toggled);
+    let chars: Vec<char> = state.input.chars().collect();
+    set_cursor(
+        state,
+        end.min(line_end(&chars, pos).saturating_sub(1)).max(pos),
+    );
+}
+
+/// `J`: joins `count` lines, at least two, with a space in place of each line break.
+fn join_lines(state: &mut MutexGuard<'_, App>, count: usize) {
+    for _ in 0..count.saturating_sub(1).max(1) {
+        let chars: Vec<char> = state.input.chars().collect();
+        let pos = state.input[..state.cursor_position].chars().count();
+        let end = line_end(&chars, pos);
+        if end >= chars.len() {
+            return;
+        }
+
+        let mut next = end + 1;
+        while next < chars.len() && matches!(chars[next], ' ' | '\t') {
+            next += 1;
+        }
+        let separator = match chars.get(next) {
+            None | Some('\n' | ')') => "",
+            _ if end > 0 && matches!(chars[end - 1], ' ' | '\t') => "",
+            _ => " ",
+        };
+        replace_chars(state, end, next, separator);
+        set_cursor(state, end);
     }
+}
+
+fn move_down(state: &mut MutexGuard<'_, App>) {
+    let current_pos = state.cursor_position;
+    let current_line_start = state.input[..current_pos]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let current_column_width =
+        UnicodeWidthStr::width(&state.input[current_line_start..current_pos]);
 
-    // We need to clone some state to avoid borrow checker issues when calling async functions
-    // or when mutating state later.
-    let current_operator = state.vim_state.as_ref().unwrap().operator;
+    if let Some(newline_offset) = state.input[current_pos..].find('\n') {
+        let next_line_start = current_pos + newline_offset + 1;
+        if next_line_start < state.input.len() {
+            let next_line_end = state.input[next_line_start..]
+                .find('\n')
+                .map(|i| next_line_start + i)
+                .unwrap_or(state.input.len());
+            let next_line_str = &state.input[next_line_start..next_line_end];
 
-    match c {
-        'i' => {
+            let mut target_offset = 0;
+            let mut current_width = 0;
+            for c in next_line_str.chars() {
+                let w = c.width().unwrap_or(0); // Optimization: avoid c.to_string() allocation
+                if current_width + w > current_column_width {
+                    break;
+                }
+                current_width += w;
+                target_offset += c.len_utf8();
+            }
+            if target_offset == next_line_str.len()
+                && target_offset > 0
+                && let Some(last_char) = next_line_str.chars().next_back()
+            {
+                target_offset -= last_char.len_utf8();
+            }
+            state.cursor_position = next_line_start + target_offset;
+            clamp_cursor(state);
+        }
+    }
+}
+
+fn move_up(state: &mut MutexGuard<'_, App>) {
+    let current_pos = state.cursor_position;
+    let current_column_width = {
+        let current_line_start = state.input[..current_pos]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        UnicodeWidthStr::width(&state.input[current_line_start..current_pos])
+    };
+
+    let input_before = &state.input[..current_pos];
+
+    if let Some(last_newline) = input_before.rfind('\n') {
+        let prev_line_start = state.input[..last_newline]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let prev_line_end = last_newline;
+        let prev_line_str = &state.input[prev_line_start..prev_line_end];
+
+        let mut target_offset = 0;
+        let mut current_width = 0;
+        for c in prev_line_str.chars() {
+            let w = c.width().unwrap_or(0); // Optimization: avoid c.to_string() allocation
+            if current_width + w > current_column_width {
+                break;
+            }
+            current_width += w;
+            target_offset += c.len_utf8();
+        }
+        if target_offset == prev_line_str.len()
+            && target_offset > 0
+            && let Some(last_char) = prev_line_str.chars().next_back()
+        {
+            target_offset -= last_char.len_utf8();
+        }
+        state.cursor_position = prev_line_start + target_offset;
+        clamp_cursor(state);
+    }
+}
+
+fn act(state: &mut MutexGuard<'_, App>, action: Action, count: usize, register: Option<char>) {
+    match action {
+        Action::Insert => {
             state.mode = InputMode::Insert;
         }
-        'I' => {
+        Action::InsertLineStart => {
             let start_of_line = state.input[..state.cursor_position]
                 .rfind('\n')
                 .map(|i| i + 1)
@@ -257,13 +1137,13 @@ pub async fn handle_vim_keys(
             state.cursor_position = start_of_line;
             state.mode = InputMode::Insert;
         }
-        'a' => {
+        Action::Append => {
             if let Some(c) = state.input[state.cursor_position..].chars().next() {
                 state.cursor_position += c.len_utf8();
             }
             state.mode = InputMode::Insert;
         }
-        'A' => {
+        Action::AppendLineEnd => {
             let end_of_line = state.input[state.cursor_position..]
                 .find('\n')
                 .map(|i| state.cursor_position + i)
@@ -271,7 +1151,7 @@ pub async fn handle_vim_keys(
             state.cursor_position = end_of_line;
             state.mode = InputMode::Insert;
         }
-        'O' => {
+        Action::OpenAbove => {
             let current_line_start = state.input[..state.cursor_position]
                 .rfind('\n')
                 .map(|i| i + 1)
@@ -280,7 +1160,7 @@ pub async fn handle_vim_keys(
             state.cursor_position = current_line_start;
             state.mode = InputMode::Insert;
         }
-        'o' => {
+        Action::OpenBelow => {
             let next_line_start = state.input[state.cursor_position..]
                 .find('\n')
                 .map(|i| state.cursor_position + i + 1)
@@ -296,202 +1176,700 @@ pub async fn handle_vim_keys(
 
             state.mode = InputMode::Insert;
         }
-        'j' => {
-            if let AppState::Chatting(_) = &state.state {
-                let current_pos = state.cursor_position;
-                let current_line_start = state.input[..current_pos]
-                    .rfind('\n')
-                    .map(|i| i + 1)
-                    .unwrap_or(0);
-                let current_column_width =
-                    UnicodeWidthStr::width(&state.input[current_line_start..current_pos]);
-
-                if let Some(newline_offset) = state.input[current_pos..].find('\n') {
-                    let next_line_start = current_pos + newline_offset + 1;
-                    if next_line_start < state.input.len() {
-                        let next_line_end = state.input[next_line_start..]
-                            .find('\n')
-                            .map(|i| next_line_start + i)
-                            .unwrap_or(state.input.len());
-                        let next_line_str = &state.input[next_line_start..next_line_end];
-
-                        let mut target_offset = 0;
-                        let mut current_width = 0;
-                        for c in next_line_str.chars() {
-                            let w = c.width().unwrap_or(0); // Optimization: avoid c.to_string() allocation
-                            if current_width + w > current_column_width {
-                                break;
-                            }
-                            current_width += w;
-                            target_offset += c.len_utf8();
-                        }
-                        if target_offset == next_line_str.len()
-                            && target_offset > 0
-                            && let Some(last_char) = next_line_str.chars().next_back()
-                        {
-                            target_offset -= last_char.len_utf8();
-                        }
-                        state.cursor_position = next_line_start + target_offset;
-                        clamp_cursor(&mut state);
-                    }
-                }
-            } else {
-                tx_action.send(AppAction::SelectNext).await.ok();
+        Action::Put { before } => match selected_range(state) {
+            Some(selection) => replace_selection(state, selection, register, count),
+            None => put(state, register, count, before),
+        },
+        Action::Replace(c) => replace(state, c, count),
+        Action::ToggleCase => toggle_case(state, count),
+        Action::Join => join_lines(state, count),
+        Action::CommandLine => command::open(state),
+        Action::Undo if matches!(state.state, AppState::Chatting(_)) => {
+            if undo::undo(state, count) == 0 {
+                state.status_message = "Already at oldest change.".to_string();
             }
+            clamp_cursor(state);
         }
-        'k' => {
-            if let AppState::Chatting(_) = &state.state {
-                let current_pos = state.cursor_position;
-                let current_column_width = {
-                    let current_line_start = state.input[..current_pos]
-                        .rfind('\n')
-                        .map(|i| i + 1)
-                        .unwrap_or(0);
-                    UnicodeWidthStr::width(&state.input[current_line_start..current_pos])
-                };
-
-                let input_before = &state.input[..current_pos];
-
-                if let Some(last_newline) = input_before.rfind('\n') {
-                    let prev_line_start = state.input[..last_newline]
-                        .rfind('\n')
-                        .map(|i| i + 1)
-                        .unwrap_or(0);
-                    let prev_line_end = last_newline;
-                    let prev_line_str = &state.input[prev_line_start..prev_line_end];
-
-                    let mut target_offset = 0;
-                    let mut current_width = 0;
-                    for c in prev_line_str.chars() {
-                        let w = c.width().unwrap_or(0); // Optimization: avoid c.to_string() allocation
-                        if current_width + w > current_column_width {
-                            break;
-                        }
-                        current_width += w;
-                        target_offset += c.len_utf8();
-                    }
-                    if target_offset == prev_line_str.len()
-                        && target_offset > 0
-                        && let Some(last_char) = prev_line_str.chars().next_back()
-                    {
-                        target_offset -= last_char.len_utf8();
-                    }
-                    state.cursor_position = prev_line_start + target_offset;
-                    clamp_cursor(&mut state);
-                }
-            } else {
-                tx_action.send(AppAction::SelectPrevious).await.ok();
-            }
+        // Only the message input has a history, and `.` is handled by `handle_vim_keys`
+        Action::Undo | Action::Repeat => {}
+        Action::Visual { linewise } => toggle_visual(state, linewise),
+        Action::SwapEnds => {
+            let pos = state.input[..state.cursor_position].chars().count();
+            let anchor = match &mut state.vim_state {
+                Some(VimState {
+                    visual: Some(visual),
+                    ..
+                }) => std::mem::replace(&mut visual.anchor, pos),
+                _ => return,
+            };
+            set_cursor(state, anchor);
+            clamp_cursor(state);
         }
-        'h' => {
-            if let Some(c) = state.input[..state.cursor_position].chars().next_back()
-                && (!c.is_control() || c == '\t')
-            {
-                state.cursor_position -= c.len_utf8();
+        Action::SelectObject(object) => {
+            let chars: Vec<char> = state.input.chars().collect();
+            let pos = state.input[..state.cursor_position].chars().count();
+            let Some((start, end)) = text_object_range(&chars, pos, object) else {
+                return;
+            };
+            if end <= start {
+                return;
+            }
+            if let Some(visual) = state.vim_state.as_mut().and_then(|v| v.visual.as_mut()) {
+                visual.anchor = start;
             }
+            set_cursor(state, end - 1);
         }
-        'l' => {
-            if let Some(c) = state.input[state.cursor_position..].chars().next()
-                && c != '\n'
-            {
-                let next_pos = state.cursor_position + c.len_utf8();
-                // Optional: check if next_pos lands on newline and decide whether to step onto it?
-                // For now, simply blocking movement FROM newline (checked above) prevents wrapping to next line.
-                // But we also want to maybe stop AT the last char, not ON the newline.
-                // If we want to emulate vim standard behavior:
-                // If next char is '\n', we DON'T move onto it?
-                if let Some(next_c) = state.input[next_pos..].chars().next() {
-                    if next_c != '\n' {
-                        state.cursor_position = next_pos;
-                    }
-                } else if next_pos < state.input.len() {
-                    // End of file case
-                    state.cursor_position = next_pos;
-                }
+    }
+}
+
+/// `v` and `V` start visual mode, switch between its kinds or leave it.
+fn toggle_visual(state: &mut MutexGuard<'_, App>, linewise: bool) {
+    if !matches!(state.state, AppState::Chatting(_)) {
+        return;
+    }
+    let anchor = state.input[..state.cursor_position].chars().count();
+    let Some(vim_state) = &mut state.vim_state else {
+        return;
+    };
+
+    match &mut vim_state.visual {
+        Some(visual) if visual.linewise == linewise => {
+            exit_visual(state);
+            return;
+        }
+        Some(visual) => visual.linewise = linewise,
+        None => vim_state.visual = Some(Visual { anchor, linewise }),
+    }
+    state.status_message = if linewise {
+        VISUAL_LINE_STATUS.to_string()
+    } else {
+        VISUAL_STATUS.to_string()
+    };
+}
+
+/// The selection's ends as character indices, and how an operator takes it.
+fn selected_range(state: &App) -> Option<((usize, usize), MotionKind)> {
+    let visual = state.vim_state.as_ref()?.visual?;
+    let len = state.input.chars().count();
+    let pos = state.input[..state.cursor_position].chars().count();
+    let kind = if visual.linewise {
+        MotionKind::Linewise
+    } else {
+        MotionKind::Inclusive
+    };
+    Some(((visual.anchor.min(len), pos), kind))
+}
+
+/// Characters covered by the visual mode selection, the end excluded.
+pub fn selected_chars(app: &App) -> Option<(usize, usize)> {
+    if app.mode != InputMode::Normal {
+        return None;
+    }
+    let ((from, to), kind) = selected_range(app)?;
+    let (low, high) = (from.min(to), from.max(to));
+    let chars: Vec<char> = app.input.chars().collect();
+
+    match kind {
+        MotionKind::Linewise => Some((line_start(&chars, low), line_end(&chars, high))),
+        _ => Some((low, (high + 1).min(chars.len()))),
+    }
+}
+
+/// `p` in visual mode: the selection is replaced by the register.
+fn replace_selection(
+    state: &mut MutexGuard<'_, App>,
+    ((from, to), kind): ((usize, usize), MotionKind),
+    register: Option<char>,
+    count: usize,
+) {
+    exit_visual(state);
+    let chars: Vec<char> = state.input.chars().collect();
+    // Whether text is left after the selection, to put the register before it
+    let before = match kind {
+        MotionKind::Linewise => line_end(&chars, from.max(to)) < chars.len(),
+        _ => from.max(to) + 1 < chars.len(),
+    };
+
+    apply_operator(state, VimOperator::Delete, (from, to), kind, Some('_'));
+    put(state, register, count, before);
+}
+
+/// Back to normal mode, on the last inserted character like vim. Keeps what the insert
+/// session typed for `.`.
+pub fn leave_insert(state: &mut MutexGuard<'_, App>) {
+    let start = state.vim_state.as_mut().and_then(|v| v.insert_start.take());
+    if let Some(start) = start
+        && start <= state.cursor_position
+        && state.input.is_char_boundary(start)
+    {
+        let inserted = state.input[start..state.cursor_position].to_string();
+        if let Some(change) = state
+            .vim_state
+            .as_mut()
+            .and_then(|v| v.last_change.as_mut())
+        {
+            change.inserted = Some(inserted);
+        }
+    }
+    undo::commit(state);
+
+    state.mode = InputMode::Normal;
+    if let Some(c) = state.input[..state.cursor_position].chars().next_back()
+        && c != '\n'
+    {
+        state.cursor_position -= c.len_utf8();
+    }
+    if !(state.cursor_position == state.input.len() && state.input.ends_with('\n')) {
+        clamp_cursor(state);
+    }
+}
+
+/// Runs a command as one undoable change. A command entering insert mode leaves the change
+/// open until Esc.
+async fn run(
+    state: &mut MutexGuard<'_, App>,
+    command: NormalCommand,
+    tx_action: &Sender<AppAction>,
+) {
+    let changes_text = command.changes_text();
+    if changes_text {
+        undo::record(state, EditKind::Other);
+    }
+
+    execute(state, command, tx_action).await;
+
+    if state.mode == InputMode::Insert {
+        undo::continue_as_insert(state);
+        let cursor_position = state.cursor_position;
+        if let Some(vim_state) = &mut state.vim_state {
+            vim_state.insert_start = Some(cursor_position);
+        }
+    } else if changes_text {
+        undo::commit(state);
+    }
+}
+
+/// `.`: runs the last change again with what its insert session typed, a count replacing
+/// the original one.
+async fn repeat(
+    state: &mut MutexGuard<'_, App>,
+    count: Option<usize>,
+    tx_action: &Sender<AppAction>,
+) {
+    let Some(change) = state.vim_state.as_ref().and_then(|v| v.last_change.clone()) else {
+        return;
+    };
+    let keys: Vec<char> = change.keys.chars().collect();
+    let Parsed::Done(mut command) = parse(&keys) else {
+        return;
+    };
+    if count.is_some() {
+        command.count = count;
+    }
+
+    undo::record(state, EditKind::Other);
+    execute(state, command, tx_action).await;
+    if state.mode == InputMode::Insert {
+        if let Some(text) = &change.inserted {
+            let pos = state.cursor_position;
+            state.input.insert_str(pos, text);
+            state.cursor_position += text.len();
+        }
+        leave_insert(state);
+    }
+    undo::commit(state);
+}
+
+async fn execute(
+    state: &mut MutexGuard<'_, App>,
+    command: NormalCommand,
+    tx_action: &Sender<AppAction>,
+) {
+    let count = command.count.unwrap_or(1);
+    let chatting = matches!(state.state, AppState::Chatting(_));
+
+    match command.kind {
+        // Moved directly: this runs in the only consumer of the action channel, so sending a
+        // move per count could fill it
+        Kind::Move(motion @ (VimMotion::LineDown | VimMotion::LineUp)) if !chatting => {
+            let step = if motion == VimMotion::LineDown { 1 } else { -1 };
+            for _ in 0..count {
+                events::move_list_selection(state, step).await;
             }
         }
-        'w' => {
-            if let Some(op) = current_operator {
-                let range = get_motion_range(&state, VimMotion::WordForward);
-                execute_operator(&mut state, op, range);
-                if let Some(vim_state) = &mut state.vim_state {
-                    vim_state.operator = None;
-                }
-            } else {
-                let (_, end) = get_motion_range(&state, VimMotion::WordForward);
-                state.cursor_position = end;
-                clamp_cursor(&mut state);
+        Kind::Move(VimMotion::LineDown) => (0..count).for_each(|_| move_down(state)),
+        Kind::Move(VimMotion::LineUp) => (0..count).for_each(|_| move_up(state)),
+        Kind::Move(VimMotion::FirstLine) => events::scroll_chat(state, tx_action, ChatScroll::Top),
+        Kind::Move(VimMotion::LastLine) => {
+            events::scroll_chat(state, tx_action, ChatScroll::Bottom);
+        }
+        Kind::Move(motion) => {
+            let chars: Vec<char> = state.input.chars().collect();
+            let pos = state.input[..state.cursor_position].chars().count();
+            let last_find = state.vim_state.as_ref().and_then(|v| v.last_find);
+            remember_find(state, motion);
+
+            if let Some((target, _)) = motion_target(&chars, pos, motion, count, last_find, false) {
+                set_cursor(state, target);
+                clamp_cursor(state);
             }
         }
-        'b' => {
-            if let Some(op) = current_operator {
-                let range = get_motion_range(&state, VimMotion::WordBackward);
-                execute_operator(&mut state, op, range);
-                if let Some(vim_state) = &mut state.vim_state {
-                    vim_state.operator = None;
+        Kind::Operate(operator, target) => {
+            let chars: Vec<char> = state.input.chars().collect();
+            let pos = state.input[..state.cursor_position].chars().count();
+            let last_find = state.vim_state.as_ref().and_then(|v| v.last_find);
+
+            let range = match target {
+                Target::Lines => {
+                    let down = VimMotion::LineDown;
+                    let end = motion_target(&chars, pos, down, count - 1, None, true)
+                        .map_or(chars.len(), |(end, _)| end);
+                    Some(((pos, end), MotionKind::Linewise))
                 }
-            } else {
-                let (_, end) = get_motion_range(&state, VimMotion::WordBackward);
-                state.cursor_position = end;
+                Target::Object(object) => text_object_range(&chars, pos, object)
+                    .map(|range| (range, MotionKind::Exclusive)),
+                Target::Selection => {
+                    let selection = selected_range(state);
+                    exit_visual(state);
+                    selection
+                }
+                Target::Motion(VimMotion::WordForward { big })
+                    if operator == VimOperator::Change
+                        && chars.get(pos).is_some_and(|c| !c.is_whitespace()) =>
+                {
+                    let end = change_word_end(&chars, pos, count, big);
+                    Some(((pos, end), MotionKind::Inclusive))
+                }
+                Target::Motion(motion) => {
+                    remember_find(state, motion);
+                    motion_target(&chars, pos, motion, count, last_find, true)
+                        .map(|(target, kind)| ((pos, target), kind))
+                }
+            };
+
+            if let Some((range, kind)) = range {
+                apply_operator(state, operator, range, kind, command.register);
             }
         }
-        'd' => {
-            if let Some(VimOperator::Delete) = current_operator {
-                let current_pos = state.cursor_position;
-                let current_line_start = state.input[..current_pos]
-                    .rfind('\n')
-                    .map(|i| i + 1)
-                    .unwrap_or(0);
-
-                if let Some(newline_offset) = state.input[current_pos..].find('\n') {
-                    let next_newline_index = current_pos + newline_offset;
-                    state
-                        .input
-                        .drain(current_line_start..next_newline_index + 1);
-                    state.cursor_position = current_line_start;
-                } else if current_line_start > 0 {
-                    let len = state.input.len();
-                    state.input.drain(current_line_start - 1..len);
-                    let prev_line_start = state.input[..current_line_start - 1]
-                        .rfind('\n')
-                        .map(|i| i + 1)
-                        .unwrap_or(0);
-                    state.cursor_position = prev_line_start;
-                } else {
-                    state.input.clear();
-                    state.cursor_position = 0;
-                }
+        Kind::Act(action) => act(state, action, count, command.register),
+    }
+}
 
-                clamp_cursor(&mut state);
+/// Keeps `f`, `F`, `t` and `T` for `;` and `,`.
+fn remember_find(state: &mut MutexGuard<'_, App>, motion: VimMotion) {
+    if let (VimMotion::Find(kind, c), Some(vim_state)) = (motion, &mut state.vim_state) {
+        vim_state.last_find = Some((kind, c));
+    }
+}
 
-                if let Some(vim_state) = &mut state.vim_state {
-                    vim_state.operator = None;
-                }
-            } else if let Some(vim_state) = &mut state.vim_state {
-                vim_state.operator = Some(VimOperator::Delete);
-                vim_state.last_action_time = Instant::now();
+pub async fn handle_vim_keys(
+    mut state: MutexGuard<'_, App>,
+    c: char,
+    tx_action: Sender<AppAction>,
+) {
+    // Ensure vim_state exists (it should, but for safety)
+    let vim_state = state.vim_state.get_or_insert_with(VimState::default);
+
+    // A command left half typed for a second is dropped
+    if Instant::now()
+        .duration_since(vim_state.last_action_time)
+        .as_secs()
+        >= 1
+    {
+        vim_state.pending_keys.clear();
+    }
+    vim_state.pending_keys.push(c);
+    vim_state.last_action_time = Instant::now();
+
+    let keys: Vec<char> = vim_state.pending_keys.chars().collect();
+    let visual = vim_state.visual.is_some();
+    let parsed = if visual {
+        parse_visual(&keys)
+    } else {
+        parse(&keys)
+    };
+    match parsed {
+        Parsed::Incomplete => {}
+        Parsed::Invalid => vim_state.pending_keys.clear(),
+        Parsed::Done(command) => {
+            let keys = std::mem::take(&mut vim_state.pending_keys);
+            if command.kind == Kind::Act(Action::Repeat) {
+                repeat(&mut state, command.count, &tx_action).await;
+                return;
             }
-        }
-        'x' => {
-            let pos = state.cursor_position;
-            if pos < state.input.len()
-                && state.input.is_char_boundary(pos)
-                && let Some(ch) = state.input[pos..].chars().next()
-            {
-                let char_end = pos + ch.len_utf8();
-                state.input.drain(pos..char_end);
-                clamp_cursor(&mut state);
+            // Changes made in visual mode depend on the selection, `.` does not repeat them
+            if command.changes_text() && !visual {
+                vim_state.last_change = Some(LastChange {
+                    keys,
+                    inserted: None,
+                });
             }
+            run(&mut state, command, &tx_action).await;
         }
-        ':' => {
-            // In the future, this could enter command mode.
-            // For now, we do nothing to avoid conflict with standard Vim behavior.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{Mutex, mpsc};
+
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn object(kind: TextObjectKind, around: bool) -> TextObject {
+        TextObject { kind, around }
+    }
+
+    fn command(keys: &str) -> NormalCommand {
+        match parse(&chars(keys)) {
+            Parsed::Done(command) => command,
+            _ => panic!("{keys} is not a whole command"),
         }
-        _ => {
-            if let Some(vim_state) = &mut state.vim_state {
-                vim_state.operator = None;
-                vim_state.pending_keys.clear();
+    }
+
+    fn is_incomplete(keys: &str) -> bool {
+        matches!(parse(&chars(keys)), Parsed::Incomplete)
+    }
+
+    fn is_invalid(keys: &str) -> bool {
+        matches!(parse(&chars(keys)), Parsed::Invalid)
+    }
+
+    /// Where the cursor lands after the motion in normal mode.
+    fn land(text: &str, pos: usize, motion: VimMotion, count: usize) -> Option<usize> {
+        motion_target(&chars(text), pos, motion, count, None, false).map(|(target, _)| target)
+    }
+
+    fn range(text: &str, pos: usize, object: TextObject) -> Option<(usize, usize)> {
+        text_object_range(&chars(text), pos, object)
+    }
+
+    /// A chat with `text` in the input and the cursor at byte `cursor`, in normal mode.
+    fn chat(text: &str, cursor: usize) -> Mutex<App> {
+        let mut app = App::for_tests();
+        app.state = AppState::Chatting("1".to_string());
+        app.input = text.to_string();
+        app.cursor_position = cursor;
+        Mutex::new(app)
+    }
+
+    async fn type_keys(app: &Mutex<App>, keys: &str) {
+        let (tx, _rx) = mpsc::channel(32);
+        for c in keys.chars() {
+            handle_vim_keys(app.lock().await, c, tx.clone()).await;
+        }
+    }
+
+    async fn input(app: &Mutex<App>) -> (String, usize) {
+        let state = app.lock().await;
+        (state.input.clone(), state.cursor_position)
+    }
+
+    #[test]
+    fn parses_registers_counts_operators_and_targets() {
+        let delete_words = Kind::Operate(
+            VimOperator::Delete,
+            Target::Motion(VimMotion::WordForward { big: false }),
+        );
+        assert_eq!(
+            command("3dw"),
+            NormalCommand {
+                register: None,
+                count: Some(3),
+                kind: delete_words,
             }
+        );
+        // Both counts multiply
+        assert_eq!(command("2d3w").count, Some(6));
+
+        let yank_lines = command("\"ayy");
+        assert_eq!(yank_lines.register, Some('a'));
+        assert_eq!(
+            yank_lines.kind,
+            Kind::Operate(VimOperator::Yank, Target::Lines)
+        );
+
+        assert_eq!(
+            command("ci(").kind,
+            Kind::Operate(
+                VimOperator::Change,
+                Target::Object(object(TextObjectKind::Bracket('(', ')'), false))
+            )
+        );
+        assert_eq!(
+            command("dt,").kind,
+            Kind::Operate(
+                VimOperator::Delete,
+                Target::Motion(VimMotion::Find(FindKind::TillForward, ','))
+            )
+        );
+    }
+
+    #[test]
+    fn parses_motions_and_actions() {
+        assert_eq!(command("0").kind, Kind::Move(VimMotion::StartOfLine));
+        assert_eq!(command("10j").count, Some(10));
+        assert_eq!(command("gg").kind, Kind::Move(VimMotion::FirstLine));
+        assert_eq!(
+            command("Fx").kind,
+            Kind::Move(VimMotion::Find(FindKind::Backward, 'x'))
+        );
+        assert_eq!(command("ra").kind, Kind::Act(Action::Replace('a')));
+        assert_eq!(
+            command("D").kind,
+            Kind::Operate(VimOperator::Delete, Target::Motion(VimMotion::EndOfLine))
+        );
+        assert!(command("x").changes_text());
+        assert!(!command("yw").changes_text());
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_command() {
+        for keys in ["\"", "\"a", "3", "d", "d3", "di", "f", "g", "r"] {
+            assert!(is_incomplete(keys), "{keys}");
+        }
+        for keys in ["Q", "dz", "diz", "gz"] {
+            assert!(is_invalid(keys), "{keys}");
         }
     }
+
+    #[test]
+    fn parses_visual_commands() {
+        let Parsed::Done(command) = parse_visual(&chars("iw")) else {
+            panic!("a whole command");
+        };
+        assert_eq!(
+            command.kind,
+            Kind::Act(Action::SelectObject(object(TextObjectKind::Word, false)))
+        );
+        let Parsed::Done(command) = parse_visual(&chars("\"+y")) else {
+            panic!("a whole command");
+        };
+        assert_eq!(command.register, Some('+'));
+        assert_eq!(
+            command.kind,
+            Kind::Operate(VimOperator::Yank, Target::Selection)
+        );
+    }
+
+    #[test]
+    fn word_motions() {
+        let text = "foo.bar baz";
+        assert_eq!(
+            land(text, 0, VimMotion::WordForward { big: false }, 1),
+            Some(3)
+        );
+        assert_eq!(
+            land(text, 0, VimMotion::WordForward { big: false }, 3),
+            Some(8)
+        );
+        assert_eq!(
+            land(text, 0, VimMotion::WordForward { big: true }, 1),
+            Some(8)
+        );
+        assert_eq!(
+            land(text, 8, VimMotion::WordBackward { big: false }, 1),
+            Some(4)
+        );
+        assert_eq!(
+            land(text, 8, VimMotion::WordBackward { big: true }, 1),
+            Some(0)
+        );
+        assert_eq!(land(text, 0, VimMotion::WordEnd { big: false }, 1), Some(2));
+        assert_eq!(land(text, 0, VimMotion::WordEnd { big: true }, 1), Some(6));
+    }
+
+    #[test]
+    fn operators_stop_words_at_the_end_of_the_line() {
+        let text = chars("foo bar\nbaz");
+        let motion = VimMotion::WordForward { big: false };
+        assert_eq!(
+            motion_target(&text, 4, motion, 1, None, true),
+            Some((7, MotionKind::Exclusive))
+        );
+        assert_eq!(land("foo bar\nbaz", 4, motion, 1), Some(8));
+    }
+
+    #[test]
+    fn line_motions() {
+        let text = "ab\n  cd";
+        assert_eq!(land(text, 7, VimMotion::StartOfLine, 1), Some(3));
+        assert_eq!(land(text, 3, VimMotion::FirstNonBlank, 1), Some(5));
+        assert_eq!(land(text, 3, VimMotion::EndOfLine, 1), Some(6));
+        assert_eq!(land(text, 0, VimMotion::EndOfLine, 2), Some(6));
+        assert_eq!(land(text, 1, VimMotion::LineDown, 1), Some(3));
+        assert_eq!(land(text, 4, VimMotion::LineDown, 1), None);
+        assert_eq!(land(text, 4, VimMotion::LineUp, 1), Some(0));
+    }
+
+    #[test]
+    fn find_motions_and_repeats() {
+        let text = chars("a,b,c");
+        let find = |kind, count, repeat| find_char(&text, 0, kind, ',', count, repeat);
+        assert_eq!(find(FindKind::Forward, 1, false), Some(1));
+        assert_eq!(find(FindKind::Forward, 2, false), Some(3));
+        assert_eq!(find(FindKind::Forward, 3, false), None);
+        assert_eq!(find(FindKind::TillForward, 1, false), Some(0));
+        // Repeating a till skips the match it stands before
+        assert_eq!(find(FindKind::TillForward, 1, true), Some(2));
+
+        assert_eq!(
+            find_char(&text, 4, FindKind::Backward, ',', 1, false),
+            Some(3)
+        );
+        assert_eq!(
+            find_char(&text, 4, FindKind::TillBackward, ',', 1, false),
+            Some(4)
+        );
+        // Finds stay on the line
+        assert_eq!(
+            find_char(&chars("a\n,"), 0, FindKind::Forward, ',', 1, false),
+            None
+        );
+    }
+
+    #[test]
+    fn word_objects() {
+        let inner = object(TextObjectKind::Word, false);
+        let around = object(TextObjectKind::Word, true);
+        assert_eq!(range("say hi now", 5, inner), Some((4, 6)));
+        assert_eq!(range("say hi now", 5, around), Some((4, 7)));
+        // The last word takes the blanks before it
+        assert_eq!(range("say hi", 5, around), Some((3, 6)));
+        // Around blanks takes the word after them
+        assert_eq!(range("say hi", 3, around), Some((3, 6)));
+        assert_eq!(range("say\n", 3, inner), None);
+    }
+
+    #[test]
+    fn quote_objects() {
+        let inner = object(TextObjectKind::Quote('"'), false);
+        let around = object(TextObjectKind::Quote('"'), true);
+        let text = "x \"hi\" y";
+        assert_eq!(range(text, 3, inner), Some((3, 5)));
+        assert_eq!(range(text, 3, around), Some((2, 7)));
+        // Before the quotes, the next quoted text is taken
+        assert_eq!(range(text, 0, inner), Some((3, 5)));
+        assert_eq!(range(text, 7, inner), None);
+    }
+
+    #[test]
+    fn bracket_objects_nest() {
+        let inner = object(TextObjectKind::Bracket('(', ')'), false);
+        let around = object(TextObjectKind::Bracket('(', ')'), true);
+        let text = "say (a (b) c) now";
+        assert_eq!(range(text, 8, inner), Some((8, 9)));
+        assert_eq!(range(text, 8, around), Some((7, 10)));
+        assert_eq!(range(text, 5, inner), Some((5, 12)));
+        assert_eq!(range(text, 12, inner), Some((5, 12)));
+        assert_eq!(range(text, 0, inner), None);
+    }
+
+    #[tokio::test]
+    async fn operators_edit_the_input() {
+        let app = chat("foo bar baz", 0);
+        type_keys(&app, "dw").await;
+        assert_eq!(input(&app).await, ("bar baz".to_string(), 0));
+        type_keys(&app, "u").await;
+        assert_eq!(input(&app).await.0, "foo bar baz");
+
+        let app = chat("foo bar baz", 0);
+        type_keys(&app, "d2w").await;
+        assert_eq!(input(&app).await.0, "baz");
+
+        let app = chat("say (a, b) now", 6);
+        type_keys(&app, "ci(").await;
+        assert_eq!(input(&app).await, ("say () now".to_string(), 5));
+        assert_eq!(app.lock().await.mode, InputMode::Insert);
+    }
+
+    #[tokio::test]
+    async fn yanks_and_puts_through_registers() {
+        let app = chat("foo bar", 0);
+        type_keys(&app, "\"ayw$\"ap").await;
+        assert_eq!(input(&app).await.0, "foo barfoo ");
+
+        let app = chat("foo", 0);
+        type_keys(&app, "yyp").await;
+        assert_eq!(input(&app).await.0, "foo\nfoo");
+    }
+
+    #[tokio::test]
+    async fn dot_repeats_the_last_change() {
+        let app = chat("abcdef", 0);
+        type_keys(&app, "x.").await;
+        assert_eq!(input(&app).await.0, "cdef");
+        type_keys(&app, "2.").await;
+        assert_eq!(input(&app).await.0, "ef");
+    }
+
+    #[tokio::test]
+    async fn visual_mode_operates_on_the_selection() {
+        let app = chat("foo bar", 0);
+        type_keys(&app, "ved").await;
+        assert_eq!(input(&app).await.0, " bar");
+        assert!(
+            app.lock()
+                .await
+                .vim_state
+                .as_ref()
+                .unwrap()
+                .visual
+                .is_none()
+        );
+
+        let app = chat("one\ntwo\nthree", 0);
+        type_keys(&app, "Vjd").await;
+        assert_eq!(input(&app).await.0, "three");
+
+        let app = chat("say (a, b) now", 5);
+        type_keys(&app, "vi(\"ay").await;
+        let state = app.lock().await;
+        let register = state.vim_state.as_ref().unwrap().registers.get(Some('a'));
+        assert_eq!(register.unwrap().text, "a, b");
+    }
+
+    #[tokio::test]
+    async fn find_repeats_move_the_cursor() {
+        let app = chat("a,b,c,d", 0);
+        type_keys(&app, "f,;").await;
+        assert_eq!(input(&app).await.1, 3);
+        type_keys(&app, ",").await;
+        assert_eq!(input(&app).await.1, 1);
+    }
+
+    #[test]
+    fn counts_are_capped() {
+        let Parsed::Done(command) = parse(&chars("99999999999d9999999w")) else {
+            panic!("a whole command");
+        };
+        assert_eq!(command.count, Some(MAX_COUNT));
+
+        let keys = chars("999999999999999999999999p");
+        let (count, rest) = parse_count(&keys);
+        assert_eq!(count, Some(MAX_COUNT));
+        assert_eq!(rest, ['p']);
+    }
+
+    #[test]
+    fn bracket_object_on_empty_input() {
+        let inner = object(TextObjectKind::Bracket('(', ')'), false);
+        assert_eq!(text_object_range(&[], 0, inner), None);
+    }
+
+    #[test]
+    fn bracket_object_with_cursor_past_trailing_newline() {
+        let text = chars("f(a, b)\n");
+        let inner = object(TextObjectKind::Bracket('(', ')'), false);
+        assert_eq!(text_object_range(&text, text.len(), inner), None);
+
+        let text = chars("(a\n");
+        assert_eq!(text_object_range(&text, text.len(), inner), None);
+
+        let text = chars("(a\nb)");
+        assert_eq!(text_object_range(&text, text.len(), inner), Some((1, 4)));
+    }
 }